@@ -0,0 +1,545 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::fs::File as FsFile;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use url::Url;
+
+use crate::vault::Vault;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub title: String,
+    pub url: Url,
+    pub tags: Vec<String>,
+    /// Alternate URLs tried in order when the server can not be reached
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<Url>,
+    /// Quick access slot 1 to 9, opened by typing its digit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slot: Option<u8>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Bookmarks {
+    /// All bookmarks
+    pub entries: Vec<Bookmark>,
+    /// Deleted bookmarks that can be restored, most recently deleted last
+    pub trash: Vec<Bookmark>,
+    /// Encrypts the bookmarks file if set
+    #[serde(skip)]
+    vault: Option<Arc<Vault>>,
+}
+
+impl Bookmarks {
+    /// Reads the bookmarks. With a vault, the bookmarks are read from the
+    /// encrypted file, or from an unencrypted file which is replaced by an
+    /// encrypted one. Fails if the bookmarks can not be read, so that
+    /// they are not overwritten.
+    pub fn new(vault: Option<Arc<Vault>>) -> std::io::Result<Bookmarks> {
+        let confdir = Bookmarks::get_bookmark_path();
+        let encrypted = Bookmarks::encrypted_path();
+        info!("Looking for bookmarks file {:?}", confdir);
+        let mut bookmarks_string = String::new();
+        let mut migrate = false;
+        match vault.as_ref() {
+            Some(vault) if encrypted.exists() => {
+                let data = vault.read(&encrypted)?;
+                bookmarks_string = String::from_utf8_lossy(&data).to_string();
+            }
+            _ if confdir.as_path().exists() => {
+                bookmarks_string = read_to_string(&confdir)?;
+                migrate = vault.is_some();
+            }
+            _ => (),
+        }
+        info!("Reading bookmarks...");
+        let (entries, trash) = Bookmarks::parse(&bookmarks_string);
+
+        let bookmarks = Bookmarks {
+            entries,
+            trash,
+            vault,
+        };
+        if let (true, Some(vault)) = (migrate, bookmarks.vault.as_ref()) {
+            // written right away, as the unencrypted file is removed
+            match vault.write(&encrypted, &bookmarks.to_toml()?) {
+                Ok(()) => std::fs::remove_file(&confdir)
+                    .unwrap_or_else(|err| warn!("Could not remove {:?}: {}", confdir, err)),
+                Err(err) => warn!("Could not encrypt bookmarks: {}", err),
+            }
+        }
+        Ok(bookmarks)
+    }
+
+    /// Returns the bookmarks and the trash of a bookmarks file
+    fn parse(bookmarks_string: &str) -> (Vec<Bookmark>, Vec<Bookmark>) {
+        let mut bookmarks_table: HashMap<String, Vec<Bookmark>> =
+            toml::from_str(bookmarks_string).unwrap_or_default();
+        let entries = bookmarks_table.remove("bookmark").unwrap_or_default();
+        let trash = bookmarks_table.remove("trash").unwrap_or_default();
+        (entries, trash)
+    }
+
+    /// Returns the path of the encrypted bookmarks file
+    pub fn encrypted_path() -> PathBuf {
+        Bookmarks::get_bookmark_path().with_file_name("bookmarks.age")
+    }
+
+    fn get_bookmark_path() -> PathBuf {
+        let mut dir = dirs::config_dir().expect("no configuration directory");
+        dir.push(crate::CONFIG_DIR_NAME);
+        dir.push("bookmarks");
+        info!("Looking for bookmark file {:?}", dir);
+        dir
+    }
+
+    /// Replace an existting bookmark or add a new bookmark.
+    /// If an entry is replaced, it will remain at the same position
+    /// Returns the index of the existing entry or None.
+    pub fn insert(&mut self, mut entry: Bookmark) -> Option<usize> {
+        info!("Adding entry to bookmark: {:?}", entry);
+        let index = self.entries.iter().position(|e| e.url == entry.url);
+        if let Some(i) = index {
+            // replace item
+            entry.slot = entry.slot.or(self.entries[i].slot);
+            self.entries.remove(i);
+            self.entries.insert(i, entry);
+        } else {
+            // insert new item at end
+            self.entries.push(entry);
+        };
+        self.write_bookmarks_to_file()
+            .unwrap_or_else(|err| warn!("Could not write bookmarks file: {}", err));
+        index
+    }
+
+    /// Replaces the bookmark with a URL by an edited one, which may have
+    /// another URL, keeping its position
+    pub fn replace(&mut self, url: &Url, mut entry: Bookmark) {
+        info!("Replacing bookmark {}: {:?}", url, entry);
+        // an existing bookmark with the new URL is merged into this one
+        if &entry.url != url {
+            self.entries.retain(|e| e.url != entry.url);
+        }
+        match self.entries.iter().position(|e| &e.url == url) {
+            Some(i) => {
+                entry.slot = entry.slot.or(self.entries[i].slot);
+                self.entries[i] = entry;
+            }
+            None => self.entries.push(entry),
+        }
+        self.write_bookmarks_to_file()
+            .unwrap_or_else(|err| warn!("Could not write bookmarks file: {}", err));
+    }
+
+    /// Assigns a bookmark to a quick access slot, taking the slot from
+    /// the bookmark it was assigned to before, or clears its slot
+    pub fn set_slot(&mut self, url: &Url, slot: Option<u8>) {
+        for entry in self.entries.iter_mut() {
+            if &entry.url == url {
+                entry.slot = slot;
+            } else if slot.is_some() && entry.slot == slot {
+                entry.slot = None;
+            }
+        }
+        self.write_bookmarks_to_file()
+            .unwrap_or_else(|err| warn!("Could not write bookmarks file: {}", err));
+    }
+
+    /// Returns the bookmark in a quick access slot
+    pub fn in_slot(&self, slot: u8) -> Option<&Bookmark> {
+        self.entries.iter().find(|e| e.slot == Some(slot))
+    }
+
+    /// Moves a bookmark up (negative offset) or down in the order of the
+    /// bookmarks. Returns its new position.
+    pub fn move_by(&mut self, url: &Url, offset: isize) -> Option<usize> {
+        let i = self.entries.iter().position(|e| &e.url == url)?;
+        let j = (i as isize + offset).clamp(0, self.entries.len() as isize - 1) as usize;
+        if i != j {
+            let entry = self.entries.remove(i);
+            self.entries.insert(j, entry);
+            self.write_bookmarks_to_file()
+                .unwrap_or_else(|err| warn!("Could not write bookmarks file: {}", err));
+        }
+        Some(j)
+    }
+
+    /// Moves a bookmark to the trash
+    pub fn remove(&mut self, url: &Url) {
+        info!("Removing entry to bookmark: {:?}", url);
+        if let Some(i) = self.entries.iter().position(|e| &e.url == url) {
+            let entry = self.entries.remove(i);
+            self.trash.retain(|e| &e.url != url);
+            self.trash.push(entry);
+        }
+        if let Err(why) = self.write_bookmarks_to_file() {
+            warn!("Could not write bookmarks file: {}", why)
+        }
+    }
+
+    /// Moves a bookmark from the trash back to the bookmarks. Returns
+    /// the restored bookmark.
+    pub fn restore(&mut self, url: &Url) -> Option<Bookmark> {
+        info!("Restoring bookmark from trash: {:?}", url);
+        let i = self.trash.iter().position(|e| &e.url == url)?;
+        let entry = self.trash.remove(i);
+        self.insert(entry.clone());
+        Some(entry)
+    }
+
+    /// Permanently deletes all bookmarks in the trash
+    pub fn empty_trash(&mut self) {
+        info!("Emptying bookmark trash");
+        self.trash.clear();
+        if let Err(why) = self.write_bookmarks_to_file() {
+            warn!("Could not write bookmarks file: {}", why)
+        }
+    }
+
+    pub fn get_bookmarks(&self) -> Vec<Bookmark> {
+        self.entries.clone()
+    }
+
+    /// Returns the URL to try after `url` could not be reached: the next
+    /// mirror of the bookmark that has `url` as its URL or as a mirror.
+    pub fn next_mirror(&self, url: &Url) -> Option<Url> {
+        self.entries.iter().find_map(|b| {
+            if &b.url == url {
+                b.mirrors.first().cloned()
+            } else {
+                let i = b.mirrors.iter().position(|m| m == url)?;
+                b.mirrors.get(i + 1).cloned()
+            }
+        })
+    }
+
+    /// Returns the bookmarks whose title, URL or tags contain the
+    /// characters of `query` in order, like fzf, the best matches first
+    pub fn fuzzy_find(&self, query: &str) -> Vec<&Bookmark> {
+        let mut matches = self
+            .entries
+            .iter()
+            .filter_map(|b| {
+                let text = format!("{} {} {}", b.title, b.tags.join(" "), b.url);
+                Some((fuzzy_score(query, &text)?, b))
+            })
+            .collect::<Vec<_>>();
+        // stable, so that equal matches keep the order of the bookmarks
+        matches.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        matches.into_iter().map(|(_, b)| b).collect()
+    }
+
+    /// Returns the bookmark that lists `url` as one of its mirrors
+    pub fn mirror_of(&self, url: &Url) -> Option<&Bookmark> {
+        self.entries.iter().find(|b| b.mirrors.contains(url))
+    }
+
+    /// Groups the bookmarks by tag, untagged bookmarks first. Bookmarks
+    /// with several tags are listed under each of their tags. If a tag is
+    /// given, only the bookmarks with this tag are returned.
+    fn grouped(&self, tag: Option<&str>) -> Vec<(Option<String>, Vec<&Bookmark>)> {
+        let mut groups = Vec::new();
+        if tag.is_none() {
+            let untagged = self
+                .entries
+                .iter()
+                .filter(|b| b.tags.is_empty())
+                .collect::<Vec<_>>();
+            if !untagged.is_empty() {
+                groups.push((None, untagged));
+            }
+        }
+        let mut tags = self
+            .entries
+            .iter()
+            .flat_map(|b| b.tags.iter())
+            .filter(|t| tag.is_none_or(|tag| *t == tag))
+            .collect::<Vec<_>>();
+        tags.sort();
+        tags.dedup();
+        for tag in tags {
+            let entries = self.entries.iter().filter(|b| b.tags.contains(tag));
+            groups.push((Some(tag.clone()), entries.collect()));
+        }
+        groups
+    }
+
+    /// Returns all tags in alphabetical order
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags = self
+            .entries
+            .iter()
+            .flat_map(|b| b.tags.iter().cloned())
+            .collect::<Vec<_>>();
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+
+    /// Renders the bookmarks as a gophermap grouped by tag, optionally
+    /// only those with the given tag.
+    pub fn to_gophermap(&self, tag: Option<&str>) -> String {
+        let mut gophermap = String::new();
+        gophermap.push_str("iBookmarks\n");
+        for (tag, entries) in self.grouped(tag) {
+            gophermap.push_str("i\n");
+            if let Some(tag) = tag {
+                gophermap.push_str(&format!("i[{}]\n", tag));
+            }
+            for b in entries {
+                gophermap.push_str(&Bookmarks::gophermap_line(b));
+            }
+        }
+        gophermap
+    }
+
+    /// Renders the bookmarks as a gemtext links page with the tags as
+    /// headings, optionally only those with the given tag.
+    pub fn to_gemtext(&self, tag: Option<&str>) -> String {
+        let mut gemtext = String::from("# Bookmarks\n");
+        for (tag, entries) in self.grouped(tag) {
+            gemtext.push('\n');
+            if let Some(tag) = tag {
+                gemtext.push_str(&format!("## {}\n\n", tag));
+            }
+            for b in entries {
+                if b.title.is_empty() {
+                    gemtext.push_str(&format!("=> {}\n", b.url));
+                } else {
+                    gemtext.push_str(&format!("=> {} {}\n", b.url, b.title));
+                }
+            }
+        }
+        gemtext
+    }
+
+    /// Renders the bookmarks in the Netscape bookmark file format, which
+    /// web browsers import, with a folder for each tag, optionally only
+    /// those with the given tag.
+    pub fn to_html(&self, tag: Option<&str>) -> String {
+        let mut html = String::from(
+            "<!DOCTYPE NETSCAPE-Bookmark-file-1>\n\
+             <META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">\n\
+             <TITLE>Bookmarks</TITLE>\n\
+             <H1>Bookmarks</H1>\n\
+             <DL><p>\n",
+        );
+        for (tag, entries) in self.grouped(tag) {
+            let indent = match &tag {
+                Some(tag) => {
+                    html.push_str(&format!("    <DT><H3>{}</H3>\n    <DL><p>\n", escape_html(tag)));
+                    "        "
+                }
+                None => "    ",
+            };
+            for b in entries {
+                let title = if b.title.is_empty() {
+                    b.url.as_str()
+                } else {
+                    b.title.as_str()
+                };
+                html.push_str(&format!(
+                    "{}<DT><A HREF=\"{}\" TAGS=\"{}\">{}</A>\n",
+                    indent,
+                    escape_html(b.url.as_str()),
+                    escape_html(&b.tags.join(",")),
+                    escape_html(title)
+                ));
+            }
+            if tag.is_some() {
+                html.push_str("    </DL><p>\n");
+            }
+        }
+        html.push_str("</DL><p>\n");
+        html
+    }
+
+    /// Returns a gophermap line for a bookmark. Bookmarks that do not
+    /// point to gopher use the common `URL:` selector convention.
+    fn gophermap_line(b: &Bookmark) -> String {
+        let title = if b.title.is_empty() {
+            b.url.to_string()
+        } else {
+            b.title.replace('\t', " ")
+        };
+        if b.url.scheme() == "gopher" {
+            let path = b.url.path();
+            let (item_type, selector) = if path.chars().count() > 2 {
+                let mut chars = path.chars();
+                chars.next(); // leading slash
+                let item_type = chars.next().unwrap();
+                (item_type, chars.as_str())
+            } else {
+                ('1', "")
+            };
+            format!(
+                "{}{}\t{}\t{}\t{}\n",
+                item_type,
+                title,
+                selector,
+                b.url.host_str().unwrap_or_default(),
+                b.url.port().unwrap_or(70)
+            )
+        } else {
+            format!("h{}\tURL:{}\tlocalhost\t70\n", title, b.url)
+        }
+    }
+
+    /// Returns the bookmarks file, which is not encrypted yet
+    fn to_toml(&self) -> std::io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        data.write_all(b"# Automatically generated by ncgopher.\n")?;
+        for b in self.entries.iter() {
+            data.write_all(b"\n[[bookmark]]\n")?;
+            let item = toml::to_string(b).unwrap();
+            data.write_all(item.as_bytes())?;
+        }
+        for b in self.trash.iter() {
+            data.write_all(b"\n[[trash]]\n")?;
+            let item = toml::to_string(b).unwrap();
+            data.write_all(item.as_bytes())?;
+        }
+        Ok(data)
+    }
+
+    /// Returns the path of the bookmarks as they were when they were
+    /// last synced, to tell which side changed a bookmark
+    fn sync_base_path() -> PathBuf {
+        Bookmarks::get_bookmark_path().with_file_name("bookmarks.synced")
+    }
+
+    /// Returns whether the bookmarks have been synced before
+    pub fn has_sync_base(&self) -> bool {
+        Bookmarks::sync_base_path().exists()
+    }
+
+    /// Reads the bookmarks of a bookmarks file, encrypted if there is a
+    /// vault
+    fn read_entries(&self, path: &Path) -> std::io::Result<Vec<Bookmark>> {
+        let data = match self.vault.as_ref() {
+            Some(vault) => vault.read(path)?,
+            None => std::fs::read(path)?,
+        };
+        Ok(Bookmarks::parse(&String::from_utf8_lossy(&data)).0)
+    }
+
+    /// Merges the bookmarks of a copy from another computer, if there is
+    /// one. Bookmarks added, changed or deleted on only one side since
+    /// the last sync are taken from that side. If both changed a
+    /// bookmark, the local one is kept. Returns the number of bookmarks
+    /// that changed here.
+    pub fn merge_sync_file(&mut self, path: Option<&Path>) -> std::io::Result<usize> {
+        let remote = match path {
+            Some(path) => self.read_entries(path)?,
+            None => Vec::new(),
+        };
+        let base = match self.has_sync_base() {
+            true => self.read_entries(&Bookmarks::sync_base_path())?,
+            false => Vec::new(),
+        };
+        let find = |list: &[Bookmark], url: &Url| list.iter().position(|b| &b.url == url);
+        let mut merged = Vec::new();
+        for local in self.entries.iter() {
+            let in_base = find(&base, &local.url).map(|i| &base[i]);
+            let in_remote = find(&remote, &local.url).map(|i| &remote[i]);
+            match (in_base, in_remote) {
+                // unchanged here, so changes there win
+                (Some(b), Some(r)) if b == local => merged.push(r.clone()),
+                // deleted there and unchanged here
+                (Some(b), None) if b == local => (),
+                _ => merged.push(local.clone()),
+            }
+        }
+        for r in remote.iter() {
+            if find(&self.entries, &r.url).is_some() {
+                continue;
+            }
+            match find(&base, &r.url) {
+                // deleted here and unchanged there
+                Some(i) if base[i] == *r => (),
+                _ => merged.push(r.clone()),
+            }
+        }
+        let changes = merged
+            .iter()
+            .filter(|b| !self.entries.contains(b))
+            .count()
+            + self
+                .entries
+                .iter()
+                .filter(|b| !merged.iter().any(|m| m.url == b.url))
+                .count();
+        if changes > 0 {
+            self.entries = merged;
+            self.write_bookmarks_to_file()?;
+        }
+        Ok(changes)
+    }
+
+    /// Returns the bookmarks file to upload for syncing, encrypted like
+    /// the local one
+    pub fn sync_data(&self) -> std::io::Result<Vec<u8>> {
+        let data = self.to_toml()?;
+        match self.vault.as_ref() {
+            Some(vault) => vault.encrypt(&data),
+            None => Ok(data),
+        }
+    }
+
+    /// Remembers the uploaded bookmarks as the state of the last sync
+    pub fn save_sync_base(&self, data: &[u8]) -> std::io::Result<()> {
+        std::fs::write(Bookmarks::sync_base_path(), data)
+    }
+
+    pub fn write_bookmarks_to_file(&mut self) -> std::io::Result<()> {
+        let data = self.to_toml()?;
+
+        if let Some(vault) = self.vault.as_ref() {
+            let path = Bookmarks::encrypted_path();
+            info!("Saving bookmarks to encrypted file: {:?}", path);
+            vault.write_in_background(path, data);
+            return Ok(());
+        }
+
+        let path = Bookmarks::get_bookmark_path();
+        info!("Saving bookmarks to file: {:?}", path);
+
+        let mut file = FsFile::create(path)?;
+        file.write_all(&data)
+    }
+}
+
+/// Escapes text for HTML elements and quoted attributes
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Scores how well a text matches a query whose characters it contains
+/// in order, ignoring case. Consecutive characters and characters at
+/// the start of words score higher. Returns `None` if it does not match.
+fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for c in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = position + text[position..].iter().position(|t| *t == c)?;
+        score += match previous {
+            Some(previous) if previous + 1 == found => 8,
+            _ if found == 0 || !text[found - 1].is_alphanumeric() => 6,
+            // gaps between matched characters cost a little
+            Some(previous) => 1 - (found - previous).min(5) as i64,
+            None => 1,
+        };
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
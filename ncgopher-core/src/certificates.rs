@@ -31,7 +31,7 @@ impl Certificates {
     fn get_known_hosts_filename() -> String {
         let confdir: String = match dirs::config_dir() {
             Some(mut dir) => {
-                dir.push(env!("CARGO_PKG_NAME"));
+                dir.push(crate::CONFIG_DIR_NAME);
                 dir.push("known_hosts");
                 dir.into_os_string().into_string().unwrap()
             }
@@ -70,7 +70,7 @@ impl Certificates {
         // Create a path to the desired file
         let path = Path::new(&filename);
 
-        let mut file = std::fs::File::create(&path)?;
+        let mut file = std::fs::File::create(path)?;
 
         file.write_all(b"# Automatically generated by ncgopher.\n")?;
         file.write_all(
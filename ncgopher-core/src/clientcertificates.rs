@@ -48,7 +48,7 @@ impl ClientCertificates {
     fn get_client_certificates_filename() -> String {
         let confdir: String = match dirs::config_dir() {
             Some(mut dir) => {
-                dir.push(env!("CARGO_PKG_NAME"));
+                dir.push(crate::CONFIG_DIR_NAME);
                 dir.push("client_certificates");
                 dir.into_os_string().into_string().unwrap()
             }
@@ -88,7 +88,7 @@ impl ClientCertificates {
     }
 
     pub fn get_client_certificate_fingerprint(&mut self, url: &Url) -> Option<String> {
-        if let Some(fingerprint) = self.urls.get(&url.as_str().to_string()) {
+        if let Some(fingerprint) = self.urls.get(url.as_str()) {
             if self.certificates.contains_key(fingerprint) {
                 return Some(fingerprint.to_string());
             }
@@ -157,7 +157,7 @@ impl ClientCertificates {
         // Create a path to the desired file
         let path = Path::new(&filename);
 
-        let mut file = std::fs::File::create(&path)?;
+        let mut file = std::fs::File::create(path)?;
 
         file.write_all(b"# Automatically generated by ncgopher.\n")?;
         file.write_all(
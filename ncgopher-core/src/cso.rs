@@ -0,0 +1,134 @@
+use std::io::{self, Read};
+use url::Url;
+use urlencoding::decode;
+
+use crate::transport::Transport;
+
+/// A field of an entry in the response of a CSO phonebook server
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    /// Number of the matching entry, starting with 1
+    pub entry: u32,
+    pub name: String,
+    pub value: String,
+}
+
+/// Returns the query of a CSO URL, i.e. the search terms after the
+/// tab of the selector, as in gopher://host:105/2%09name=smith
+pub fn query(url: &Url) -> Option<String> {
+    let path = decode(url.path()).ok()?;
+    let (_, query) = path.split_once('\t')?;
+    Some(query.to_string())
+}
+
+/// Parses the response of a ph query. Lines look like
+/// `-200:1:     email: smith@example.org`, where a leading minus marks
+/// a continued response. Error codes (4xx and 5xx) are returned as error,
+/// except for 501, which means that nothing matched the query.
+pub fn parse(response: &str) -> Result<Vec<Field>, String> {
+    let mut fields: Vec<Field> = vec![];
+    for line in response.lines() {
+        let mut parts = line.splitn(3, ':');
+        let code = parts.next().unwrap_or_default();
+        let code: i32 = match code.trim().parse() {
+            Ok(code) => code,
+            Err(_) => continue,
+        };
+        let rest: Vec<&str> = parts.collect();
+        match code.abs() {
+            501 => return Ok(Vec::new()),
+            400..=599 => return Err(rest.join(":").trim().to_string()),
+            200 if code < 0 && rest.len() == 2 => {
+                let entry = rest[0].trim().parse().unwrap_or_default();
+                match rest[1].split_once(':') {
+                    Some((name, value)) => fields.push(Field {
+                        entry,
+                        name: name.trim().to_string(),
+                        value: value.trim().to_string(),
+                    }),
+                    // values spanning several lines repeat the field
+                    // with an empty name
+                    None => fields.push(Field {
+                        entry,
+                        name: String::new(),
+                        value: rest[1].trim().to_string(),
+                    }),
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(fields)
+}
+
+/// Formats the fields of a response as text with one block per entry
+pub fn format(fields: &[Field]) -> String {
+    let width = fields.iter().map(|f| f.name.len()).max().unwrap_or_default();
+    let mut text = String::new();
+    let mut entry = None;
+    for field in fields {
+        if entry.is_some() && entry != Some(field.entry) {
+            text.push('\n');
+        }
+        entry = Some(field.entry);
+        text.push_str(&format!("{:>width$}: {}\n", field.name, field.value, width = width));
+    }
+    if text.is_empty() {
+        text.push_str("No matches found.\n");
+    }
+    text
+}
+
+/// Sends a ph query to the server of a CSO URL and returns the response
+pub fn fetch(transport: &dyn Transport, url: &Url, query: &str) -> io::Result<Vec<u8>> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no host"))?;
+    let port = url.port().unwrap_or(105);
+    let request = format!("query {}\r\nquit\r\n", query.trim());
+    let mut buf = vec![];
+    transport
+        .request(host, port, false, request.as_bytes())?
+        .read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_entries() {
+        let response = "102:There were 2 matches to your request.\n\
+                        -200:1:     name: Smith, John\n\
+                        -200:1:    email: smith@example.org\n\
+                        -200:2:     name: Smith, Jane\n\
+                        200:Ok.\n";
+        let fields = parse(response).unwrap();
+        assert_eq!(fields.len(), 3);
+        assert_eq!(
+            fields[1],
+            Field {
+                entry: 1,
+                name: "email".to_string(),
+                value: "smith@example.org".to_string(),
+            }
+        );
+        assert_eq!(fields[2].entry, 2);
+    }
+
+    #[test]
+    fn no_matches() {
+        let fields = parse("501:No matches to your query.\n").unwrap();
+        assert!(fields.is_empty());
+        assert_eq!(format(&fields), "No matches found.\n");
+    }
+
+    #[test]
+    fn error_code() {
+        assert_eq!(
+            parse("598:query:Command not recognized.\n"),
+            Err("query:Command not recognized.".to_string())
+        );
+    }
+}
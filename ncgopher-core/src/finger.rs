@@ -0,0 +1,27 @@
+use std::io::{self, Read};
+use url::Url;
+
+use crate::transport::Transport;
+
+/// Returns the query of a finger URL, which is either the user part of
+/// the URL (finger://user@host) or the path (finger://host/user).
+pub fn query(url: &Url) -> String {
+    match url.username() {
+        "" => url.path().trim_matches('/').to_string(),
+        username => username.to_string(),
+    }
+}
+
+/// Fetches the response to a finger query (RFC 1288)
+pub fn fetch(transport: &dyn Transport, url: &Url) -> io::Result<Vec<u8>> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no host"))?;
+    let port = url.port().unwrap_or(79);
+    let request = format!("{}\r\n", query(url));
+    let mut buf = vec![];
+    transport
+        .request(host, port, false, request.as_bytes())?
+        .read_to_end(&mut buf)?;
+    Ok(buf)
+}
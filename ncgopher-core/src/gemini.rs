@@ -0,0 +1,87 @@
+use native_tls::Identity;
+use std::io::{self, Read};
+use url::Url;
+
+use crate::transport::Transport;
+// https://gemini.circumlunar.space/docs/spec-spec.txt
+
+/// Connects to the server of a gemini URL and sends the request. The
+/// certificate of the server is passed to `verify` before, see
+/// [`Transport::request_self_signed`]. The response, starting with the
+/// header line, can be read from the returned stream.
+pub fn connect(
+    transport: &dyn Transport,
+    url: &Url,
+    identity: Option<&Identity>,
+    verify: &mut dyn FnMut(Option<&[u8]>) -> bool,
+) -> io::Result<Box<dyn Read + Send>> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no host"))?;
+    let port = url.port().unwrap_or(1965);
+    let request = format!("{}\r\n", url);
+    transport.request_self_signed(host, port, identity, verify, request.as_bytes())
+}
+
+/// Collects the alt-text of all preformatted blocks in a document, in order.
+///
+/// The alt-text is the part of the opening toggle line after the three
+/// backticks, which the gemtext parser discards.
+pub fn preformatted_alt_texts(text: &str) -> Vec<String> {
+    let mut alt_texts = Vec::new();
+    let mut preformatted = false;
+    for line in text.lines() {
+        if let Some(alt) = line.strip_prefix("```") {
+            if !preformatted {
+                alt_texts.push(alt.trim().to_string());
+            }
+            preformatted = !preformatted;
+        }
+    }
+    alt_texts
+}
+
+/// Guesses whether a text document is gemtext: it needs both headings
+/// and link lines, which plain text hardly ever has.
+pub fn looks_like_gemtext(text: &str) -> bool {
+    let mut heading = false;
+    let mut link = false;
+    for line in text.lines() {
+        heading |= line.starts_with("# ") || line.starts_with("## ");
+        link |= line.starts_with("=> ");
+        if heading && link {
+            return true;
+        }
+    }
+    false
+}
+
+/// Returns the readable text of a gemtext document without markup.
+/// Headings, links and list items become paragraphs of their own.
+pub fn plain_text(text: &str) -> String {
+    let mut paragraphs: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for node in gemtext::parse(text) {
+        use gemtext::Node;
+        let block = match node {
+            Node::Text(text) | Node::Quote(text) => {
+                if text.trim().is_empty() {
+                    paragraphs.push(std::mem::take(&mut current));
+                } else {
+                    current.push_str(&text);
+                    current.push('\n');
+                }
+                continue;
+            }
+            Node::Link { to, name } => name.unwrap_or(to),
+            Node::Heading { body, .. } => body,
+            Node::ListItem(text) => text,
+            Node::Preformatted(_) => continue,
+        };
+        paragraphs.push(std::mem::take(&mut current));
+        paragraphs.push(block);
+    }
+    paragraphs.push(current);
+    paragraphs.retain(|p| !p.trim().is_empty());
+    paragraphs.join("\n\n")
+}
@@ -0,0 +1,425 @@
+use std::io::{self, Read};
+use url::Url;
+use urlencoding::decode_binary;
+
+use crate::transport::Transport;
+
+/// Returns the selector of a gopher URL, i.e. the path without the
+/// leading item type with %xx decoded to binary values. Fixes #78
+pub fn selector(url: &Url) -> String {
+    let binary = decode_binary(url.path().as_bytes());
+    let path = String::from_utf8_lossy(&binary);
+    // TODO: check x[0] == / and x[1] == itemtype
+    path.get(2..).unwrap_or_default().to_string()
+}
+
+/// Connects to the server of a gopher URL and sends the selector. The
+/// response can be read from the returned stream. Servers on non-standard
+/// ports are tried with TLS first.
+pub fn connect(transport: &dyn Transport, url: &Url) -> io::Result<Box<dyn Read + Send>> {
+    send(transport, url, &format!("{}\r\n", selector(url)))
+}
+
+/// Sends a request to the server of a gopher URL
+fn send(transport: &dyn Transport, url: &Url, request: &str) -> io::Result<Box<dyn Read + Send>> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no host"))?;
+    let port = url.port().unwrap_or(70);
+
+    if port != 70 {
+        match transport.request(host, port, true, request.as_bytes()) {
+            Ok(stream) => {
+                info!("Connected with TLS");
+                return Ok(stream);
+            }
+            Err(e) => info!("Could not open tls stream: {} to {}:{}", e, host, port),
+        }
+    }
+    transport.request(host, port, false, request.as_bytes())
+}
+
+/// Fetches the document at a gopher URL
+pub fn fetch(transport: &dyn Transport, url: &Url) -> io::Result<Vec<u8>> {
+    let mut buf = vec![];
+    connect(transport, url)?.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// A representation of a Gopher+ item as listed in its +VIEWS attribute
+#[derive(Clone, Debug, PartialEq)]
+pub struct View {
+    /// MIME type, e.g. text/plain
+    pub mime: String,
+    /// Language code, e.g. En_US
+    pub language: Option<String>,
+    /// Size as announced by the server, e.g. 12k
+    pub size: Option<String>,
+}
+
+impl View {
+    /// The view as used in Gopher+ requests
+    fn request(&self) -> String {
+        match &self.language {
+            Some(language) => format!("{} {}", self.mime, language),
+            None => self.mime.clone(),
+        }
+    }
+}
+
+/// Parses the +VIEWS block of Gopher+ attributes. Each view is on a line
+/// of its own, indented by a space: ` text/plain En_US: <10k>`
+pub fn parse_views(attributes: &str) -> Vec<View> {
+    attributes
+        .lines()
+        .skip_while(|line| !line.starts_with("+VIEWS"))
+        .skip(1)
+        .take_while(|line| line.starts_with(' '))
+        .filter_map(|line| {
+            let (view, size) = match line.split_once(':') {
+                Some((view, size)) => (view, Some(size)),
+                None => (line, None),
+            };
+            let mut parts = view.split_whitespace();
+            let mime = parts.next()?.to_string();
+            let language = parts.next().map(String::from);
+            let size = size
+                .map(|size| size.trim().trim_start_matches('<').trim_end_matches('>'))
+                .filter(|size| !size.is_empty())
+                .map(String::from);
+            Some(View {
+                mime,
+                language,
+                size,
+            })
+        })
+        .collect()
+}
+
+/// Reads a Gopher+ response, which starts with a line giving its length:
+/// a number of bytes, -1 for a text terminated by a line with a single
+/// period or -2 for data until the connection is closed. Errors start
+/// with `--` followed by the length and a message.
+fn read_gopher_plus_response(mut stream: Box<dyn Read + Send>) -> io::Result<Vec<u8>> {
+    let mut buf = vec![];
+    stream.read_to_end(&mut buf)?;
+    let header_end = buf
+        .iter()
+        .position(|b| *b == b'\n')
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no Gopher+ header"))?;
+    let header = String::from_utf8_lossy(&buf[..header_end]).trim().to_string();
+    let mut data = buf.split_off(header_end + 1);
+    if header.starts_with("--") {
+        let message = String::from_utf8_lossy(&data).trim().to_string();
+        return Err(io::Error::other(message));
+    }
+    match header.strip_prefix('+').map(str::parse::<i64>) {
+        Some(Ok(-1)) => {
+            if data.ends_with(b"\r\n.\r\n") {
+                data.truncate(data.len() - 3);
+            } else if data.ends_with(b"\n.\n") {
+                data.truncate(data.len() - 2);
+            }
+        }
+        Some(Ok(-2)) => (),
+        Some(Ok(len)) if len >= 0 => data.truncate(len as usize),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid Gopher+ header: {}", header),
+            ))
+        }
+    }
+    Ok(data)
+}
+
+/// Fetches the attributes of a Gopher+ item as text
+fn fetch_attribute_text(transport: &dyn Transport, url: &Url) -> io::Result<String> {
+    let stream = send(transport, url, &format!("{}\t!\r\n", selector(url)))?;
+    let attributes = read_gopher_plus_response(stream)?;
+    Ok(String::from_utf8_lossy(&attributes).to_string())
+}
+
+/// Fetches the views of a Gopher+ item from its attributes
+pub fn fetch_views(transport: &dyn Transport, url: &Url) -> io::Result<Vec<View>> {
+    Ok(parse_views(&fetch_attribute_text(transport, url)?))
+}
+
+/// A block of Gopher+ attributes, e.g. +INFO, +ADMIN or +ABSTRACT
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttributeBlock {
+    /// Name of the block without the leading +
+    pub name: String,
+    /// The text after the name and the following lines, without their
+    /// indentation
+    pub value: String,
+}
+
+/// Splits Gopher+ attributes into their blocks. A block starts with a
+/// line `+NAME: value`, following lines are indented by a space.
+pub fn parse_attributes(attributes: &str) -> Vec<AttributeBlock> {
+    let mut blocks: Vec<AttributeBlock> = Vec::new();
+    for line in attributes.lines() {
+        let line = line.trim_end_matches('\r');
+        if let Some(header) = line.strip_prefix('+') {
+            let (name, value) = header.split_once(':').unwrap_or((header, ""));
+            blocks.push(AttributeBlock {
+                name: name.to_string(),
+                value: value.trim().to_string(),
+            });
+        } else if let Some(block) = blocks.last_mut() {
+            if !block.value.is_empty() {
+                block.value.push('\n');
+            }
+            block.value.push_str(line.strip_prefix(' ').unwrap_or(line));
+        }
+    }
+    blocks
+}
+
+/// Fetches all attributes of a Gopher+ item
+pub fn fetch_attributes(transport: &dyn Transport, url: &Url) -> io::Result<Vec<AttributeBlock>> {
+    Ok(parse_attributes(&fetch_attribute_text(transport, url)?))
+}
+
+/// A question of a Gopher+ form (+ASK block)
+#[derive(Clone, Debug, PartialEq)]
+pub enum Question {
+    /// Text shown to the user, not answered
+    Note(String),
+    /// Single line of text with a default answer
+    Ask { prompt: String, default: String },
+    /// Password, which is not shown while typing
+    AskPassword { prompt: String },
+    /// Longer text
+    AskLong { prompt: String },
+    /// Yes or no, answered with 1 or 0
+    Select { prompt: String, default: bool },
+    /// One of several options
+    Choose { prompt: String, options: Vec<String> },
+}
+
+/// Parses the questions of a +ASK block. Unknown kinds of questions,
+/// e.g. file uploads, are asked as single lines of text.
+pub fn parse_ask(block: &str) -> Vec<Question> {
+    block
+        .lines()
+        .filter_map(|line| {
+            let (kind, rest) = line.split_once(':')?;
+            let mut fields = rest.trim_start().split('\t').map(String::from);
+            let prompt = fields.next().unwrap_or_default();
+            Some(match kind.trim() {
+                "Note" => Question::Note(prompt),
+                "AskP" => Question::AskPassword { prompt },
+                "AskL" => Question::AskLong { prompt },
+                "Select" => {
+                    // the default follows the prompt after a colon
+                    let (prompt, default) = match prompt.rsplit_once(':') {
+                        Some((prompt, default)) => (prompt.to_string(), default.trim() == "1"),
+                        None => (prompt, false),
+                    };
+                    Question::Select { prompt, default }
+                }
+                "Choose" => Question::Choose {
+                    prompt,
+                    options: fields.collect(),
+                },
+                _ => Question::Ask {
+                    prompt,
+                    default: fields.next().unwrap_or_default(),
+                },
+            })
+        })
+        .collect()
+}
+
+/// Sends the answers to a Gopher+ form and returns the response. There
+/// is an answer for each question except for notes. Answers are single
+/// lines, except for those to AskL questions, whose lines are followed
+/// by a line with a single period.
+pub fn submit_form(
+    transport: &dyn Transport,
+    url: &Url,
+    questions: &[Question],
+    answers: &[String],
+) -> io::Result<Vec<u8>> {
+    let mut request = format!("{}\t+\t1\r\n+-1\r\n", selector(url));
+    let questions = questions
+        .iter()
+        .filter(|question| !matches!(question, Question::Note(_)));
+    for (question, answer) in questions.zip(answers) {
+        if let Question::AskLong { .. } = question {
+            for line in answer.lines() {
+                // lines starting with a period are escaped by doubling it
+                if line.starts_with('.') {
+                    request.push('.');
+                }
+                request.push_str(line.trim_end_matches('\r'));
+                request.push_str("\r\n");
+            }
+            request.push_str(".\r\n");
+        } else {
+            request.push_str(&answer.replace(['\r', '\n'], " "));
+            request.push_str("\r\n");
+        }
+    }
+    request.push_str(".\r\n");
+    read_gopher_plus_response(send(transport, url, &request)?)
+}
+
+/// Fetches a view of a Gopher+ item
+pub fn fetch_view(transport: &dyn Transport, url: &Url, view: &View) -> io::Result<Vec<u8>> {
+    let request = format!("{}\t+{}\r\n", selector(url), view.request());
+    read_gopher_plus_response(send(transport, url, &request)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use native_tls::Identity;
+    use std::sync::Mutex;
+
+    /// Transport that remembers the request and answers with a response
+    struct Capture {
+        request: Mutex<Vec<u8>>,
+        response: &'static [u8],
+    }
+
+    impl Transport for Capture {
+        fn request(
+            &self,
+            _host: &str,
+            _port: u16,
+            _tls: bool,
+            request: &[u8],
+        ) -> io::Result<Box<dyn Read + Send>> {
+            *self.request.lock().unwrap() = request.to_vec();
+            Ok(Box::new(self.response))
+        }
+
+        fn request_self_signed(
+            &self,
+            _host: &str,
+            _port: u16,
+            _identity: Option<&Identity>,
+            _verify: &mut dyn FnMut(Option<&[u8]>) -> bool,
+            _request: &[u8],
+        ) -> io::Result<Box<dyn Read + Send>> {
+            unreachable!("gopher does not use self-signed certificates")
+        }
+    }
+
+    fn response(data: &'static [u8]) -> io::Result<Vec<u8>> {
+        read_gopher_plus_response(Box::new(data))
+    }
+
+    #[test]
+    fn views() {
+        let attributes = "+INFO: 0Readme\t/readme\texample.org\t70\t+\r\n\
+                          +VIEWS:\n \
+                          text/plain: <2k>\n \
+                          application/pdf De_DE: <120K>\n \
+                          image/gif\n\
+                          +ABSTRACT:\n \
+                          text/html: <1k>\n";
+        assert_eq!(
+            parse_views(attributes),
+            vec![
+                View {
+                    mime: "text/plain".to_string(),
+                    language: None,
+                    size: Some("2k".to_string()),
+                },
+                View {
+                    mime: "application/pdf".to_string(),
+                    language: Some("De_DE".to_string()),
+                    size: Some("120K".to_string()),
+                },
+                View {
+                    mime: "image/gif".to_string(),
+                    language: None,
+                    size: None,
+                },
+            ]
+        );
+        assert!(parse_views("+INFO: 0Readme\t/readme\texample.org\t70\t+\n").is_empty());
+    }
+
+    #[test]
+    fn ask() {
+        let block = "Note: Please sign the guestbook\n\
+                     Ask: Name\tAnonymous\n\
+                     AskP: Password\n\
+                     AskL: Message\n\
+                     Select: Subscribe:1\n\
+                     Choose: Color\tred\tgreen\n\
+                     AskF: Upload\n";
+        assert_eq!(
+            parse_ask(block),
+            vec![
+                Question::Note("Please sign the guestbook".to_string()),
+                Question::Ask {
+                    prompt: "Name".to_string(),
+                    default: "Anonymous".to_string(),
+                },
+                Question::AskPassword {
+                    prompt: "Password".to_string(),
+                },
+                Question::AskLong {
+                    prompt: "Message".to_string(),
+                },
+                Question::Select {
+                    prompt: "Subscribe".to_string(),
+                    default: true,
+                },
+                Question::Choose {
+                    prompt: "Color".to_string(),
+                    options: vec!["red".to_string(), "green".to_string()],
+                },
+                Question::Ask {
+                    prompt: "Upload".to_string(),
+                    default: String::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn response_lengths() {
+        assert_eq!(response(b"+5\r\nhello, world").unwrap(), b"hello");
+        assert_eq!(response(b"+-1\r\nline\r\n.\r\n").unwrap(), b"line\r\n");
+        assert_eq!(response(b"+-1\nline\n.\n").unwrap(), b"line\n");
+        assert_eq!(response(b"+-2\r\nuntil closed").unwrap(), b"until closed");
+    }
+
+    #[test]
+    fn response_errors() {
+        let err = response(b"--1\r\n1 Item is not available\r\n").unwrap_err();
+        assert_eq!(err.to_string(), "1 Item is not available");
+        let err = response(b"200 OK\r\n").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = response(b"").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn form_answers() {
+        let transport = Capture {
+            request: Mutex::new(Vec::new()),
+            response: b"+-2\r\nThanks!",
+        };
+        let url = Url::parse("gopher://example.org/1/guestbook").unwrap();
+        let questions = parse_ask("Note: Sign here\nAsk: Name\nAskL: Message\nSelect: Subscribe:0\n");
+        let answers = vec![
+            "Ann\r\nOnymous".to_string(),
+            "Hello\n.\nBye".to_string(),
+            "1".to_string(),
+        ];
+        let response = submit_form(&transport, &url, &questions, &answers).unwrap();
+        assert_eq!(response, b"Thanks!");
+        assert_eq!(
+            String::from_utf8(transport.request.into_inner().unwrap()).unwrap(),
+            "/guestbook\t+\t1\r\n+-1\r\nAnn  Onymous\r\nHello\r\n..\r\nBye\r\n.\r\n1\r\n.\r\n"
+        );
+    }
+}
@@ -16,6 +16,10 @@ pub struct GopherMapEntry {
     pub port: u16,
     /// The combined URL of host, port and selector
     pub url: Url,
+    /// The server supports Gopher+ for this item
+    pub gopher_plus: bool,
+    /// The item is a Gopher+ form (+ASK) to be filled in before fetching
+    pub ask: bool,
 }
 
 impl GopherMapEntry {
@@ -32,9 +36,11 @@ impl GopherMapEntry {
                 host: "about:blank".to_string(),
                 port: 70,
                 url: Url::parse("about:blank").unwrap(),
+                gopher_plus: false,
+                ask: false,
             });
         }
-        if l.len() == 0 {
+        if l.is_empty() {
             // Happens e.g. if a text file is parsed as a gophermap
             return Err("Invalid gophermap entry (2)");
         }
@@ -64,6 +70,8 @@ impl GopherMapEntry {
                 host,
                 port,
                 url,
+                gopher_plus: false,
+                ask: false,
             })
         } else {
             if l.len() <= 3 {
@@ -78,6 +86,13 @@ impl GopherMapEntry {
             path.insert(0, ch);
         }
 
+        // Links to other protocols, commonly to the web. Entries with an
+        // invalid URL link to the selector on the gopher server instead.
+        let target = if item_type == ItemType::Html && GopherMapEntry::is_url_selector(&selector) {
+            GopherMapEntry::parse_url_selector(&selector)
+        } else {
+            None
+        };
         if item_type == ItemType::Telnet {
             // Telnet URLs have no selector
             url.set_scheme("telnet").unwrap();
@@ -85,17 +100,8 @@ impl GopherMapEntry {
                 url.set_host(Some(host.as_str())).unwrap();
             }
             url.set_port(Some(port)).unwrap();
-        } else if item_type == ItemType::Html {
-            if path.starts_with("hURL:") {
-                let mut html_url = path;
-                html_url.replace_range(..5, "");
-                match Url::parse(html_url.as_str()) {
-                    Ok(u) => url = u,
-                    Err(e) => {
-                        warn!("Could not parse url {}: {}", e, html_url);
-                    }
-                }
-            }
+        } else if let Some(target) = target {
+            url = target;
         } else {
             if !host.is_empty() {
                 if let Err(e) = url.set_host(Some(host.as_str())) {
@@ -113,12 +119,154 @@ impl GopherMapEntry {
             host,
             port,
             url,
+            gopher_plus: l.get(4).is_some_and(|f| f.starts_with('+') || f.starts_with('?')),
+            ask: l.get(4).is_some_and(|f| f.contains('?')),
         })
     }
 
+    /// Returns true if the selector follows the `URL:` convention for
+    /// links to resources outside of gopherspace. Some servers prefix
+    /// the selector with a slash.
+    fn is_url_selector(selector: &str) -> bool {
+        let selector = selector.strip_prefix('/').unwrap_or(selector);
+        selector
+            .get(..4)
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case("URL:"))
+    }
+
+    /// Extracts the URL from a `URL:` selector
+    fn parse_url_selector(selector: &str) -> Option<Url> {
+        let selector = selector.strip_prefix('/').unwrap_or(selector);
+        let target = selector[4..].trim();
+        Url::parse(target)
+            .map_err(|e| warn!("Could not parse url {}: {}", target, e))
+            .ok()
+    }
+
     pub fn label(self) -> String {
         self.name
     }
+
+    /// Formats the entry for display using a template. The placeholders
+    /// `{type}`, `{title}`, `{host}`, `{port}`, `{selector}` and `{url}`
+    /// are replaced by the respective values. `title` is passed separately
+    /// so that wrapped info lines can be formatted row by row.
+    /// `type_label` replaces `{type}`, see [`ItemType::as_str`] for the
+    /// default labels.
+    pub fn format(&self, template: &str, type_label: &str, title: &str) -> String {
+        let (host, port, selector, url) = if self.item_type.is_inline() {
+            (String::new(), String::new(), String::new(), String::new())
+        } else {
+            (
+                self.host.clone(),
+                self.port.to_string(),
+                self.selector.clone(),
+                self.url.to_string(),
+            )
+        };
+        template
+            .replace("{type}", type_label)
+            .replace("{host}", &host)
+            .replace("{port}", &port)
+            .replace("{selector}", &selector)
+            .replace("{url}", &url)
+            // title last, so placeholders in titles are not replaced
+            .replace("{title}", title)
+    }
+}
+
+/// A problem in a gophermap found by [`lint`]
+#[derive(Clone, Debug)]
+pub struct LintIssue {
+    /// Line number, starting at 1
+    pub line: usize,
+    pub message: String,
+}
+
+/// RFC 1436 recommends keeping display strings under 70 characters
+const MAX_DISPLAY_STRING: usize = 70;
+/// Maximum selector length according to RFC 1436
+const MAX_SELECTOR: usize = 255;
+
+/// Checks a gophermap as sent by a server against RFC 1436 and returns
+/// the problems found, e.g. missing tabs, bad ports or unknown item types.
+pub fn lint(gophermap: &str) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    let mut issue = |line: usize, message: String| issues.push(LintIssue { line, message });
+    let mut lines = gophermap.split('\n').enumerate().peekable();
+    let mut terminated = false;
+    let mut reported_lf = false;
+    let mut last = 0;
+
+    while let Some((i, line)) = lines.next() {
+        let number = i + 1;
+        if lines.peek().is_none() && line.is_empty() {
+            // the newline of the last line
+            break;
+        }
+        last = number;
+        if terminated {
+            issue(number, "Content after terminating '.' line".to_string());
+            break;
+        }
+        if !line.ends_with('\r') && !reported_lf {
+            issue(number, "Line ends with LF instead of CRLF".to_string());
+            reported_lf = true;
+        }
+        let line = line.trim_end_matches('\r');
+        if line == "." {
+            terminated = true;
+            continue;
+        }
+        if line.is_empty() {
+            issue(number, "Empty line".to_string());
+            continue;
+        }
+
+        let fields = line.split('\t').collect::<Vec<_>>();
+        let item_type = line.chars().next().unwrap_or_default();
+        if let ItemType::Other(c) = ItemType::decode(item_type) {
+            issue(number, format!("Unknown item type '{}'", c));
+        }
+        if fields.len() < 4 {
+            issue(
+                number,
+                format!("Expected 4 tab separated fields, found {}", fields.len()),
+            );
+            continue;
+        }
+        let display_string = fields[0].get(item_type.len_utf8()..).unwrap_or_default();
+        if display_string.chars().count() >= MAX_DISPLAY_STRING {
+            issue(
+                number,
+                format!(
+                    "Display string has {} characters, should be under {}",
+                    display_string.chars().count(),
+                    MAX_DISPLAY_STRING
+                ),
+            );
+        }
+        if fields[1].len() > MAX_SELECTOR {
+            issue(
+                number,
+                format!("Selector is longer than {} characters", MAX_SELECTOR),
+            );
+        }
+        // Info lines do not point anywhere, so host and port do not matter
+        if item_type != 'i' {
+            if fields[2].is_empty() {
+                issue(number, "Missing host".to_string());
+            }
+            match fields[3].trim().parse::<u16>() {
+                Ok(port) if port > 0 => {}
+                _ => issue(number, format!("Bad port '{}'", fields[3])),
+            }
+        }
+    }
+    if !terminated && last > 0 {
+        issue(last, "Missing terminating '.' line".to_string());
+    }
+    issues
 }
 
 /// The type of a resource in a Gopher directory.
@@ -202,6 +350,34 @@ impl ItemType {
         }
     }
 
+    /// Returns the character of the item type in gophermaps
+    pub fn encode(self) -> char {
+        match self {
+            ItemType::File => '0',
+            ItemType::Dir => '1',
+            ItemType::CsoServer => '2',
+            ItemType::Error => '3',
+            ItemType::BinHex => '4',
+            ItemType::Dos => '5',
+            ItemType::Uuencoded => '6',
+            ItemType::IndexServer => '7',
+            ItemType::Telnet => '8',
+            ItemType::Binary => '9',
+            ItemType::RedundantServer => '+',
+            ItemType::Tn3270 => 'T',
+            ItemType::Gif => 'g',
+            ItemType::Image => 'I',
+            ItemType::Html => 'h',
+            ItemType::Document => 'd',
+            ItemType::Video => ';',
+            ItemType::Mime => 'M',
+            ItemType::Calendar => 'c',
+            ItemType::Sound => 's',
+            ItemType::Inline => 'i',
+            ItemType::Other(ch) => ch,
+        }
+    }
+
     pub fn as_str(item_type: ItemType) -> String {
         match item_type {
             ItemType::File => "[TXT]",
@@ -259,6 +435,10 @@ impl ItemType {
         matches!(self, ItemType::IndexServer)
     }
 
+    pub fn is_cso(self) -> bool {
+        matches!(self, ItemType::CsoServer)
+    }
+
     pub fn is_inline(self) -> bool {
         matches!(self, ItemType::Inline)
     }
@@ -286,3 +466,28 @@ impl ItemType {
         item_type
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_selector() {
+        let entry = GopherMapEntry::parse(
+            "hWeb site\tURL:https://example.com/page\tgopher.example.com\t70".to_string(),
+        )
+        .unwrap();
+        assert_eq!(entry.url.as_str(), "https://example.com/page");
+    }
+
+    #[test]
+    fn invalid_url_selector_links_to_gopher_server() {
+        let entry =
+            GopherMapEntry::parse("hBroken\tURL:not a url\tgopher.example.com\t7070".to_string())
+                .unwrap();
+        assert_eq!(entry.url.scheme(), "gopher");
+        assert_eq!(entry.url.host_str(), Some("gopher.example.com"));
+        assert_eq!(entry.url.port(), Some(7070));
+        assert_eq!(entry.url.path(), "/hURL:not%20a%20url");
+    }
+}
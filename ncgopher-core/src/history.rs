@@ -0,0 +1,496 @@
+use ::time::OffsetDateTime;
+use rusqlite::{params, params_from_iter, Connection, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+use url::Url;
+
+use crate::vault::Vault;
+
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    pub title: String,
+    pub url: Url,
+    pub timestamp: OffsetDateTime,
+    pub visited_count: u16,
+
+    pub position: usize,
+}
+
+/// A visited page matching a full-text search
+#[derive(Clone, Debug)]
+pub struct PageMatch {
+    pub url: Url,
+    pub title: String,
+    /// The first line of the page containing the search term
+    pub snippet: String,
+}
+
+/// Contents of the database tables as saved in the encrypted history file
+#[derive(Default, Serialize, Deserialize)]
+struct Dump {
+    #[serde(default)]
+    history: Vec<DumpedVisit>,
+    #[serde(default)]
+    pages: Vec<DumpedPage>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DumpedVisit {
+    title: Option<String>,
+    url: String,
+    timestmp: Option<String>,
+    visitedcount: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DumpedPage {
+    url: String,
+    title: Option<String>,
+    content: Option<String>,
+    timestmp: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct History {
+    /// Navigational stack, used for back-functionality
+    pub stack: Vec<HistoryEntry>,
+    /// Entries left by going back, most recent last
+    forward: Vec<HistoryEntry>,
+    /// Log of all visited gopherholes
+    sql: Connection,
+    /// With a vault, the database is kept in memory and saved encrypted
+    /// by `save`
+    vault: Option<Arc<Vault>>,
+    /// Unencrypted database to remove once the history has been saved
+    /// encrypted
+    migrate_from: Option<PathBuf>,
+    /// Number of changes to the database when it was last saved by
+    /// `save_in_background`
+    saved_changes: i64,
+}
+
+impl History {
+    pub fn new(vault: Option<Arc<Vault>>) -> std::result::Result<Self, Box<dyn Error>> {
+        info!("Creating history object");
+        let filename = History::get_history_filename();
+        let connection = match vault {
+            Some(_) => Connection::open_in_memory()?,
+            None => Connection::open(&filename)?,
+        };
+        History::create_tables(&connection)?;
+        let mut migrate_from = None;
+        if let Some(vault) = vault.as_ref() {
+            let encrypted = History::encrypted_path();
+            if encrypted.exists() {
+                let dump: Dump = toml::from_str(&String::from_utf8(vault.read(&encrypted)?)?)?;
+                History::load(&connection, dump)?;
+            } else if filename.exists() {
+                // copy the unencrypted history
+                connection.execute(
+                    "ATTACH DATABASE ?1 AS plain",
+                    params![filename.to_string_lossy()],
+                )?;
+                connection.execute(
+                    "INSERT INTO history (title, url, timestmp, visitedcount)
+                     SELECT title, url, timestmp, visitedcount FROM plain.history",
+                    [],
+                )?;
+                // histories of older versions have no indexed pages
+                let has_pages: bool = connection.query_row(
+                    "SELECT EXISTS (SELECT 1 FROM plain.sqlite_master
+                     WHERE type = 'table' AND name = 'pages')",
+                    [],
+                    |row| row.get(0),
+                )?;
+                if has_pages {
+                    connection.execute(
+                        "INSERT INTO pages (url, title, content, timestmp)
+                         SELECT url, title, content, timestmp FROM plain.pages",
+                        [],
+                    )?;
+                }
+                connection.execute("DETACH DATABASE plain", [])?;
+                migrate_from = Some(filename);
+            }
+        }
+        Ok(History {
+            stack: Vec::new(),
+            forward: Vec::new(),
+            sql: connection,
+            vault,
+            migrate_from,
+            saved_changes: 0,
+        })
+    }
+
+    /// Creates the tables of the history database if they do not exist
+    fn create_tables(connection: &Connection) -> Result<()> {
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS history (
+             id INTEGER PRIMARY KEY,
+             title TEXT,
+             url TEXT NOT NULL,
+             timestmp DATETIME DEFAULT CURRENT_TIMESTAMP,
+             visitedcount NUMBER NOT NULL DEFAULT 1
+         )",
+            [],
+        )?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS pages (
+             url TEXT PRIMARY KEY,
+             title TEXT,
+             content TEXT,
+             timestmp DATETIME DEFAULT CURRENT_TIMESTAMP
+         )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn get_history_filename() -> PathBuf {
+        let mut dir = dirs::config_dir().expect("no configuration directory");
+        dir.push(crate::CONFIG_DIR_NAME);
+        dir.push("history.db");
+        dir
+    }
+
+    /// Returns the path of the encrypted history file
+    pub fn encrypted_path() -> PathBuf {
+        History::get_history_filename().with_file_name("history.age")
+    }
+
+    fn load(connection: &Connection, dump: Dump) -> Result<()> {
+        for visit in dump.history {
+            connection.execute(
+                "INSERT INTO history (title, url, timestmp, visitedcount) VALUES (?1, ?2, ?3, ?4)",
+                params![visit.title, visit.url, visit.timestmp, visit.visitedcount],
+            )?;
+        }
+        for page in dump.pages {
+            connection.execute(
+                "INSERT OR REPLACE INTO pages (url, title, content, timestmp)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![page.url, page.title, page.content, page.timestmp],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn dump(&self) -> Result<Dump> {
+        let mut dump = Dump::default();
+        let mut stmt = self
+            .sql
+            .prepare("SELECT title, url, timestmp, visitedcount FROM history ORDER BY id")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            dump.history.push(DumpedVisit {
+                title: row.get(0)?,
+                url: row.get(1)?,
+                timestmp: row.get(2)?,
+                visitedcount: row.get(3)?,
+            });
+        }
+        let mut stmt = self
+            .sql
+            .prepare("SELECT url, title, content, timestmp FROM pages")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            dump.pages.push(DumpedPage {
+                url: row.get(0)?,
+                title: row.get(1)?,
+                content: row.get(2)?,
+                timestmp: row.get(3)?,
+            });
+        }
+        Ok(dump)
+    }
+
+    /// Writes the history to the encrypted history file. The unencrypted
+    /// history is written continuously, so there is nothing to do
+    /// without a vault.
+    pub fn save(&self) -> std::result::Result<(), Box<dyn Error>> {
+        let vault = match self.vault.as_ref() {
+            Some(vault) => vault,
+            None => return Ok(()),
+        };
+        // a write started by save_in_background must not overwrite this one
+        vault.flush();
+        let dump = toml::to_string(&self.dump()?)?;
+        vault.write(&History::encrypted_path(), dump.as_bytes())?;
+        if let Some(path) = self.migrate_from.as_ref() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the history to the encrypted history file in the
+    /// background if it changed since it was saved last, so that little
+    /// history is lost if ncgopher does not quit normally
+    pub fn save_in_background(&mut self) -> std::result::Result<(), Box<dyn Error>> {
+        let vault = match self.vault.as_ref() {
+            Some(vault) => vault,
+            None => return Ok(()),
+        };
+        let changes: i64 = self
+            .sql
+            .query_row("SELECT total_changes()", [], |row| row.get(0))?;
+        if changes == self.saved_changes {
+            return Ok(());
+        }
+        let dump = toml::to_string(&self.dump()?)?;
+        vault.write_in_background(History::encrypted_path(), dump.into_bytes());
+        self.saved_changes = changes;
+        Ok(())
+    }
+
+    pub fn add(&mut self, entry: HistoryEntry) -> Result<()> {
+        info!("Adding entry to history: {:?}", entry);
+        self.stack.push(entry.clone());
+        self.forward.clear();
+
+        trace!("History::add(): checking for entry with url {}", entry.url);
+        if self
+            .sql
+            .query_row(
+                "SELECT id FROM history WHERE url=?1",
+                params![&entry.url.to_string()],
+                |_| Ok(()),
+            )
+            .is_ok()
+        {
+            trace!("History::add(): Row exists, updating");
+            let mut stmt = self
+                .sql
+                .prepare("UPDATE history SET visitedcount=visitedcount+1,timestmp=datetime('NOW') WHERE url=?1")?;
+            stmt.execute(params![&entry.url.to_string()])?;
+        } else {
+            trace!("History::add(): Adding entry");
+            self.sql.execute(
+                "INSERT INTO history (url) values (?1)",
+                [entry.url.as_str()],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Records the title of a visited page, once it has been loaded
+    pub fn set_title(&self, url: &Url, title: &str) -> Result<()> {
+        trace!("History::set_title(): {} {}", url, title);
+        self.sql.execute(
+            "UPDATE history SET title=?1 WHERE url=?2",
+            params![title, url.as_str()],
+        )?;
+        Ok(())
+    }
+
+    pub fn clear(&mut self) -> Result<()> {
+        trace!("History::clear()");
+        self.stack.clear();
+        self.forward.clear();
+        self.sql.execute("DELETE FROM history", [])?;
+        self.sql.execute("DELETE FROM pages", [])?;
+        Ok(())
+    }
+
+    /// Stores the text of a visited page for full-text search
+    pub fn index_page(&self, url: &Url, title: &str, content: &str) -> Result<()> {
+        trace!("History::index_page(): {}", url);
+        self.sql.execute(
+            "INSERT OR REPLACE INTO pages (url, title, content, timestmp)
+             VALUES (?1, ?2, ?3, datetime('NOW'))",
+            params![url.to_string(), title, content],
+        )?;
+        Ok(())
+    }
+
+    /// Searches the text, title and URL of all visited pages for a
+    /// case-insensitive search term. Most recently visited pages come first.
+    pub fn search_pages(&self, query: &str) -> Result<Vec<PageMatch>> {
+        let pattern = format!(
+            "%{}%",
+            query
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        let mut stmt = self.sql.prepare(
+            "SELECT url, title, content FROM pages
+             WHERE content LIKE ?1 ESCAPE '\\' OR title LIKE ?1 ESCAPE '\\' OR url LIKE ?1 ESCAPE '\\'
+             ORDER BY timestmp DESC LIMIT 100",
+        )?;
+        let mut rows = stmt.query(params![pattern])?;
+        let query = query.to_lowercase();
+        let mut res = Vec::new();
+        while let Some(row) = rows.next()? {
+            let content: String = row.get(2)?;
+            let snippet = content
+                .lines()
+                .find(|line| line.to_lowercase().contains(&query))
+                .unwrap_or_default()
+                .trim()
+                .chars()
+                .take(80)
+                .collect();
+            res.push(PageMatch {
+                url: row.get(0)?,
+                title: row.get(1)?,
+                snippet,
+            });
+        }
+        trace!("Found {} pages matching {}", res.len(), query);
+        Ok(res)
+    }
+
+    /// Returns all visited URLs whose URL or title contains a
+    /// case-insensitive filter, most recently visited first. Titles are
+    /// taken from the indexed pages, falling back to the title recorded
+    /// with the visit and the URL.
+    pub fn entries(&self, filter: &str) -> Result<Vec<HistoryEntry>> {
+        let pattern = format!(
+            "%{}%",
+            filter
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        let mut stmt = self.sql.prepare(
+            "SELECT COALESCE(pages.title, history.title, history.url), history.url,
+                    history.timestmp, history.visitedcount
+             FROM history LEFT JOIN pages ON pages.url = history.url
+             WHERE history.url LIKE ?1 ESCAPE '\\' OR pages.title LIKE ?1 ESCAPE '\\'
+                OR history.title LIKE ?1 ESCAPE '\\'
+             ORDER BY history.timestmp DESC",
+        )?;
+        let mut rows = stmt.query(params![pattern])?;
+        let mut res = Vec::new();
+        while let Some(row) = rows.next()? {
+            res.push(HistoryEntry {
+                title: row.get(0)?,
+                url: row.get(1)?,
+                timestamp: row.get(2)?,
+                visited_count: row.get(3)?,
+                position: 0,
+            });
+        }
+        trace!("Found {} history entries matching {}", res.len(), filter);
+        Ok(res)
+    }
+
+    pub fn back(&mut self) -> Option<HistoryEntry> {
+        // Removes the topmost entry from the history and returns it
+        if self.stack.len() > 1 {
+            let entry = self.stack.pop()?;
+            self.forward.push(entry);
+            Some(self.stack.last()?.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Returns to the entry left by the last step back, if any
+    pub fn forward(&mut self) -> Option<HistoryEntry> {
+        let entry = self.forward.pop()?;
+        self.stack.push(entry.clone());
+        Some(entry)
+    }
+
+    pub fn update_selected_item(&mut self, index: usize) {
+        // Updates the current selection position of the history item
+        // on top of the stack
+        if !self.stack.is_empty() {
+            let mut item = self.stack.pop().expect("Could not fetch history item");
+            info!(
+                "update_selected_item(): {} {} => {}",
+                item.url, item.position, index
+            );
+            item.position = index;
+            self.stack.push(item);
+        }
+    }
+
+    pub fn get_latest_history(&self, num_items: usize) -> Result<Vec<HistoryEntry>> {
+        let mut res = Vec::<HistoryEntry>::new();
+        let mut stmt = self
+            .sql
+            .prepare(
+                "SELECT title, url, timestmp, visitedcount FROM history ORDER BY timestmp DESC LIMIT ?1",
+            )?;
+        let mut rows = stmt.query(params![num_items as u32])?;
+        while let Some(row) = rows.next()? {
+            let title = row.get(1)?;
+            let entry = HistoryEntry {
+                title,
+                url: row.get(1)?,
+                timestamp: row.get(2)?,
+                visited_count: row.get(3)?,
+                position: 0,
+            };
+            res.push(entry);
+        }
+        trace!("Returning {} history entries", res.len());
+        Ok(res)
+    }
+
+    /// Returns which of the given URLs have been visited
+    pub fn visited_urls(&self, urls: &[Url]) -> Result<HashSet<Url>> {
+        let mut res = HashSet::new();
+        // SQLite limits the number of parameters of a statement
+        for chunk in urls.chunks(500) {
+            let placeholders = vec!["?"; chunk.len()].join(",");
+            let mut stmt = self.sql.prepare(&format!(
+                "SELECT DISTINCT url FROM history WHERE url IN ({})",
+                placeholders
+            ))?;
+            let mut rows = stmt.query(params_from_iter(chunk))?;
+            while let Some(row) = rows.next()? {
+                res.insert(row.get(0)?);
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history() -> History {
+        let connection = Connection::open_in_memory().unwrap();
+        History::create_tables(&connection).unwrap();
+        History {
+            stack: Vec::new(),
+            forward: Vec::new(),
+            sql: connection,
+            vault: None,
+            migrate_from: None,
+            saved_changes: 0,
+        }
+    }
+
+    fn visit(url: &Url) -> HistoryEntry {
+        HistoryEntry {
+            title: url.to_string(),
+            url: url.clone(),
+            timestamp: OffsetDateTime::now_utc(),
+            visited_count: 1,
+            position: 0,
+        }
+    }
+
+    #[test]
+    fn entries_match_recorded_titles() {
+        let mut history = history();
+        let url = Url::parse("gopher://example.com/1/phlog").unwrap();
+        history.add(visit(&url)).unwrap();
+        history.set_title(&url, "Weekly Notes").unwrap();
+
+        let found = history.entries("weekly").unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].url, url);
+        assert_eq!(found[0].title, "Weekly Notes");
+        assert!(history.entries("monthly").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,52 @@
+//! Protocol and data handling of the ncgopher client, without any user
+//! interface. It can be used by other tools, e.g. bots or exporters.
+//!
+//! - [`gopher`], [`gemini`] and [`finger`] fetch documents from servers,
+//!   [`cso`] queries phonebook servers (item type 2)
+//! - [`gemini`] also extracts the plain text of gemtext documents
+//! - [`server`] serves a local directory over gopher for previews
+//! - [`transport`] abstracts the network access of the clients. Sessions
+//!   can be recorded to fixture files and replayed, e.g. for tests
+//! - [`gophermap`] parses gopher menus (RFC 1436)
+//! - [`uuencode`] decodes uuencoded files (item type 6)
+//! - [`bookmarks`] and [`history`] give access to the bookmarks and the
+//!   browsing history of ncgopher
+//! - [`certificates`] and [`clientcertificates`] store the known server
+//!   certificates (TOFU) and client certificates used for gemini
+//! - [`vault`] encrypts bookmarks and history with a passphrase
+//!
+//! ```no_run
+//! use ncgopher_core::gophermap::{GopherMapEntry, ItemType};
+//! use ncgopher_core::{gopher, transport::Network};
+//! use url::Url;
+//!
+//! let url = Url::parse("gopher://gopher.floodgap.com/1/").unwrap();
+//! let menu = gopher::fetch(&Network, &url).unwrap();
+//! for line in String::from_utf8_lossy(&menu).lines() {
+//!     if let Ok(entry) = GopherMapEntry::parse(line.to_string()) {
+//!         let type_label = ItemType::as_str(entry.item_type);
+//!         println!("{}", entry.format("{type}  {title}  {url}", &type_label, &entry.name));
+//!     }
+//! }
+//! ```
+
+#[macro_use]
+extern crate log;
+
+/// Name of the directory in the user's configuration directory holding
+/// the files of ncgopher
+pub const CONFIG_DIR_NAME: &str = "ncgopher";
+
+pub mod bookmarks;
+pub mod certificates;
+pub mod clientcertificates;
+pub mod cso;
+pub mod finger;
+pub mod gemini;
+pub mod gopher;
+pub mod gophermap;
+pub mod history;
+pub mod server;
+pub mod transport;
+pub mod uuencode;
+pub mod vault;
@@ -0,0 +1,164 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+
+/// Starts a gopher server on localhost serving the files in a directory,
+/// so authors can preview a gopher hole before uploading it. Directories
+/// with a `gophermap` file are served as that menu, other directories are
+/// listed. Port 0 picks a free port. Returns the address of the server.
+pub fn serve(root: PathBuf, port: u16) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    let addr = listener.local_addr()?;
+    info!("Serving {} on {}", root.display(), addr);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let root = root.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = handle(&root, stream, addr.port()) {
+                            warn!("Error serving request: {}", e);
+                        }
+                    });
+                }
+                Err(e) => warn!("Could not accept connection: {}", e),
+            }
+        }
+    });
+    Ok(addr)
+}
+
+fn handle(root: &Path, stream: TcpStream, port: u16) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    // Clients try TLS on non-standard ports first. Close the connection
+    // on a TLS handshake so they fall back to plain gopher.
+    if reader.fill_buf()?.first() == Some(&0x16) {
+        return Ok(());
+    }
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    // Ignore search terms of type 7 requests
+    let selector = line.trim_end_matches(&['\r', '\n'][..]);
+    let selector = selector.split('\t').next().unwrap_or_default();
+    info!("Serving selector '{}'", selector);
+
+    let mut stream = stream;
+    match resolve(root, selector) {
+        Some(path) if path.is_dir() => {
+            let gophermap = path.join("gophermap");
+            let menu = if gophermap.is_file() {
+                expand_gophermap(&fs::read_to_string(gophermap)?, selector, port)
+            } else {
+                list_directory(&path, selector, port)?
+            };
+            stream.write_all(menu.as_bytes())?;
+            stream.write_all(b".\r\n")
+        }
+        Some(path) if path.is_file() => stream.write_all(&fs::read(path)?),
+        _ => write!(
+            stream,
+            "3'{}' does not exist\t\terror.host\t1\r\n.\r\n",
+            selector
+        ),
+    }
+}
+
+/// Maps a selector to a path below the root. Selectors leaving the root
+/// are rejected.
+fn resolve(root: &Path, selector: &str) -> Option<PathBuf> {
+    let relative = Path::new(selector.trim_start_matches('/'));
+    if relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        Some(root.join(relative))
+    } else {
+        None
+    }
+}
+
+/// Joins a directory selector and a file name
+fn child_selector(dir: &str, name: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches('/'), name)
+}
+
+/// Completes a gophermap as written by authors: lines without tab are
+/// info lines, missing hosts and ports refer to this server and relative
+/// selectors to the directory of the gophermap.
+fn expand_gophermap(gophermap: &str, dir: &str, port: u16) -> String {
+    let mut menu = String::new();
+    for line in gophermap.lines() {
+        if line == "." {
+            break;
+        }
+        if !line.contains('\t') {
+            menu.push_str(&format!("i{}\t\tlocalhost\t{}\r\n", line, port));
+            continue;
+        }
+        let mut fields = line.split('\t').collect::<Vec<_>>();
+        let name = fields[0];
+        let selector = match fields.get(1) {
+            Some(s) if !s.is_empty() && !s.starts_with('/') && !s.starts_with("URL:") => {
+                child_selector(dir, s)
+            }
+            Some(s) if !s.is_empty() => s.to_string(),
+            _ => child_selector(dir, name.get(1..).unwrap_or_default()),
+        };
+        fields.resize(4, "");
+        let host = if fields[2].is_empty() { "localhost" } else { fields[2] };
+        let port = if fields[3].is_empty() {
+            port.to_string()
+        } else {
+            fields[3].to_string()
+        };
+        menu.push_str(&format!("{}\t{}\t{}\t{}\r\n", name, selector, host, port));
+    }
+    menu
+}
+
+/// Guesses the gopher item type of a file from its extension
+fn item_type(path: &Path) -> char {
+    if path.is_dir() {
+        return '1';
+    }
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "" | "txt" | "md" | "gmi" | "org" => '0',
+        "gif" => 'g',
+        "png" | "jpg" | "jpeg" | "bmp" | "webp" => 'I',
+        "html" | "htm" => 'h',
+        _ => '9',
+    }
+}
+
+/// Creates a menu of all files in a directory
+fn list_directory(path: &Path, dir: &str, port: u16) -> io::Result<String> {
+    let mut entries = fs::read_dir(path)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    entries.sort();
+    let mut menu = format!("iIndex of {}\t\tlocalhost\t{}\r\n", dir, port);
+    for entry in entries {
+        let name = entry
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if name.starts_with('.') {
+            continue;
+        }
+        menu.push_str(&format!(
+            "{}{}\t{}\tlocalhost\t{}\r\n",
+            item_type(&entry),
+            name,
+            child_selector(dir, &name),
+            port
+        ));
+    }
+    Ok(menu)
+}
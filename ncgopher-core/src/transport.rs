@@ -0,0 +1,1048 @@
+use native_tls::{Identity, Protocol, TlsConnector};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Network access of the protocol clients. A transport sends a request to
+/// a server and returns the response as a stream.
+pub trait Transport: Send + Sync {
+    fn request(
+        &self,
+        host: &str,
+        port: u16,
+        tls: bool,
+        request: &[u8],
+    ) -> io::Result<Box<dyn Read + Send>>;
+
+    /// Sends a request over TLS as gemini clients do: the certificate of
+    /// the server is not validated against certificate authorities, as
+    /// self-signed certificates are common, and a client certificate is
+    /// presented if given. The server certificate (DER) is passed to
+    /// `verify` before the request is sent. If it is not accepted, the
+    /// request fails with `ErrorKind::PermissionDenied`.
+    fn request_self_signed(
+        &self,
+        host: &str,
+        port: u16,
+        identity: Option<&Identity>,
+        verify: &mut dyn FnMut(Option<&[u8]>) -> bool,
+        request: &[u8],
+    ) -> io::Result<Box<dyn Read + Send>>;
+}
+
+fn tls_error(e: impl std::fmt::Display) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+fn rejected() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "server certificate not accepted")
+}
+
+/// Sends a request over a TCP stream, with TLS if requested
+fn send_request(
+    stream: TcpStream,
+    host: &str,
+    tls: bool,
+    request: &[u8],
+) -> io::Result<Box<dyn Read + Send>> {
+    if tls {
+        let connector = TlsConnector::new()
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        // IPv6 addresses are enclosed in brackets in URLs
+        let domain = host.trim_start_matches('[').trim_end_matches(']');
+        let mut stream = connector
+            .connect(domain, stream)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        stream.write_all(request)?;
+        Ok(Box::new(stream))
+    } else {
+        let mut stream = stream;
+        stream.write_all(request)?;
+        Ok(Box::new(stream))
+    }
+}
+
+/// Sends a request over a TCP stream with TLS, accepting self-signed
+/// certificates that `verify` accepts
+fn send_self_signed(
+    stream: TcpStream,
+    host: &str,
+    identity: Option<&Identity>,
+    verify: &mut dyn FnMut(Option<&[u8]>) -> bool,
+    request: &[u8],
+) -> io::Result<Box<dyn Read + Send>> {
+    let mut builder = TlsConnector::builder();
+    // Self-signed certificates are considered invalid, but they are quite
+    // common for gemini servers. They are checked by `verify` instead.
+    builder.danger_accept_invalid_certs(true);
+    // Rust's native-tls does not yet provide Tlsv13 :(
+    builder.min_protocol_version(Some(Protocol::Tlsv12));
+    if let Some(identity) = identity {
+        info!("Using identity for request");
+        builder.identity(identity.clone());
+    }
+    let connector = builder.build().map_err(tls_error)?;
+    // IPv6 addresses are enclosed in brackets in URLs
+    let domain = host.trim_start_matches('[').trim_end_matches(']');
+    let mut stream = connector.connect(domain, stream).map_err(tls_error)?;
+    let certificate = match stream.peer_certificate().map_err(tls_error)? {
+        Some(certificate) => Some(certificate.to_der().map_err(tls_error)?),
+        None => None,
+    };
+    if !verify(certificate.as_deref()) {
+        return Err(rejected());
+    }
+    stream.write_all(request)?;
+    Ok(Box::new(stream))
+}
+
+/// Transport over TCP, optionally with TLS
+pub struct Network;
+
+impl Transport for Network {
+    fn request(
+        &self,
+        host: &str,
+        port: u16,
+        tls: bool,
+        request: &[u8],
+    ) -> io::Result<Box<dyn Read + Send>> {
+        let stream = connect(None, None, host, port)?;
+        send_request(stream, host, tls, request)
+    }
+
+    fn request_self_signed(
+        &self,
+        host: &str,
+        port: u16,
+        identity: Option<&Identity>,
+        verify: &mut dyn FnMut(Option<&[u8]>) -> bool,
+        request: &[u8],
+    ) -> io::Result<Box<dyn Read + Send>> {
+        let stream = connect(None, None, host, port)?;
+        send_self_signed(stream, host, identity, verify, request)
+    }
+}
+
+/// Cached addresses by host and port, with the time they were resolved
+type DnsEntries = HashMap<(String, u16), (Instant, Vec<SocketAddr>)>;
+
+/// Remembers the addresses of hosts for some time, so that following
+/// links on the same server does not resolve its name again
+#[derive(Debug)]
+pub struct DnsCache {
+    ttl: Duration,
+    entries: Mutex<DnsEntries>,
+}
+
+impl DnsCache {
+    /// Creates a cache whose entries expire after `ttl`. With a `ttl` of
+    /// zero, nothing is cached.
+    pub fn new(ttl: Duration) -> Self {
+        DnsCache {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the addresses of a host, looking them up if they are not
+    /// cached or have expired
+    pub fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        let key = (host.to_string(), port);
+        if let Some((resolved, addrs)) = self.entries.lock().unwrap().get(&key) {
+            if resolved.elapsed() < self.ttl {
+                return Ok(addrs.clone());
+            }
+        }
+        let addrs: Vec<SocketAddr> = (host, port).to_socket_addrs()?.collect();
+        if !self.ttl.is_zero() {
+            self.entries
+                .lock()
+                .unwrap()
+                .insert(key, (Instant::now(), addrs.clone()));
+        }
+        Ok(addrs)
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+/// Time to wait for a connection attempt before trying the next address
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Opens a TCP connection to a host with several addresses by racing
+/// them as in Happy Eyeballs (RFC 8305): IPv6 and IPv4 addresses are
+/// tried alternately, each attempt started shortly after the previous
+/// one, and the first connection wins. A broken IPv6 route then only
+/// costs a fraction of a second instead of a timeout.
+fn connect_direct(cache: Option<&DnsCache>, host: &str, port: u16) -> io::Result<TcpStream> {
+    // IPv6 addresses are enclosed in brackets in URLs
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let resolved = match cache {
+        Some(cache) => cache.resolve(host, port)?,
+        None => (host, port).to_socket_addrs()?.collect(),
+    };
+    let (v6, v4): (Vec<SocketAddr>, Vec<SocketAddr>) =
+        resolved.into_iter().partition(|a| a.is_ipv6());
+    let mut addrs: Vec<SocketAddr> = vec![];
+    for i in 0..v6.len().max(v4.len()) {
+        addrs.extend(v6.get(i));
+        addrs.extend(v4.get(i));
+    }
+    if addrs.len() <= 1 {
+        return match addrs.first() {
+            Some(addr) => TcpStream::connect(addr),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "no address for host")),
+        };
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let mut pending = 0;
+    let mut last_error = None;
+    for addr in addrs {
+        let tx = tx.clone();
+        // connections that lose the race are closed when sending fails
+        thread::spawn(move || tx.send(TcpStream::connect(addr)));
+        pending += 1;
+        match rx.recv_timeout(CONNECTION_ATTEMPT_DELAY) {
+            Ok(Ok(stream)) => return Ok(stream),
+            Ok(Err(e)) => {
+                pending -= 1;
+                last_error = Some(e);
+            }
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => (),
+        }
+    }
+    drop(tx);
+    while pending > 0 {
+        match rx.recv() {
+            Ok(Ok(stream)) => return Ok(stream),
+            Ok(Err(e)) => {
+                pending -= 1;
+                last_error = Some(e);
+            }
+            Err(_) => break,
+        }
+    }
+    Err(last_error.unwrap_or_else(|| io::Error::other("could not connect")))
+}
+
+/// A SOCKS5 proxy (RFC 1928). Host names are resolved by the proxy, so
+/// it can be used with Tor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Proxy {
+    pub host: String,
+    pub port: u16,
+    /// User name for authentication (RFC 1929), empty for none
+    pub username: String,
+    pub password: String,
+}
+
+fn socks_error(message: &str) -> io::Error {
+    io::Error::other(format!("SOCKS5 proxy: {}", message))
+}
+
+impl Proxy {
+    /// Opens a connection to `host` through the proxy
+    pub fn connect(&self, host: &str, port: u16) -> io::Result<TcpStream> {
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port))?;
+
+        let auth = !self.username.is_empty();
+        if auth {
+            stream.write_all(&[5, 2, 0, 2])?;
+        } else {
+            stream.write_all(&[5, 1, 0])?;
+        }
+        let mut reply = [0u8; 2];
+        stream.read_exact(&mut reply)?;
+        match reply {
+            [5, 0] => (),
+            [5, 2] if auth => {
+                if self.username.len() > 255 || self.password.len() > 255 {
+                    return Err(socks_error("user name or password too long"));
+                }
+                let mut request = vec![1, self.username.len() as u8];
+                request.extend_from_slice(self.username.as_bytes());
+                request.push(self.password.len() as u8);
+                request.extend_from_slice(self.password.as_bytes());
+                stream.write_all(&request)?;
+                stream.read_exact(&mut reply)?;
+                if reply[1] != 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        "SOCKS5 proxy: authentication failed",
+                    ));
+                }
+            }
+            [5, _] => return Err(socks_error("no acceptable authentication method")),
+            _ => return Err(socks_error("not a SOCKS5 server")),
+        }
+
+        // IPv6 addresses are enclosed in brackets in URLs
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let mut request = vec![5, 1, 0];
+        match host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => {
+                request.push(1);
+                request.extend_from_slice(&ip.octets());
+            }
+            Ok(IpAddr::V6(ip)) => {
+                request.push(4);
+                request.extend_from_slice(&ip.octets());
+            }
+            Err(_) if host.len() > 255 => return Err(socks_error("host name too long")),
+            Err(_) => {
+                request.push(3);
+                request.push(host.len() as u8);
+                request.extend_from_slice(host.as_bytes());
+            }
+        }
+        request.extend_from_slice(&port.to_be_bytes());
+        stream.write_all(&request)?;
+
+        let mut reply = [0u8; 4];
+        stream.read_exact(&mut reply)?;
+        if reply[1] != 0 {
+            let message = match reply[1] {
+                1 => "general failure",
+                2 => "connection not allowed",
+                3 => "network unreachable",
+                4 => "host unreachable",
+                5 => "connection refused",
+                6 => "TTL expired",
+                7 => "command not supported",
+                8 => "address type not supported",
+                _ => "unknown error",
+            };
+            return Err(socks_error(message));
+        }
+        // skip the bound address
+        let len = match reply[3] {
+            1 => 4,
+            4 => 16,
+            3 => {
+                let mut len = [0u8; 1];
+                stream.read_exact(&mut len)?;
+                len[0] as usize
+            }
+            _ => return Err(socks_error("invalid reply")),
+        };
+        let mut address = vec![0u8; len + 2];
+        stream.read_exact(&mut address)?;
+        Ok(stream)
+    }
+}
+
+/// Opens a TCP connection, through a proxy if given. Direct connections
+/// look up the host in the DNS cache if given.
+pub fn connect(
+    proxy: Option<&Proxy>,
+    cache: Option<&DnsCache>,
+    host: &str,
+    port: u16,
+) -> io::Result<TcpStream> {
+    let stream = match proxy {
+        Some(proxy) => proxy.connect(host, port)?,
+        None => connect_direct(cache, host, port)?,
+    };
+    opened(&stream);
+    Ok(stream)
+}
+
+/// Chooses the proxy for a host
+type ProxyFn = dyn Fn(&str) -> io::Result<Option<Proxy>> + Send + Sync;
+
+/// Transport over TCP that connects through the proxy returned by a
+/// function for the host, or directly if it returns `None`. Errors of
+/// the function fail the request. The function is called for every
+/// request, so the proxy can be changed at any time.
+pub struct Proxied {
+    proxy: Box<ProxyFn>,
+    dns_cache: Arc<DnsCache>,
+}
+
+impl Proxied {
+    pub fn new(
+        proxy: impl Fn(&str) -> io::Result<Option<Proxy>> + Send + Sync + 'static,
+        dns_cache: Arc<DnsCache>,
+    ) -> Self {
+        Proxied {
+            proxy: Box::new(proxy),
+            dns_cache,
+        }
+    }
+}
+
+impl Transport for Proxied {
+    fn request(
+        &self,
+        host: &str,
+        port: u16,
+        tls: bool,
+        request: &[u8],
+    ) -> io::Result<Box<dyn Read + Send>> {
+        let proxy = (self.proxy)(host)?;
+        let stream = connect(proxy.as_ref(), Some(&self.dns_cache), host, port)?;
+        send_request(stream, host, tls, request)
+    }
+
+    fn request_self_signed(
+        &self,
+        host: &str,
+        port: u16,
+        identity: Option<&Identity>,
+        verify: &mut dyn FnMut(Option<&[u8]>) -> bool,
+        request: &[u8],
+    ) -> io::Result<Box<dyn Read + Send>> {
+        let proxy = (self.proxy)(host)?;
+        let stream = connect(proxy.as_ref(), Some(&self.dns_cache), host, port)?;
+        send_self_signed(stream, host, identity, verify, request)
+    }
+}
+
+/// FNV-1a, which unlike the std hasher is stable across Rust versions
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Returns the fixture file for a request. The name contains host and
+/// port to make fixture directories browsable.
+fn fixture_path(dir: &Path, host: &str, port: u16, tls: bool, request: &[u8]) -> PathBuf {
+    let host = host.replace(|c: char| !c.is_ascii_alphanumeric() && c != '.' && c != '-', "_");
+    let mut key = vec![tls as u8];
+    key.extend_from_slice(request);
+    dir.join(format!("{}-{}-{:016x}", host, port, fnv1a(&key)))
+}
+
+/// Returns the file holding the server certificate of a fixture
+fn certificate_path(fixture: &Path) -> PathBuf {
+    let mut path = fixture.as_os_str().to_owned();
+    path.push(".der");
+    PathBuf::from(path)
+}
+
+/// Transport that passes requests to another transport and saves the
+/// responses as fixtures in a directory, for replaying them later.
+pub struct Recorder<T: Transport> {
+    inner: T,
+    dir: PathBuf,
+}
+
+impl<T: Transport> Recorder<T> {
+    pub fn new(inner: T, dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Recorder { inner, dir })
+    }
+}
+
+/// Copies everything read from a stream to a file
+struct Tee {
+    stream: Box<dyn Read + Send>,
+    file: File,
+}
+
+impl Read for Tee {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.stream.read(buf)?;
+        self.file.write_all(&buf[..bytes_read])?;
+        Ok(bytes_read)
+    }
+}
+
+impl<T: Transport> Transport for Recorder<T> {
+    fn request(
+        &self,
+        host: &str,
+        port: u16,
+        tls: bool,
+        request: &[u8],
+    ) -> io::Result<Box<dyn Read + Send>> {
+        let stream = self.inner.request(host, port, tls, request)?;
+        let path = fixture_path(&self.dir, host, port, tls, request);
+        info!("Recording {}:{} to {}", host, port, path.display());
+        let file = File::create(path)?;
+        Ok(Box::new(Tee { stream, file }))
+    }
+
+    fn request_self_signed(
+        &self,
+        host: &str,
+        port: u16,
+        identity: Option<&Identity>,
+        verify: &mut dyn FnMut(Option<&[u8]>) -> bool,
+        request: &[u8],
+    ) -> io::Result<Box<dyn Read + Send>> {
+        let mut certificate = None;
+        let mut record = |der: Option<&[u8]>| {
+            certificate = der.map(<[u8]>::to_vec);
+            verify(der)
+        };
+        let stream = self
+            .inner
+            .request_self_signed(host, port, identity, &mut record, request)?;
+        let path = fixture_path(&self.dir, host, port, true, request);
+        info!("Recording {}:{} to {}", host, port, path.display());
+        if let Some(der) = certificate {
+            fs::write(certificate_path(&path), der)?;
+        }
+        let file = File::create(path)?;
+        Ok(Box::new(Tee { stream, file }))
+    }
+}
+
+/// Transport that answers requests from fixtures saved by a [`Recorder`]
+/// without any network access. Requests without fixture fail with
+/// `ErrorKind::NotFound`.
+pub struct Replay {
+    dir: PathBuf,
+}
+
+impl Replay {
+    pub fn new(dir: PathBuf) -> Self {
+        Replay { dir }
+    }
+}
+
+impl Transport for Replay {
+    fn request(
+        &self,
+        host: &str,
+        port: u16,
+        tls: bool,
+        request: &[u8],
+    ) -> io::Result<Box<dyn Read + Send>> {
+        let path = fixture_path(&self.dir, host, port, tls, request);
+        info!("Replaying {}:{} from {}", host, port, path.display());
+        Ok(Box::new(File::open(path)?))
+    }
+
+    fn request_self_signed(
+        &self,
+        host: &str,
+        port: u16,
+        _identity: Option<&Identity>,
+        verify: &mut dyn FnMut(Option<&[u8]>) -> bool,
+        request: &[u8],
+    ) -> io::Result<Box<dyn Read + Send>> {
+        let path = fixture_path(&self.dir, host, port, true, request);
+        info!("Replaying {}:{} from {}", host, port, path.display());
+        let certificate = match fs::read(certificate_path(&path)) {
+            Ok(der) => Some(der),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        if !verify(certificate.as_deref()) {
+            return Err(rejected());
+        }
+        Ok(Box::new(File::open(path)?))
+    }
+}
+
+/// Limits the combined throughput of all streams read through it. The
+/// limit in bytes per second is queried for every read, so it can be
+/// changed while transfers are running. A limit of 0 means no limit.
+pub struct RateLimiter {
+    limit: Box<dyn Fn() -> u64 + Send + Sync>,
+    /// Point in time until which the bandwidth has been used up
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: impl Fn() -> u64 + Send + Sync + 'static) -> Self {
+        RateLimiter {
+            limit: Box::new(limit),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Returns how many bytes may be read at once, about a second worth
+    fn chunk_size(&self) -> usize {
+        match (self.limit)() {
+            0 => usize::MAX,
+            limit => limit as usize,
+        }
+    }
+
+    /// Accounts for `bytes` that have been read and sleeps until reading
+    /// them is within the limit
+    pub fn consume(&self, bytes: usize) {
+        thread::sleep(self.reserve(bytes, Instant::now()));
+    }
+
+    /// Accounts for `bytes` that have been read at `now` and returns how
+    /// long to wait until reading them is within the limit
+    fn reserve(&self, bytes: usize, now: Instant) -> Duration {
+        let limit = (self.limit)();
+        if limit == 0 || bytes == 0 {
+            return Duration::ZERO;
+        }
+        let until = {
+            let mut next = self.next.lock().unwrap();
+            // idle time is not saved up for bursts
+            if *next < now {
+                *next = now;
+            }
+            *next += Duration::from_secs_f64(bytes as f64 / limit as f64);
+            *next
+        };
+        until.saturating_duration_since(now)
+    }
+}
+
+/// Stream whose reads are limited by a [`RateLimiter`]
+pub struct ThrottledReader<R: Read> {
+    inner: R,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<R: Read> ThrottledReader<R> {
+    pub fn new(inner: R, limiter: Arc<RateLimiter>) -> Self {
+        ThrottledReader { inner, limiter }
+    }
+}
+
+impl<R: Read> Read for ThrottledReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.limiter.chunk_size());
+        let bytes_read = self.inner.read(&mut buf[..len])?;
+        self.limiter.consume(bytes_read);
+        Ok(bytes_read)
+    }
+}
+
+/// Transport whose responses share the bandwidth of a [`RateLimiter`]
+pub struct Throttled<T: Transport> {
+    inner: T,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<T: Transport> Throttled<T> {
+    pub fn new(inner: T, limiter: Arc<RateLimiter>) -> Self {
+        Throttled { inner, limiter }
+    }
+}
+
+impl<T: Transport> Transport for Throttled<T> {
+    fn request(
+        &self,
+        host: &str,
+        port: u16,
+        tls: bool,
+        request: &[u8],
+    ) -> io::Result<Box<dyn Read + Send>> {
+        let stream = self.inner.request(host, port, tls, request)?;
+        Ok(Box::new(ThrottledReader::new(stream, self.limiter.clone())))
+    }
+
+    fn request_self_signed(
+        &self,
+        host: &str,
+        port: u16,
+        identity: Option<&Identity>,
+        verify: &mut dyn FnMut(Option<&[u8]>) -> bool,
+        request: &[u8],
+    ) -> io::Result<Box<dyn Read + Send>> {
+        let stream = self
+            .inner
+            .request_self_signed(host, port, identity, verify, request)?;
+        Ok(Box::new(ThrottledReader::new(stream, self.limiter.clone())))
+    }
+}
+
+thread_local! {
+    /// Connections opened on this thread while a [`Cancellable`] sends a
+    /// request
+    static OPENED: RefCell<Option<Vec<TcpStream>>> = const { RefCell::new(None) };
+}
+
+/// Remembers a connection opened for the request a [`Cancellable`] is
+/// sending on this thread, if any
+fn opened(stream: &TcpStream) {
+    OPENED.with(|opened| {
+        if let Some(opened) = opened.borrow_mut().as_mut() {
+            match stream.try_clone() {
+                Ok(stream) => opened.push(stream),
+                Err(e) => warn!("Could not watch connection for cancelling: {}", e),
+            }
+        }
+    });
+}
+
+/// Calls `send` and returns its result with the connections it opened
+fn with_opened<T>(send: impl FnOnce() -> T) -> (T, Vec<TcpStream>) {
+    OPENED.with(|opened| *opened.borrow_mut() = Some(Vec::new()));
+    let result = send();
+    let connections = OPENED.with(|opened| opened.borrow_mut().take());
+    (result, connections.unwrap_or_default())
+}
+
+#[derive(Debug, Default)]
+struct TokenState {
+    cancelled: AtomicBool,
+    /// Connections of the responses being read, by the id returned by
+    /// `watch`
+    connections: Mutex<Vec<(u64, TcpStream)>>,
+    next_id: AtomicU64,
+}
+
+/// Flag shared between the user interface and a transfer to abort it
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<TokenState>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    /// Aborts the transfers, shutting down their connections so that
+    /// reads waiting for data return at once
+    pub fn cancel(&self) {
+        let mut connections = self.0.connections.lock().unwrap();
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        for (_, connection) in connections.drain(..) {
+            // fails if the server has closed the connection already
+            let _ = connection.shutdown(Shutdown::Both);
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Shuts down connections when the token is cancelled, or right away
+    /// if it is cancelled already. Returns the id for `unwatch`.
+    fn watch(&self, connections: Vec<TcpStream>) -> u64 {
+        let id = self.0.next_id.fetch_add(1, Ordering::SeqCst);
+        let mut watched = self.0.connections.lock().unwrap();
+        for connection in connections {
+            if self.is_cancelled() {
+                let _ = connection.shutdown(Shutdown::Both);
+            } else {
+                watched.push((id, connection));
+            }
+        }
+        id
+    }
+
+    /// Forgets the connections of a response that has been read
+    fn unwatch(&self, id: u64) {
+        self.0.connections.lock().unwrap().retain(|(i, _)| *i != id);
+    }
+}
+
+fn cancelled() -> io::Error {
+    io::Error::other("request cancelled")
+}
+
+/// Stream that fails with "request cancelled" once its [`CancelToken`]
+/// is cancelled. The connections of responses of [`Cancellable`] are
+/// shut down then, so that a read waiting for data returns at once.
+/// Other streams are only checked between reads.
+pub struct CancellableReader<R: Read> {
+    inner: R,
+    token: CancelToken,
+    /// Id of the connections watched by the token
+    watched: Option<u64>,
+}
+
+impl<R: Read> CancellableReader<R> {
+    pub fn new(inner: R, token: CancelToken) -> Self {
+        CancellableReader {
+            inner,
+            token,
+            watched: None,
+        }
+    }
+
+    /// Wraps a response whose connections are shut down on cancelling
+    fn watching(inner: R, token: CancelToken, connections: Vec<TcpStream>) -> Self {
+        let watched = Some(token.watch(connections));
+        CancellableReader {
+            inner,
+            token,
+            watched,
+        }
+    }
+}
+
+impl<R: Read> Read for CancellableReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.token.is_cancelled() {
+            return Err(cancelled());
+        }
+        let result = self.inner.read(buf);
+        // a connection that has been shut down looks like the end of
+        // the response
+        if self.token.is_cancelled() {
+            return Err(cancelled());
+        }
+        result
+    }
+}
+
+impl<R: Read> Drop for CancellableReader<R> {
+    fn drop(&mut self) {
+        if let Some(id) = self.watched {
+            self.token.unwatch(id);
+        }
+    }
+}
+
+/// Transport whose responses can be aborted with a [`CancelToken`]
+pub struct Cancellable {
+    inner: Arc<dyn Transport>,
+    token: CancelToken,
+}
+
+impl Cancellable {
+    pub fn new(inner: Arc<dyn Transport>, token: CancelToken) -> Self {
+        Cancellable { inner, token }
+    }
+}
+
+impl Transport for Cancellable {
+    fn request(
+        &self,
+        host: &str,
+        port: u16,
+        tls: bool,
+        request: &[u8],
+    ) -> io::Result<Box<dyn Read + Send>> {
+        let (stream, connections) = with_opened(|| self.inner.request(host, port, tls, request));
+        Ok(Box::new(CancellableReader::watching(
+            stream?,
+            self.token.clone(),
+            connections,
+        )))
+    }
+
+    fn request_self_signed(
+        &self,
+        host: &str,
+        port: u16,
+        identity: Option<&Identity>,
+        verify: &mut dyn FnMut(Option<&[u8]>) -> bool,
+        request: &[u8],
+    ) -> io::Result<Box<dyn Read + Send>> {
+        let (stream, connections) = with_opened(|| {
+            self.inner
+                .request_self_signed(host, port, identity, verify, request)
+        });
+        Ok(Box::new(CancellableReader::watching(
+            stream?,
+            self.token.clone(),
+            connections,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv6Addr, TcpListener};
+
+    /// Starts a proxy on a local port that follows a script
+    fn socks_server(script: impl FnOnce(TcpStream) + Send + 'static) -> Proxy {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || script(listener.accept().unwrap().0));
+        Proxy {
+            host: "127.0.0.1".to_string(),
+            port,
+            username: String::new(),
+            password: String::new(),
+        }
+    }
+
+    fn expect(stream: &mut TcpStream, expected: &[u8]) {
+        let mut buf = vec![0u8; expected.len()];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn socks_request_by_name() {
+        let proxy = socks_server(|mut stream| {
+            expect(&mut stream, &[5, 1, 0]);
+            stream.write_all(&[5, 0]).unwrap();
+            expect(&mut stream, b"\x05\x01\x00\x03\x0bexample.org\x00\x46");
+            stream.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0x1f, 0x90]).unwrap();
+            expect(&mut stream, b"/about\r\n");
+            stream.write_all(b"hello").unwrap();
+        });
+        let transport = Proxied::new(
+            move |_| Ok(Some(proxy.clone())),
+            Arc::new(DnsCache::new(Duration::ZERO)),
+        );
+        let mut stream = transport
+            .request("example.org", 70, false, b"/about\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert_eq!(response, "hello");
+    }
+
+    #[test]
+    fn socks_connect_to_ipv6_address() {
+        let proxy = socks_server(|mut stream| {
+            expect(&mut stream, &[5, 1, 0]);
+            stream.write_all(&[5, 0]).unwrap();
+            let ip: Ipv6Addr = "2001:db8::1".parse().unwrap();
+            let mut request = vec![5, 1, 0, 4];
+            request.extend_from_slice(&ip.octets());
+            request.extend_from_slice(&[0x07, 0xad]);
+            expect(&mut stream, &request);
+            // the bound address is a name in this reply
+            stream.write_all(b"\x05\x00\x00\x03\x05proxy\x1f\x90").unwrap();
+            stream.write_all(b"hello").unwrap();
+        });
+        let mut stream = proxy.connect("[2001:db8::1]", 1965).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert_eq!(response, "hello");
+    }
+
+    #[test]
+    fn socks_authentication_rejected() {
+        let mut proxy = socks_server(|mut stream| {
+            expect(&mut stream, &[5, 2, 0, 2]);
+            stream.write_all(&[5, 2]).unwrap();
+            expect(&mut stream, b"\x01\x04user\x06secret");
+            stream.write_all(&[1, 1]).unwrap();
+        });
+        proxy.username = "user".to_string();
+        proxy.password = "secret".to_string();
+        let err = proxy.connect("example.org", 70).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn socks_connection_refused() {
+        let proxy = socks_server(|mut stream| {
+            expect(&mut stream, &[5, 1, 0]);
+            stream.write_all(&[5, 0]).unwrap();
+            expect(&mut stream, &[5, 1, 0, 1, 192, 0, 2, 1, 0, 70]);
+            stream.write_all(&[5, 5, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+        });
+        let err = proxy.connect("192.0.2.1", 70).unwrap_err();
+        assert_eq!(err.to_string(), "SOCKS5 proxy: connection refused");
+    }
+
+    #[test]
+    fn socks_unknown_address_type() {
+        let proxy = socks_server(|mut stream| {
+            expect(&mut stream, &[5, 1, 0]);
+            stream.write_all(&[5, 0]).unwrap();
+            expect(&mut stream, &[5, 1, 0, 1, 192, 0, 2, 1, 0, 70]);
+            stream.write_all(&[5, 0, 0, 9]).unwrap();
+        });
+        let err = proxy.connect("192.0.2.1", 70).unwrap_err();
+        assert_eq!(err.to_string(), "SOCKS5 proxy: invalid reply");
+    }
+
+    #[test]
+    fn not_a_socks_server() {
+        let proxy = socks_server(|mut stream| {
+            expect(&mut stream, &[5, 1, 0]);
+            stream.write_all(b"HTTP/1.1 400 Bad Request\r\n").unwrap();
+        });
+        let err = proxy.connect("example.org", 70).unwrap_err();
+        assert_eq!(err.to_string(), "SOCKS5 proxy: not a SOCKS5 server");
+    }
+
+    #[test]
+    fn connect_falls_back_to_second_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // nothing listens on port 1, so the IPv6 attempt fails first
+        let cache = DnsCache::new(Duration::from_secs(60));
+        cache.entries.lock().unwrap().insert(
+            ("dual.example".to_string(), addr.port()),
+            (Instant::now(), vec!["[::1]:1".parse().unwrap(), addr]),
+        );
+        let stream = connect_direct(Some(&cache), "dual.example", addr.port()).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+    }
+
+    #[test]
+    fn rate_limiter_budget() {
+        let limiter = RateLimiter::new(|| 1000);
+        let start = Instant::now();
+        assert_eq!(limiter.reserve(500, start), Duration::from_millis(500));
+        // the second read has to wait for the first one, too
+        assert_eq!(limiter.reserve(500, start), Duration::from_secs(1));
+        let later = start + Duration::from_millis(600);
+        assert_eq!(limiter.reserve(100, later), Duration::from_millis(500));
+        // idle time is not saved up
+        let idle = start + Duration::from_secs(10);
+        assert_eq!(limiter.reserve(250, idle), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn rate_limiter_without_limit() {
+        let limiter = RateLimiter::new(|| 0);
+        assert_eq!(limiter.reserve(1 << 20, Instant::now()), Duration::ZERO);
+        assert_eq!(limiter.chunk_size(), usize::MAX);
+    }
+
+    #[test]
+    fn dns_cache_expiry() {
+        let cache = DnsCache::new(Duration::from_millis(50));
+        let cached: SocketAddr = "192.0.2.1:70".parse().unwrap();
+        cache.entries.lock().unwrap().insert(
+            ("127.0.0.1".to_string(), 70),
+            (Instant::now(), vec![cached]),
+        );
+        // reused while fresh
+        assert_eq!(cache.resolve("127.0.0.1", 70).unwrap(), vec![cached]);
+        thread::sleep(Duration::from_millis(60));
+        // looked up again once expired, and the new result is cached
+        let resolved: SocketAddr = "127.0.0.1:70".parse().unwrap();
+        assert_eq!(cache.resolve("127.0.0.1", 70).unwrap(), vec![resolved]);
+        let entries = cache.entries.lock().unwrap();
+        assert_eq!(entries[&("127.0.0.1".to_string(), 70)].1, vec![resolved]);
+    }
+
+    #[test]
+    fn dns_cache_disabled() {
+        let cache = DnsCache::new(Duration::ZERO);
+        cache.resolve("127.0.0.1", 70).unwrap();
+        assert!(cache.entries.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn cancel_interrupts_waiting_read() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // the server accepts the request, but never answers
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            thread::sleep(Duration::from_secs(10));
+            drop(stream);
+        });
+        let token = CancelToken::new();
+        let transport = Cancellable::new(Arc::new(Network), token.clone());
+        let mut stream = transport.request("127.0.0.1", port, false, b"/\r\n").unwrap();
+        let start = Instant::now();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            token.cancel();
+        });
+        let mut buf = [0u8; 16];
+        let err = stream.read(&mut buf).unwrap_err();
+        assert_eq!(err.to_string(), "request cancelled");
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
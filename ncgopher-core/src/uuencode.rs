@@ -0,0 +1,117 @@
+use std::path::Path;
+
+/// A file decoded from uuencoded text
+#[derive(Clone, Debug)]
+pub struct UudecodedFile {
+    /// File name from the begin line, without any directories
+    pub filename: String,
+    pub data: Vec<u8>,
+}
+
+/// Decodes a single uuencoded character to its 6 bit value
+fn sixbit(c: u8) -> u8 {
+    c.wrapping_sub(b' ') & 0x3f
+}
+
+/// Decodes the first uuencoded file in a text, as sent by gopher items
+/// of type 6. Text before the begin line is ignored.
+pub fn decode(text: &str) -> Result<UudecodedFile, &'static str> {
+    let mut lines = text.lines().map(|line| line.trim_end_matches('\r'));
+    let begin = lines
+        .find(|line| line.starts_with("begin "))
+        .ok_or("No begin line found")?;
+    // begin <mode> <file name>
+    let filename = begin
+        .splitn(3, ' ')
+        .nth(2)
+        .and_then(|name| Path::new(name.trim()).file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "download".to_string());
+
+    let mut data = Vec::new();
+    for line in lines {
+        if line == "end" {
+            return Ok(UudecodedFile { filename, data });
+        }
+        let bytes = line.as_bytes();
+        let len = match bytes.first() {
+            Some(&c) => sixbit(c) as usize,
+            None => continue,
+        };
+        let mut decoded = Vec::with_capacity(len + 2);
+        for chunk in bytes[1..].chunks(4) {
+            let c = |i: usize| chunk.get(i).copied().map_or(0, sixbit);
+            decoded.push(c(0) << 2 | c(1) >> 4);
+            decoded.push(c(1) << 4 | c(2) >> 2);
+            decoded.push(c(2) << 6 | c(3));
+        }
+        if decoded.len() < len {
+            return Err("Truncated line in uuencoded data");
+        }
+        data.extend_from_slice(&decoded[..len]);
+    }
+    Err("No end line found")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_char(v: u8) -> char {
+        if v == 0 {
+            '`'
+        } else {
+            (v + b' ') as char
+        }
+    }
+
+    fn encode(filename: &str, data: &[u8]) -> String {
+        let mut text = format!("begin 644 {}\n", filename);
+        for line in data.chunks(45) {
+            text.push(encode_char(line.len() as u8));
+            for group in line.chunks(3) {
+                let b = |i: usize| group.get(i).copied().unwrap_or(0);
+                text.push(encode_char(b(0) >> 2));
+                text.push(encode_char((b(0) << 4 | b(1) >> 4) & 0x3f));
+                text.push(encode_char((b(1) << 2 | b(2) >> 6) & 0x3f));
+                text.push(encode_char(b(2) & 0x3f));
+            }
+            text.push('\n');
+        }
+        text.push_str("`\nend\n");
+        text
+    }
+
+    #[test]
+    fn decode_text() {
+        let file = decode("Here it is:\r\nbegin 644 cat.txt\r\n#0V%T\r\n`\r\nend\r\n").unwrap();
+        assert_eq!(file.filename, "cat.txt");
+        assert_eq!(file.data, b"Cat");
+    }
+
+    #[test]
+    fn round_trip() {
+        let data: Vec<u8> = (0..=255).chain(0..100).collect();
+        let file = decode(&encode("../bytes.bin", &data)).unwrap();
+        assert_eq!(file.filename, "bytes.bin");
+        assert_eq!(file.data, data);
+    }
+
+    #[test]
+    fn short_final_line() {
+        // the length character announces 9 bytes, but only 3 follow
+        assert_eq!(
+            decode("begin 644 cat.txt\n)0V%T\n`\nend\n").unwrap_err(),
+            "Truncated line in uuencoded data"
+        );
+    }
+
+    #[test]
+    fn missing_end() {
+        assert_eq!(
+            decode("begin 644 cat.txt\n#0V%T\n`\n").unwrap_err(),
+            "No end line found"
+        );
+        assert_eq!(decode("no data here").unwrap_err(), "No begin line found");
+    }
+}
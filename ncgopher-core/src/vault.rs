@@ -0,0 +1,118 @@
+use age::secrecy::Secret;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+
+/// Channel to the thread writing encrypted files in the background, and
+/// the thread
+type Writer = (Sender<(PathBuf, Vec<u8>)>, JoinHandle<()>);
+
+/// Encrypts profile files with a passphrase using age. The key is
+/// derived from the passphrase with scrypt, which deliberately takes
+/// about a second for every file that is encrypted. Files that change
+/// often are therefore written with `write_in_background`.
+pub struct Vault {
+    passphrase: Secret<String>,
+    /// Thread encrypting the files passed to `write_in_background`
+    writer: Mutex<Option<Writer>>,
+}
+
+impl fmt::Debug for Vault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Vault")
+    }
+}
+
+fn age_error<E: ToString>(err: E) -> io::Error {
+    io::Error::other(err.to_string())
+}
+
+impl Vault {
+    pub fn new(passphrase: String) -> Self {
+        Vault {
+            passphrase: Secret::new(passphrase),
+            writer: Mutex::new(None),
+        }
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        let encryptor = age::Encryptor::with_user_passphrase(self.passphrase.clone());
+        let mut encrypted = vec![];
+        let mut writer = encryptor.wrap_output(&mut encrypted).map_err(age_error)?;
+        writer.write_all(plaintext)?;
+        writer.finish()?;
+        Ok(encrypted)
+    }
+
+    /// Decrypts data encrypted with `encrypt`. Fails with
+    /// `ErrorKind::PermissionDenied` if the passphrase is wrong.
+    pub fn decrypt(&self, ciphertext: &[u8]) -> io::Result<Vec<u8>> {
+        let decryptor = match age::Decryptor::new(ciphertext).map_err(age_error)? {
+            age::Decryptor::Passphrase(decryptor) => decryptor,
+            _ => return Err(age_error("not encrypted with a passphrase")),
+        };
+        let mut reader = decryptor
+            .decrypt(&self.passphrase, None)
+            .map_err(|err| io::Error::new(io::ErrorKind::PermissionDenied, err.to_string()))?;
+        let mut decrypted = vec![];
+        reader.read_to_end(&mut decrypted)?;
+        Ok(decrypted)
+    }
+
+    /// Reads and decrypts a file
+    pub fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        info!("Decrypting {:?}", path);
+        self.decrypt(&fs::read(path)?)
+    }
+
+    /// Encrypts data and writes it to a file
+    pub fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        info!("Encrypting {:?}", path);
+        fs::write(path, self.encrypt(data)?)
+    }
+
+    /// Encrypts data and writes it to a file in a background thread.
+    /// If the file changes again before it is written, only the newest
+    /// data is encrypted.
+    pub fn write_in_background(&self, path: PathBuf, data: Vec<u8>) {
+        let mut writer = self.writer.lock().unwrap();
+        let (sender, _) = writer.get_or_insert_with(|| {
+            let vault = Vault {
+                passphrase: self.passphrase.clone(),
+                writer: Mutex::new(None),
+            };
+            let (sender, receiver) = mpsc::channel::<(PathBuf, Vec<u8>)>();
+            let worker = thread::spawn(move || {
+                while let Ok((path, data)) = receiver.recv() {
+                    let mut pending = HashMap::new();
+                    pending.insert(path, data);
+                    pending.extend(receiver.try_iter());
+                    for (path, data) in pending {
+                        vault
+                            .write(&path, &data)
+                            .unwrap_or_else(|err| warn!("Could not write {:?}: {}", path, err));
+                    }
+                }
+            });
+            (sender, worker)
+        });
+        if sender.send((path, data)).is_err() {
+            warn!("Encrypted files can not be written any more");
+        }
+    }
+
+    /// Waits until all files passed to `write_in_background` are written
+    pub fn flush(&self) {
+        if let Some((sender, worker)) = self.writer.lock().unwrap().take() {
+            drop(sender);
+            if worker.join().is_err() {
+                warn!("Writing encrypted files failed");
+            }
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Fetches documents from fixtures recorded with `ncgopher --record`,
+//! without network access.
+//!
+//! The fixtures are sessions with a local gopher server and gemini capsule
+//! (`gopher://localhost/1/` and `gemini://localhost/`), the certificate is
+//! the one the capsule presented.
+
+use ncgopher_core::gophermap::{self, GopherMapEntry, ItemType};
+use ncgopher_core::transport::Replay;
+use ncgopher_core::{gemini, gopher};
+use std::io::{ErrorKind, Read};
+use std::path::PathBuf;
+use url::Url;
+
+fn fixtures() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+fn replay() -> Replay {
+    Replay::new(fixtures())
+}
+
+fn gopher_menu_text() -> String {
+    let url = Url::parse("gopher://localhost/1/").unwrap();
+    String::from_utf8(gopher::fetch(&replay(), &url).unwrap()).unwrap()
+}
+
+fn gopher_menu_entries() -> Vec<GopherMapEntry> {
+    gopher_menu_text()
+        .lines()
+        .take_while(|line| *line != ".")
+        .map(|line| GopherMapEntry::parse(line.to_string()).unwrap())
+        .collect()
+}
+
+/// Returns the certificate the capsule presented and the response
+fn gemini_page() -> (Option<Vec<u8>>, String) {
+    let url = Url::parse("gemini://localhost/").unwrap();
+    let mut certificate = None;
+    let mut verify = |der: Option<&[u8]>| {
+        certificate = der.map(<[u8]>::to_vec);
+        true
+    };
+    let mut response = String::new();
+    gemini::connect(&replay(), &url, None, &mut verify)
+        .unwrap()
+        .read_to_string(&mut response)
+        .unwrap();
+    (certificate, response)
+}
+
+#[test]
+fn gopher_menu() {
+    let entries = gopher_menu_entries();
+
+    assert_eq!(entries.len(), 7);
+    assert_eq!(entries[0].item_type, ItemType::Inline);
+    assert_eq!(entries[0].name, "Welcome to the ncgopher test hole");
+    assert_eq!(entries[2].item_type, ItemType::Dir);
+    assert_eq!(entries[2].name, "Phlog");
+    assert_eq!(entries[2].url.as_str(), "gopher://localhost:70/1/phlog");
+    assert_eq!(entries[3].item_type, ItemType::File);
+    assert_eq!(entries[3].selector, "/about.txt");
+    assert_eq!(entries[4].item_type, ItemType::IndexServer);
+    assert_eq!(entries[5].item_type, ItemType::Html);
+    assert_eq!(
+        entries[5].url.as_str(),
+        "https://github.com/jansc/ncgopher"
+    );
+    assert!(entries[6].item_type.is_download());
+}
+
+#[test]
+fn gopher_menu_renders() {
+    assert!(gophermap::lint(&gopher_menu_text()).is_empty());
+    let rendered: Vec<String> = gopher_menu_entries()
+        .iter()
+        .map(|entry| {
+            let label = ItemType::as_str(entry.item_type);
+            entry.format("{type}  {title}", &label, &entry.name)
+        })
+        .collect();
+    assert_eq!(
+        rendered,
+        [
+            "       Welcome to the ncgopher test hole",
+            "       ",
+            "[MAP]  Phlog",
+            "[TXT]  About this server",
+            "[QRY]  Search the phlog",
+            "[HTM]  Project page",
+            "[BIN]  Release tarball",
+        ]
+    );
+}
+
+#[test]
+fn gopher_missing_fixture() {
+    let url = Url::parse("gopher://localhost/0/missing.txt").unwrap();
+    let err = gopher::fetch(&replay(), &url).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
+
+#[test]
+fn gemini_page_certificate() {
+    let (certificate, response) = gemini_page();
+
+    let recorded =
+        std::fs::read(fixtures().join("localhost-1965-5ef94e9a14d197e8.der")).unwrap();
+    assert_eq!(certificate, Some(recorded));
+    let (header, body) = response.split_once("\r\n").unwrap();
+    assert_eq!(header, "20 text/gemini");
+    assert!(gemini::looks_like_gemtext(body));
+}
+
+#[test]
+fn gemini_page_renders() {
+    let (_, response) = gemini_page();
+    let (_, body) = response.split_once("\r\n").unwrap();
+
+    assert_eq!(gemini::preformatted_alt_texts(body), ["ascii art"]);
+    assert_eq!(
+        gemini::plain_text(body),
+        "ncgopher test capsule\n\n\
+         A small capsule used to record fixtures.\n\n\n\
+         Gemlog\n\n\
+         Gopher hole\n\n\
+         Features\n\n\
+         Gophermaps\n\n\
+         Gemtext\n\n\
+         quoted text\n"
+    );
+}
+
+#[test]
+fn gemini_rejected_certificate() {
+    let url = Url::parse("gemini://localhost/").unwrap();
+    let result = gemini::connect(&replay(), &url, None, &mut |_| false);
+    assert_eq!(
+        result.err().map(|e| e.kind()),
+        Some(ErrorKind::PermissionDenied)
+    );
+}
@@ -0,0 +1,163 @@
+use cursive::theme::{BaseColor, Color, ColorStyle, ColorType, Effect, Style};
+use cursive::utils::markup::StyledString;
+use url::Url;
+
+/// Colors and effects set by SGR sequences (ECMA-48 "Select Graphic
+/// Rendition")
+#[derive(Clone, Copy, Default, PartialEq)]
+struct Attributes {
+    foreground: Option<Color>,
+    background: Option<Color>,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    reverse: bool,
+}
+
+impl Attributes {
+    fn style(&self) -> Style {
+        let mut style = Style::none();
+        for (set, effect) in [
+            (self.bold, Effect::Bold),
+            (self.italic, Effect::Italic),
+            (self.underline, Effect::Underline),
+            (self.reverse, Effect::Reverse),
+        ] {
+            if set {
+                style.effects.insert(effect);
+            }
+        }
+        let color = |c: Option<Color>| c.map(ColorType::Color).unwrap_or(ColorType::InheritParent);
+        style.color = ColorStyle::new(color(self.foreground), color(self.background));
+        style
+    }
+
+    /// Applies the parameters of an SGR sequence
+    fn apply(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            *self = Attributes::default();
+            return;
+        }
+        let mut params = params.iter().copied();
+        while let Some(param) = params.next() {
+            match param {
+                0 => *self = Attributes::default(),
+                1 => self.bold = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                7 => self.reverse = true,
+                22 => self.bold = false,
+                23 => self.italic = false,
+                24 => self.underline = false,
+                27 => self.reverse = false,
+                30..=37 => self.foreground = Some(Color::Dark(base_color(param - 30))),
+                39 => self.foreground = None,
+                40..=47 => self.background = Some(Color::Dark(base_color(param - 40))),
+                49 => self.background = None,
+                90..=97 => self.foreground = Some(Color::Light(base_color(param - 90))),
+                100..=107 => self.background = Some(Color::Light(base_color(param - 100))),
+                38 | 48 => {
+                    let color = match params.next() {
+                        Some(5) => params.next().map(|n| Color::from_256colors(n as u8)),
+                        Some(2) => {
+                            let mut rgb = params.by_ref().take(3).map(|c| c as u8);
+                            match (rgb.next(), rgb.next(), rgb.next()) {
+                                (Some(r), Some(g), Some(b)) => Some(Color::Rgb(r, g, b)),
+                                _ => None,
+                            }
+                        }
+                        _ => None,
+                    };
+                    if param == 38 {
+                        self.foreground = color;
+                    } else {
+                        self.background = color;
+                    }
+                }
+                // blinking, fonts, ...
+                _ => (),
+            }
+        }
+    }
+}
+
+fn base_color(n: u16) -> BaseColor {
+    BaseColor::from(n as u8)
+}
+
+/// Returns whether text contains ANSI escape sequences
+pub fn has_escapes(text: &str) -> bool {
+    text.contains("\x1b[")
+}
+
+/// Renders a line with ANSI escape sequences. Colors and effects are
+/// converted to styles, moving the cursor forward is converted to
+/// spaces, and all other sequences are dropped so that they cannot
+/// mess up the terminal. The attributes at the end of the line are
+/// kept for the next line.
+fn parse_line(line: &str, attributes: &mut Attributes) -> StyledString {
+    let mut styled = StyledString::new();
+    let mut text = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                // CSI: parameters and intermediate bytes, then a final byte
+                Some('[') => {
+                    let mut sequence = String::new();
+                    let mut last = None;
+                    for c in chars.by_ref() {
+                        if ('\x40'..='\x7e').contains(&c) {
+                            last = Some(c);
+                            break;
+                        }
+                        sequence.push(c);
+                    }
+                    let params: Vec<u16> = sequence
+                        .split(';')
+                        .filter(|p| !p.is_empty())
+                        .map(|p| p.parse().unwrap_or(0))
+                        .collect();
+                    match last {
+                        Some('m') => {
+                            let mut next = *attributes;
+                            next.apply(&params);
+                            if next != *attributes {
+                                styled.append_styled(std::mem::take(&mut text), attributes.style());
+                                *attributes = next;
+                            }
+                        }
+                        Some('C') => {
+                            let n = params.first().copied().unwrap_or(1).max(1) as usize;
+                            text.push_str(&" ".repeat(n));
+                        }
+                        _ => (),
+                    }
+                }
+                // OSC: terminated by BEL or ST
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                _ => (),
+            },
+            '\t' => text.push_str("        "),
+            c if c.is_control() => (),
+            c => text.push(c),
+        }
+    }
+    styled.append_styled(text, attributes.style());
+    styled
+}
+
+/// Renders text with ANSI escape sequences for the gemini view. Lines
+/// are not wrapped, as they are usually ANSI art.
+pub fn parse(text: &str) -> Vec<(StyledString, Option<Url>)> {
+    let mut attributes = Attributes::default();
+    text.split('\n')
+        .map(|line| (parse_line(line, &mut attributes), None))
+        .collect()
+}
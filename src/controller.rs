@@ -1,38 +1,48 @@
-use ::time::{OffsetDateTime};
-use ::time::format_description::well_known::Rfc3339;
+use ::time::{format_description, OffsetDateTime};
 use base64::{Engine as _, engine::{general_purpose}};
 use cursive::{
-    theme::ColorStyle,
+    theme::{ColorStyle, Effect, Style},
     utils::{lines::simple::LinesIterator, markup::StyledString},
-    view::{Nameable, Resizable},
-    views::{Dialog, EditView, NamedView, ResizedView, ScrollView, SelectView},
-    Cursive, CursiveRunnable,
+    view::{Nameable, Resizable, Scrollable},
+    views::{Dialog, EditView, NamedView, ResizedView, ScrollView, SelectView, TextView},
+    CbSink, Cursive, CursiveRunnable,
 };
-use linkify::{LinkFinder, LinkKind};
 use mime::Mime;
-use native_tls::{Identity, Protocol, TlsConnector};
+use native_tls::Identity;
+use std::collections::{HashSet, VecDeque};
 use std::error::Error;
 use std::fs::OpenOptions;
 use std::io::{BufRead, BufReader, BufWriter, Read, Write};
-use std::net::TcpStream;
 use std::path::Path;
-use std::process::Command;
 use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
+use std::time::{Duration, Instant};
+use unicode_width::UnicodeWidthStr;
 use url::{Position, Url};
-use urlencoding::decode_binary;
 use x509_parser::prelude::*;
 use sha2::{Digest, Sha256};
 
 use crate::bookmarks::{Bookmark, Bookmarks};
 use crate::certificates::Certificates;
 use crate::clientcertificates::{ClientCertificate, ClientCertificates};
+use crate::downloads::{Download, DownloadState};
+use crate::external;
+use crate::{cso, finger, gopher, uuencode};
 use crate::gemini::GeminiType;
 use crate::gophermap::{GopherMapEntry, ItemType};
 use crate::history::{History, HistoryEntry};
+use crate::transport::{
+    CancelToken, Cancellable, CancellableReader, RateLimiter, ThrottledReader, Transport,
+};
+use crate::tts::Reader;
+use crate::notifications::{notify, Event};
+use crate::session::{Session, SessionPage};
 use crate::ui::layout::Layout;
 use crate::ui::setup::move_to_next_item;
-use crate::url_tools::{download_filename_from_url, human_readable_url, normalize_domain};
+use crate::url_tools::{
+    breadcrumb, download_filename_from_url, human_readable_url, normalize_domain,
+};
+use crate::vault::Vault;
 use crate::SETTINGS;
 
 #[derive(Clone, Debug)]
@@ -41,11 +51,320 @@ pub enum Direction {
     Previous,
 }
 
+/// How the current content was rendered, so that it can be rendered again
+/// when the terminal is resized
+#[derive(Clone, Debug)]
+enum Rendering {
+    Gopher(ItemType),
+    Gemini {
+        url: Url,
+        gemini_type: GeminiType,
+        cert_fingerprint: Option<String>,
+    },
+}
+
+/// Page shown before the page being loaded
+#[derive(Clone)]
+struct PreviousPage {
+    url: Url,
+    /// Whether the page being loaded was added to the history
+    added: bool,
+    content: String,
+    rendering: Option<Rendering>,
+}
+
+/// A request for a page. Dropping it, when the thread loading the page
+/// ends, marks loading as finished once everything the thread sent to
+/// the user interface has been shown.
+struct PageRequest {
+    id: i64,
+    sender: CbSink,
+}
+
+impl Drop for PageRequest {
+    fn drop(&mut self) {
+        let id = self.id;
+        // fails if the user interface has quit already
+        let _ = self.sender.send(Box::new(move |app| {
+            let controller = app.user_data::<Controller>().expect("controller missing");
+            let mut loading = controller.loading.lock().unwrap();
+            if *loading == Some(id) {
+                *loading = None;
+                controller.previous_page = None;
+            }
+        }));
+    }
+}
+
 const HISTORY_LEN: usize = 10;
+/// Number of menu items in the history menu before the history entries
+pub(crate) const HISTORY_MENU_ITEMS: usize = 4;
+/// Number of menu items in the bookmarks menu before the bookmarks
+pub(crate) const BOOKMARK_MENU_ITEMS: usize = 9;
+
+/// How often a page that is still loading is shown again with the
+/// content received so far
+const STREAM_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often the number of bytes received is shown while loading
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How often the encrypted history is saved while browsing
+const HISTORY_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Number of bytes of a text file shown in the preview pane
+const PREVIEW_SIZE: u64 = 64 * 1024;
+
+/// Frames of the spinner shown while loading
+const SPINNER: &[char] = &['|', '/', '-', '\\'];
+
+/// Passes data through and shows the number of bytes received so far in
+/// the statusbar
+struct ProgressReader<R> {
+    inner: R,
+    sender: CbSink,
+    received: usize,
+    last_update: Instant,
+    frame: usize,
+}
+
+impl<R: Read> ProgressReader<R> {
+    fn new(
+        inner: R,
+        sender: CbSink,
+    ) -> Self {
+        ProgressReader {
+            inner,
+            sender,
+            received: 0,
+            last_update: Instant::now(),
+            frame: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.received += n;
+        if n > 0 && self.last_update.elapsed() >= PROGRESS_INTERVAL {
+            self.last_update = Instant::now();
+            self.frame = (self.frame + 1) % SPINNER.len();
+            let message = format!(
+                "Loading ... {} {}",
+                human_readable_size(self.received),
+                SPINNER[self.frame]
+            );
+            self.sender
+                .send(Box::new(move |app| {
+                    app.user_data::<Controller>()
+                        .expect("controller missing")
+                        .show_progress(&message);
+                }))
+                .unwrap();
+        }
+        Ok(n)
+    }
+}
+
+/// Details of a server certificate shown when it is unknown or has changed
+pub(crate) struct CertificateInfo {
+    /// Host and port of the server
+    pub server: String,
+    /// Base64 encoded SHA-256 fingerprint
+    pub fingerprint: String,
+    pub subject: String,
+    pub issuer: String,
+    /// Expiration date, empty if the certificate could not be parsed
+    pub expires: String,
+    pub expired: bool,
+}
+
+impl CertificateInfo {
+    fn new(server: String, fingerprint: String, der: &[u8]) -> Self {
+        let mut info = CertificateInfo {
+            server,
+            fingerprint,
+            subject: String::new(),
+            issuer: String::new(),
+            expires: String::new(),
+            expired: false,
+        };
+        match parse_x509_certificate(der) {
+            Ok((_rem, cert)) => {
+                info.subject = cert.subject().to_string();
+                info.issuer = cert.issuer().to_string();
+                let format = format_description::parse_borrowed::<1>("[year]-[month]-[day]")
+                    .expect("Could not parse date format");
+                info.expires = cert
+                    .validity()
+                    .not_after
+                    .to_datetime()
+                    .format(&format)
+                    .unwrap_or_default();
+                info.expired = cert.validity().time_to_expiration().is_none();
+            }
+            Err(err) => warn!("Could not parse peer certificate: {:?}", err),
+        }
+        info
+    }
+}
+
+/// Returns the page listing the bound keys
+fn keys_page() -> String {
+    let bindings = crate::ui::setup::key_bindings();
+    let width = bindings.iter().map(|(key, _)| key.width()).max().unwrap_or_default();
+    let mut page = String::from("# Key bindings\n\n```\n");
+    for (key, command) in bindings {
+        page.push_str(&format!("{:<width$}  {}\n", key, command, width = width));
+    }
+    page.push_str("```\n\n");
+    page.push_str("Press Esc to open the menu, which lists more commands.\n\n");
+    page.push_str("=> about:help More help\n");
+    page
+}
+
+/// Formats a number of bytes, e.g. "48 KB"
+pub(crate) fn human_readable_size(bytes: usize) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1048575 => format!("{} KB", bytes / 1024),
+        _ => format!("{:.1} MB", bytes as f64 / 1048576.0),
+    }
+}
+
+/// Number of status messages kept in the message history
+const MESSAGE_HISTORY_SIZE: usize = 200;
+
+/// Time within which digits typed form one link number
+const LINK_NUMBER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// File extensions of binary items that are played in the media player
+const MEDIA_EXTENSIONS: &[&str] = &[
+    "mp3", "ogg", "oga", "opus", "flac", "wav", "m4a", "mp4", "m4v", "mkv", "webm", "ogv", "avi",
+    "mov",
+];
+
+/// Returns whether a gopher item is sound or video
+fn is_media(item_type: ItemType, url: &Url) -> bool {
+    match item_type {
+        ItemType::Sound | ItemType::Video => true,
+        ItemType::Binary => url
+            .path()
+            .rsplit_once('.')
+            .is_some_and(|(_, ext)| MEDIA_EXTENSIONS.contains(&ext.to_lowercase().as_str())),
+        _ => false,
+    }
+}
+
+/// Returns the effects telling gophermap items apart when their type
+/// labels are hidden
+fn type_style(item_type: ItemType) -> Style {
+    match item_type {
+        ItemType::Dir => Effect::Bold.into(),
+        ItemType::IndexServer | ItemType::CsoServer => Effect::Underline.into(),
+        ItemType::Html | ItemType::Telnet | ItemType::Tn3270 => {
+            Style::from(Effect::Italic).combine(Effect::Underline)
+        }
+        ItemType::Error => Effect::Reverse.into(),
+        t if t.is_download() => Effect::Italic.into(),
+        _ => Style::none(),
+    }
+}
+
+/// Strips the extra period of lines beginning with a period.
+/// Issue #210: Note: Lines beginning with periods must be prepended with
+/// an extra period to ensure that the transmission is not terminated
+/// early.
+fn without_dots(content: &str) -> String {
+    content
+        .lines()
+        .map(|line| line.strip_prefix('.').unwrap_or(line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Highlights the matches of a search in the rows of a view and returns
+/// the indices of the matching rows. `originals` holds the rows without
+/// highlighting; it is filled on the first search of a page.
+fn highlight_matches<T: 'static>(
+    view: &mut SelectView<T>,
+    originals: &mut Vec<StyledString>,
+    needle: &str,
+) -> Vec<usize> {
+    let same_page = originals.len() == view.len()
+        && view
+            .iter()
+            .zip(originals.iter())
+            .all(|((label, _), original)| label == original.source());
+    if !same_page {
+        *originals = view.try_iter_mut().map(|(label, _)| label.clone()).collect();
+    }
+    let mut hits = Vec::new();
+    for (index, ((label, _), original)) in view.try_iter_mut().zip(originals.iter()).enumerate() {
+        let source = original.source();
+        if needle.is_empty() || !source.contains(needle) {
+            *label = original.clone();
+            continue;
+        }
+        hits.push(index);
+        let matches: Vec<(usize, usize)> = source
+            .match_indices(needle)
+            .map(|(start, m)| (start, start + m.len()))
+            .collect();
+        let mut highlighted = StyledString::new();
+        let mut position = 0;
+        for span in original.spans() {
+            let end = position + span.content.len();
+            // split the span where matches start or end
+            while position < end {
+                let current = matches.iter().find(|(s, e)| *s <= position && position < *e);
+                let part_end = match current {
+                    Some((_, e)) => (*e).min(end),
+                    None => matches
+                        .iter()
+                        .map(|(s, _)| *s)
+                        .filter(|s| *s > position)
+                        .min()
+                        .unwrap_or(end)
+                        .min(end),
+                };
+                let style = match current {
+                    Some(_) => span.attr.combine(ColorStyle::highlight()),
+                    None => *span.attr,
+                };
+                highlighted.append_styled(&source[position..part_end], style);
+                position = part_end;
+            }
+        }
+        *label = highlighted;
+    }
+    hits
+}
+
+/// Builds a gemtext page listing a chain of redirects that was not
+/// followed automatically, with a link to continue manually.
+fn redirect_chain_page(reason: &str, chain: &[Url], next: &Url) -> String {
+    let mut page = format!(
+        "# {}\n\nncgopher stopped following redirects after {} step(s). \
+         These are the URLs that were visited:\n\n",
+        reason,
+        chain.len()
+    );
+    for (i, url) in chain.iter().enumerate() {
+        page.push_str(&format!("=> {} {}. {}\n", url, i + 1, human_readable_url(url)));
+    }
+    page.push_str(&format!(
+        "\nThe last response redirected to the following URL. \
+         Select it if you want to continue manually:\n\n=> {}\n",
+        next
+    ));
+    page
+}
 
 #[derive(Clone)]
 pub struct Controller {
-    sender: crossbeam_channel::Sender<Box<dyn FnOnce(&mut Cursive) + 'static + Send>>,
+    sender: CbSink,
     /// The browsing history
     pub(crate) history: Arc<Mutex<History>>,
     /// Bookmarks
@@ -54,46 +373,140 @@ pub struct Controller {
     pub(crate) client_certificates: Arc<Mutex<ClientCertificates>>,
     /// Known hosts for gemini TOFU
     certificates: Arc<Mutex<Certificates>>,
+    /// Servers and the fingerprints of certificates accepted once, until quit
+    pub(crate) accepted_certificates: Arc<Mutex<HashSet<(String, String)>>>,
     /// Current textual content
-    content: Arc<Mutex<String>>,
+    pub(crate) content: Arc<Mutex<String>>,
+    /// Downloads of binary files, active and completed
+    pub(crate) downloads: Arc<Mutex<Vec<Download>>>,
     /// Current URL
     pub current_url: Arc<Mutex<Url>>,
     /// When the user triggers several requests, only the last request
     /// will be displayed, the other will be canceled.
     last_request_id: Arc<Mutex<i64>>,
-    /// Number of redirects in gemini protocol
-    redirect_count: Arc<Mutex<i32>>,
+    /// Aborts the transfer of the page being loaded
+    cancel_token: Arc<Mutex<CancelToken>>,
+    /// Aborts the transfer of the file shown in the preview pane
+    preview_token: Arc<Mutex<CancelToken>>,
+    /// Id of the request of the page being loaded, if any
+    loading: Arc<Mutex<Option<i64>>>,
+    /// Page shown before the page being loaded, to go back to when
+    /// loading is cancelled
+    previous_page: Option<PreviousPage>,
+    /// URLs visited while following the current chain of gemini redirects
+    redirect_chain: Arc<Mutex<Vec<Url>>>,
     /// Message shown in statusbar
     message: Arc<RwLock<String>>,
+    /// Recent status messages with the time they were shown
+    pub(crate) message_history: Arc<Mutex<VecDeque<(OffsetDateTime, String)>>>,
     // Current search string
     current_search: String,
     // Current search results
     pub current_search_results: Vec<usize>,
+    /// Rows of the current page without search highlighting
+    search_originals: Vec<StyledString>,
+    /// Digits of a link number being typed and when the last one was
+    /// typed
+    typed_link_number: Option<(String, Instant)>,
+    /// Network access for gopher, gemini and finger
+    transport: Arc<dyn Transport>,
+    /// Bandwidth limit shared by the downloads of binary files
+    download_limiter: Arc<RateLimiter>,
+    /// Indices of the gophermap entries marked for bulk actions
+    pub(crate) marked: Vec<usize>,
+    /// Reads the current page aloud
+    reader: Option<Reader>,
+    /// How the current content was rendered
+    rendering: Option<Rendering>,
+    /// Terminal width the current content was wrapped for
+    rendered_width: usize,
+    /// Encrypts the profile, including the session saved on quitting
+    vault: Option<Arc<Vault>>,
+}
+
+/// Downloads the bookmarks of another computer, merges them and uploads
+/// the result. Returns the number of bookmarks that changed here.
+fn sync_bookmarks_with(
+    bookmarks: &Mutex<Bookmarks>,
+    pull: &str,
+    push: &str,
+) -> std::io::Result<usize> {
+    let failed = |command: &str, status| {
+        std::io::Error::other(format!("{} exited with {}", command, status))
+    };
+    let path = external::temp_dir().join("bookmarks.sync");
+    std::fs::remove_file(&path).ok();
+    let status = external::run(pull, &path.display().to_string())?;
+    let pulled = status.success() && path.exists();
+    // the first sync may find nothing to download
+    if !pulled && bookmarks.lock().unwrap().has_sync_base() {
+        return Err(failed(pull, status));
+    }
+    let (changes, data) = {
+        let mut bookmarks = bookmarks.lock().unwrap();
+        let changes = bookmarks.merge_sync_file(Some(path.as_path()).filter(|_| pulled))?;
+        (changes, bookmarks.sync_data()?)
+    };
+    std::fs::write(&path, &data)?;
+    let status = external::run(push, &path.display().to_string())?;
+    std::fs::remove_file(&path).ok();
+    if !status.success() {
+        return Err(failed(push, status));
+    }
+    bookmarks.lock().unwrap().save_sync_base(&data)?;
+    Ok(changes)
 }
 
 impl Controller {
     /// Create a new controller (created in main.rs)
-    pub fn setup(app: &mut CursiveRunnable, url: Url) -> Result<(), Box<dyn Error>> {
+    pub fn setup(
+        app: &mut CursiveRunnable,
+        url: Url,
+        transport: Arc<dyn Transport>,
+        vault: Option<Arc<Vault>>,
+    ) -> Result<(), Box<dyn Error>> {
         crate::ui::setup::setup(app);
 
         let mut controller = Controller {
             sender: app.cb_sink().clone(),
-            history: Arc::new(Mutex::new(History::new()?)),
-            bookmarks: Arc::new(Mutex::new(Bookmarks::new())),
+            history: Arc::new(Mutex::new(History::new(vault.clone())?)),
+            bookmarks: Arc::new(Mutex::new(Bookmarks::new(vault.clone())?)),
             client_certificates: Arc::new(Mutex::new(ClientCertificates::new())),
             certificates: Arc::new(Mutex::new(Certificates::new())),
+            accepted_certificates: Arc::new(Mutex::new(HashSet::new())),
             content: Arc::new(Mutex::new(String::new())),
+            downloads: Arc::new(Mutex::new(Vec::new())),
             current_url: Arc::new(Mutex::new(Url::parse("about:blank").unwrap())),
             last_request_id: Arc::new(Mutex::new(0)),
-            redirect_count: Arc::new(Mutex::new(0)),
+            cancel_token: Arc::new(Mutex::new(CancelToken::new())),
+            preview_token: Arc::new(Mutex::new(CancelToken::new())),
+            loading: Arc::new(Mutex::new(None)),
+            previous_page: None,
+            redirect_chain: Arc::new(Mutex::new(Vec::new())),
             message: app
                 .find_name::<crate::ui::statusbar::StatusBar>("statusbar")
                 .unwrap()
                 .get_message(),
+            message_history: Arc::new(Mutex::new(VecDeque::new())),
             current_search: String::new(),
             current_search_results: Vec::new(),
+            search_originals: Vec::new(),
+            typed_link_number: None,
+            transport,
+            download_limiter: Arc::new(RateLimiter::new(|| {
+                SETTINGS.read().unwrap().config.download_rate_limit * 1024
+            })),
+            marked: Vec::new(),
+            reader: None,
+            rendering: None,
+            rendered_width: 0,
+            vault,
         };
 
+        app.find_name::<crate::ui::statusbar::StatusBar>("statusbar")
+            .unwrap()
+            .share(controller.current_url.clone(), controller.downloads.clone());
+
         let mut entries = controller
             .history
             .lock()
@@ -103,9 +516,14 @@ impl Controller {
         entries.reverse();
         crate::ui::setup::setup_history_menu(app, &entries);
 
-        let mut entries = controller.bookmarks.lock().unwrap().get_bookmarks();
-        entries.reverse();
+        let entries = controller.bookmarks.lock().unwrap().get_bookmarks();
         crate::ui::setup::setup_bookmark_menu(app, &entries);
+        if !SETTINGS.read().unwrap().config.bookmark_sync_pull.is_empty() {
+            controller.sync_bookmarks();
+        }
+        if controller.vault.is_some() {
+            controller.save_history_periodically();
+        }
 
         // open initial page
         controller.open_url(url, true, 0);
@@ -122,28 +540,24 @@ impl Controller {
             trace!("Controller::fetch_gemini_url({})", url);
         };
 
-        let request_id = {
-            let mut guard = self.last_request_id.lock().unwrap();
-            *guard += 1;
-            *guard
-        };
+        let request = self.start_request();
+        let request_id = request.id;
         let request_id_ref = self.last_request_id.clone();
-        let redirect_count = self.redirect_count.clone();
+        let redirect_chain = self.redirect_chain.clone();
+        let download_limiter = self.download_limiter.clone();
+        let token = self.new_cancel_token();
+        let transport = Cancellable::new(self.transport.clone(), token.clone());
 
         normalize_domain(&mut url);
 
         let host = url.host_str().unwrap().to_string();
         // can only be a gemini URL, no need to check the scheme
-        let server_details = match url.socket_addrs(|| Some(1965)) {
-            Ok(sock_addrs) => sock_addrs[0],
-            Err(err) => {
-                self.set_message(&format!("invalid URL: {}", err));
-                return;
-            }
-        };
+        let port = url.port().unwrap_or(1965);
+        let server_details = format!("{}:{}", host, port);
 
         // Get known certificate fingerprint for host
         let fingerprint = self.certificates.lock().unwrap().get(&url);
+        let accepted_certificates = self.accepted_certificates.clone();
         let sender = self.sender.clone();
 
         // Check if a client certificate exists for this host.
@@ -219,193 +633,127 @@ impl Controller {
         }
 
         thread::spawn(move || {
+            let _request = request;
             let mut buf = String::new();
-            let mut builder = TlsConnector::builder();
-
-            // Self-signed certificates are considered invalid, but they are quite
-            // common for gemini servers. Therefore, we accept invalid certs,
-            // but check for expiration later
-            builder.danger_accept_invalid_certs(true);
-
-            // Rust's native-tls does not yet provide Tlsv13 :(
-            // Alternative implementation: rusttls
-            builder.min_protocol_version(Some(Protocol::Tlsv12));
-
-            if let Some(id) = identity {
-                info!("Using identity for request");
-                builder.identity(id);
-            }
-
-            let connector = match builder.build() {
-                Ok(connector) => connector,
-                Err(err) => {
-                    sender
-                        .send(Box::new(move |app| {
-                            let controller =
-                                app.user_data::<Controller>().expect("controller missing");
-                            controller
-                                .set_message(&format!("Could not establish connection: {}", err));
-                        }))
-                        .unwrap();
-                    return;
-                }
-            };
-
-            let stream = match TcpStream::connect(&server_details) {
-                Ok(stream) => stream,
-                Err(err) => {
-                    sender
-                        .send(Box::new(move |app| {
-                            let controller =
-                                app.user_data::<Controller>().expect("controller missing");
-                            controller
-                                .set_message(&format!("Could not connect to server: {}", err));
-                        }))
-                        .unwrap();
-                    return;
-                }
-            };
-
-            let mut stream = match connector.connect(&host, stream) {
-                Ok(stream) => stream,
-                Err(err) => {
-                    warn!("Could not open tls stream: {} to {}", err, server_details);
-                    sender
-                        .send(Box::new(move |app| {
-                            let controller =
-                                app.user_data::<Controller>().expect("controller missing");
-                            controller.set_message(&format!(
-                                "Could not open tls stream to {}: {}",
-                                server_details, err
-                            ));
-                        }))
-                        .unwrap();
-                    return;
-                }
-            };
-
-            info!("Connected with TLS");
-
-            // try to get peer certificate
-            let cert_opt = match stream.peer_certificate() {
-                Ok(cert_opt) => cert_opt,
-                Err(err) => {
-                    sender
-                        .send(Box::new(move |app| {
-                            let controller =
-                                app.user_data::<Controller>().expect("controller missing");
-                            controller
-                                .set_message(&format!("Could not get peer certificate: {:?}", err));
-                        }))
-                        .unwrap();
-                    return;
-                }
-            };
+            let mut rejected = false;
 
             // check certificate
-            if let Some(cert) = cert_opt {
-                // TOFU: Check if we already have a certificate fingerprint for a given host
-                // create a Sha256 object
-                let cert_fingerprint = cert.to_der().unwrap();
+            let mut verify = |der: Option<&[u8]>| -> bool {
+                let der = match der {
+                    Some(der) => der,
+                    None => return true,
+                };
                 let mut hasher = Sha256::new();
-                hasher.update(cert_fingerprint);
-                let cert_fingerprint = base64::encode(hasher.finalize());
+                hasher.update(der);
+                let cert_fingerprint = general_purpose::STANDARD.encode(hasher.finalize());
+                let info = CertificateInfo::new(server_details.clone(), cert_fingerprint, der);
+                let expired = info.expired;
 
-                match fingerprint {
-                    Some(f) => {
-                        if f != cert_fingerprint {
-                            sender
-                                .send(Box::new(move |app| {
-                                    // Invalid certificate, notify user
-                                    let controller =
-                                        app.user_data::<Controller>().expect("controller missing");
-                                    controller.set_message(&format!(
-                                        "Certificate fingerprint DOES NOT match for {}",
-                                        url
-                                    ));
-                                    crate::ui::dialogs::certificate_changed(
-                                        app,
-                                        url,
-                                        cert_fingerprint,
-                                    );
-                                }))
-                                .unwrap();
-                            return;
-                        } else {
-                            let targeturl = url.clone();
-                            sender
-                                .send(Box::new(move |app| {
-                                    let controller =
-                                        app.user_data::<Controller>().expect("controller missing");
-                                    controller.set_message(&format!(
-                                        "Certificate fingerprint matches for {}",
-                                        targeturl
-                                    ));
-                                }))
-                                .unwrap();
-                        }
+                // TOFU: Check if we already have a certificate fingerprint for a given host
+                let accepted = accepted_certificates
+                    .lock()
+                    .unwrap()
+                    .contains(&(server_details.clone(), info.fingerprint.clone()));
+                match &fingerprint {
+                    Some(f) if *f == info.fingerprint => {
+                        let targeturl = url.clone();
+                        sender
+                            .send(Box::new(move |app| {
+                                let controller =
+                                    app.user_data::<Controller>().expect("controller missing");
+                                controller.set_message(&format!(
+                                    "Certificate fingerprint matches for {}",
+                                    targeturl
+                                ));
+                            }))
+                            .unwrap();
                     }
-                    None => {
+                    // accepted once in this session
+                    _ if accepted => (),
+                    None if SETTINGS.read().unwrap().config.trust_on_first_use => {
                         // 1st time visit: add fingerprint
                         let url = url.clone();
                         sender
                             .send(Box::new(move |app| {
-                                Controller::certificate_changed_action(app, &url, cert_fingerprint);
+                                Controller::certificate_changed_action(
+                                    app,
+                                    &url,
+                                    info.fingerprint,
+                                );
                             }))
                             .unwrap();
                     }
-                }
-
-                // Check certificate expiration date
-                match parse_x509_certificate(&cert.to_der().unwrap()) {
-                    Ok((_rem, cert)) => {
-                        info!("Successfully parsed certificate");
-                        match cert.tbs_certificate.validity.time_to_expiration() {
-                            Some(duration) => {
-                                let now: OffsetDateTime = OffsetDateTime::now_utc();
-                                let expires = now + duration;
-                                let expires: OffsetDateTime = expires.into();
-                                info!("Certificate expires {}", expires.format(&Rfc3339).unwrap());
-                                info!("Certificate valid {:?}", duration);
-                            }
-                            None => {
-                                sender
-                                    .send(Box::new(move |app| {
-                                        let controller = app
-                                            .user_data::<Controller>()
-                                            .expect("controller missing");
-                                        controller.set_message("Server certificate expired.");
-                                    }))
-                                    .unwrap();
-                            }
-                        }
-                    }
-                    Err(err) => {
+                    known => {
+                        let changed = known.is_some();
+                        let url = url.clone();
                         sender
                             .send(Box::new(move |app| {
                                 let controller =
                                     app.user_data::<Controller>().expect("controller missing");
                                 controller.set_message(&format!(
-                                    "Could not parse peer certificate: {:?}",
-                                    err
+                                    "{} for {}",
+                                    match changed {
+                                        true => "Certificate fingerprint DOES NOT match",
+                                        false => "Unknown certificate",
+                                    },
+                                    url
                                 ));
+                                crate::ui::dialogs::certificate(app, url, info, changed);
                             }))
                             .unwrap();
+                        rejected = true;
+                        return false;
                     }
                 }
-            }
+                // sent last, so that the TOFU messages do not replace it
+                if expired {
+                    sender
+                        .send(Box::new(move |app| {
+                            let controller =
+                                app.user_data::<Controller>().expect("controller missing");
+                            controller.set_message("Server certificate expired.");
+                        }))
+                        .unwrap();
+                }
+                true
+            };
 
-            // Handshake done, request URL from gemini server
+            // Request URL from gemini server
             if !SETTINGS.read().unwrap().config.disable_history {
                 info!("Writing url '{}'", url.as_str());
             }
-            stream.write_all(format!("{}\r\n", url).as_bytes()).unwrap();
+            let stream =
+                ncgopher_core::gemini::connect(&transport, &url, identity.as_ref(), &mut verify);
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) if token.is_cancelled() => return,
+                // the certificate dialog is shown instead
+                Err(_) if rejected => return,
+                Err(err) => {
+                    warn!("Could not connect to {}: {}", server_details, err);
+                    sender
+                        .send(Box::new(move |app| {
+                            let controller =
+                                app.user_data::<Controller>().expect("controller missing");
+                            let message = format!("Could not connect to server: {}", err);
+                            if !controller.try_next_mirror(&url, index, &message) {
+                                controller.set_message(&message);
+                            }
+                        }))
+                        .unwrap();
+                    return;
+                }
+            };
+
+            info!("Connected with TLS");
 
+            let stream = ProgressReader::new(stream, sender.clone());
             let mut bufr = BufReader::new(stream);
             info!("Reading from gemini stream");
             // Read Gemini Header
             match bufr.read_line(&mut buf) {
                 Ok(_) => (),
+                Err(_) if token.is_cancelled() => return,
                 Err(e) => {
                     sender
                         .send(Box::new(move |app| {
@@ -514,10 +862,9 @@ impl Controller {
             };
 
             let status = buf.chars().next();
-            // Reset redirect count when the status does not indicate a redirect
+            // Reset redirect chain when the status does not indicate a redirect
             if status != Some('3') {
-                let mut guard = redirect_count.lock().unwrap();
-                *guard = 0;
+                redirect_chain.lock().unwrap().clear();
             }
 
             match status {
@@ -550,13 +897,13 @@ impl Controller {
 
                     if mime.type_() == "text" {
                         // some kind of text. First check encoding.
-                        let encoding = mime.get_param("charset")
+                        let encoding = crate::text::encoding_for_host(&host).unwrap_or_else(|| mime.get_param("charset")
                             // default is UTF-8
                             .map_or("utf-8", |param| param.as_str())
                             // charset identifiers are case-insensitive
-                            .to_lowercase();
+                            .to_lowercase());
 
-                        if !matches!(encoding.as_str(),
+                        let utf8 = matches!(encoding.as_str(),
                             // IANA has many aliases for ASCII
                             // https://www.iana.org/assignments/character-sets/character-sets.xhtml
                             // since it's a strict subset of UTF-8 we can read it
@@ -565,18 +912,21 @@ impl Controller {
                             | "iso646-us" | "us" | "IBM367" | "cp367"
                             | "csascii"
                             // UTF-8, also allow a nonstandard spelling
-                            | "utf-8" | "csutf8" | "utf8")
-                        {
-                            // not UTF-8 or ASCII, encoding not supported
+                            | "utf-8" | "csutf8" | "utf8");
+                        if !utf8 && !crate::text::is_known_encoding(&encoding) {
+                            // encoding not supported
                             sender.send(Box::new(move |app| {
                                 app.add_layer(Dialog::info(format!("The page you tried to access is encoded as \"{}\". This encoding is not supported by ncgopher.", encoding)))
                             })).unwrap();
                             return;
                         }
-                        // if we get this far, it has to be UTF-8/ASCII
 
                         let mut buf = vec![];
-                        bufr.read_to_end(&mut buf).unwrap_or_else(|err| {
+                        let result = bufr.read_to_end(&mut buf);
+                        if token.is_cancelled() {
+                            return;
+                        }
+                        result.unwrap_or_else(|err| {
                             sender
                                 .send(Box::new(move |app| {
                                     let controller =
@@ -590,21 +940,37 @@ impl Controller {
                             0
                         });
 
+                        let html = mime.subtype() == mime::HTML;
                         let gemini_type = match mime.subtype().as_str() {
-                            "gemini" => GeminiType::Gemini,
-                            // FIXME: add HTML handler
+                            "gemini" | "html" => GeminiType::Gemini,
+                            "markdown" => GeminiType::Markdown,
+                            _ if crate::markdown::is_markdown(&url) => GeminiType::Markdown,
                             _ => GeminiType::Text,
                         };
 
-                        let s = String::from_utf8_lossy(&buf).into_owned();
+                        let s = if utf8 {
+                            crate::text::decode(&buf)
+                        } else {
+                            crate::text::decode_as(&buf, Some(&encoding))
+                        };
+                        let s = match html {
+                            true => crate::html::to_gemtext(&s, &url),
+                            false => s,
+                        };
                         sender.send(Box::new(move |app|{
                             let controller = app.user_data::<Controller>().expect("controller missing");
                             controller.clear_search();
-                            controller.set_message(url.as_str());
+                            controller.set_loaded_message(&url);
+                            controller.index_page(&url, &s, false);
                             controller.set_gemini_content(url, gemini_type, s, index, client_cert_fingerprint);
                         })).unwrap();
                     } else {
                         // Binary download
+                        let token = CancelToken::new();
+                        let mut bufr = CancellableReader::new(
+                            ThrottledReader::new(bufr, download_limiter),
+                            token.clone(),
+                        );
                         let local_filename = download_filename_from_url(&url);
                         let open = OpenOptions::new()
                             .write(true)
@@ -614,35 +980,59 @@ impl Controller {
 
                         match open {
                             Ok(file) => {
+                                let filename = local_filename.clone();
+                                sender
+                                    .send(Box::new(move |app| {
+                                        app.user_data::<Controller>()
+                                            .expect("controller missing")
+                                            .download_started(filename, token);
+                                    }))
+                                    .unwrap();
                                 let mut bw = BufWriter::new(file);
                                 let mut buf = [0u8; 1024];
                                 let mut total_written = 0;
-                                loop {
-                                    let bytes_read = bufr
-                                        .read(&mut buf)
-                                        .expect("Could not read from TCP");
-                                    if bytes_read == 0 {
-                                        break;
+                                let mut last_update = Instant::now();
+                                let result = loop {
+                                    let bytes_read = match bufr.read(&mut buf) {
+                                        Ok(0) => break bw.flush(),
+                                        Ok(bytes_read) => bytes_read,
+                                        Err(err) => break Err(err),
+                                    };
+                                    if let Err(err) = bw.write_all(&buf[..bytes_read]) {
+                                        break Err(err);
                                     }
-                                    let bytes_written = bw
-                                        .write(&buf[..bytes_read])
-                                        .expect("Could not write to file");
-                                    total_written += bytes_written;
-                                    sender
-                                        .send(Box::new(move |app| {
-                                            let controller =
-                                                app.user_data::<Controller>().expect("controller missing");
-                                            controller.set_message(&format!(
-                                                "{} bytes read",
-                                                total_written
-                                            ));
-                                        }))
-                                        .unwrap();
-                                }
+                                    total_written += bytes_read;
+                                    if last_update.elapsed() >= PROGRESS_INTERVAL {
+                                        last_update = Instant::now();
+                                        let filename = local_filename.clone();
+                                        sender
+                                            .send(Box::new(move |app| {
+                                                Controller::download_progress(
+                                                    app,
+                                                    &filename,
+                                                    total_written,
+                                                )
+                                            }))
+                                            .unwrap();
+                                    }
+                                };
                                 sender
                                     .send(Box::new(move |app| {
                                         let controller =
                                             app.user_data::<Controller>().expect("controller missing");
+                                        if let Err(err) = result {
+                                            controller.download_failed(
+                                                &local_filename,
+                                                total_written,
+                                                err.to_string(),
+                                            );
+                                            return;
+                                        }
+                                        controller.download_finished(
+                                            &url,
+                                            &local_filename,
+                                            total_written,
+                                        );
                                         controller.set_message(&format!(
                                             "File downloaded: {} ({} bytes)",
                                             local_filename, total_written
@@ -671,40 +1061,19 @@ impl Controller {
                 }
                 Some('3') => {
                     // REDIRECT
-                    let redirect_count = {
-                        let mut guard = redirect_count.lock().unwrap();
-                        *guard += 1;
-                        *guard
-                    };
-                    if redirect_count >= 5 {
-                        sender.send(Box::new(move |app|{
-                            let controller = app.user_data::<Controller>().expect("controller missing");
-                            controller.set_gemini_content(url.clone(), GeminiType::Gemini,
-                            format!("# Too many redirects\n\nYou are probably stuck in a redirect loop. \
-                                     Here is the next redirected URL if you want to continue manually:\n\n=> {}", url), 0,
-                            None);
-                            controller.set_message("Detected redirect loop.");
-                        })).unwrap();
-                        return;
-                    }
-
                     let other = buf.chars().nth(1);
                     if other == Some('1') {
                         // redirect is permanent
                         // TODO: Should automatically update bookmarks
                     } else if !check(other) {
+                        redirect_chain.lock().unwrap().clear();
                         return;
                     }
                     // redirect might be relative
-                    match url.join(&meta) {
-                        Ok(url) => {
-                            // FIXME: Try to parse url, check scheme
-                            sender.send(Box::new(move |app|{
-                                let controller = app.user_data::<Controller>().expect("controller missing");
-                                controller.open_url(url, true, 0);
-                            })).unwrap();
-                        }
+                    let target = match url.join(&meta) {
+                        Ok(target) => target,
                         Err(_) => {
+                            redirect_chain.lock().unwrap().clear();
                             sender
                                 .send(Box::new(move |app| {
                                     let controller =
@@ -715,10 +1084,42 @@ impl Controller {
                                     ));
                                 }))
                                 .unwrap();
+                            return;
                         }
+                    };
+
+                    let max_redirects = SETTINGS.read().unwrap().config.max_redirects;
+                    let stop_reason = {
+                        let mut chain = redirect_chain.lock().unwrap();
+                        chain.push(url.clone());
+                        let reason = if chain.contains(&target) {
+                            Some("Redirect loop")
+                        } else if chain.len() > max_redirects as usize {
+                            Some("Too many redirects")
+                        } else {
+                            None
+                        };
+                        // Hand the chain over to the page listing it, so that
+                        // following the redirect manually starts from scratch
+                        reason.map(|reason| (reason, chain.drain(..).collect::<Vec<_>>()))
+                    };
+                    if let Some((reason, chain)) = stop_reason {
+                        let content = redirect_chain_page(reason, &chain, &target);
+                        sender.send(Box::new(move |app|{
+                            let controller = app.user_data::<Controller>().expect("controller missing");
+                            controller.set_gemini_content(url, GeminiType::Gemini, content, 0, None);
+                            controller.set_message(&format!("{} detected.", reason));
+                        })).unwrap();
+                        return;
                     }
-                }
-                Some('4') // FAILURE
+
+                    // FIXME: Try to parse url, check scheme
+                    sender.send(Box::new(move |app|{
+                        let controller = app.user_data::<Controller>().expect("controller missing");
+                        controller.open_url(target, true, 0);
+                    })).unwrap();
+                }
+                Some('4') // FAILURE
                 | Some('5') // PERMANENT FAILURE
                 | Some('6') // CLIENT CERTIFICATE
                 => {
@@ -781,106 +1182,88 @@ impl Controller {
             trace!("Controller::fetch_url({})", url);
         }
 
-        let request_id = {
-            let mut guard = self.last_request_id.lock().unwrap();
-            *guard += 1;
-            *guard
-        };
-
-        let port = url.port().unwrap_or(70);
-        let server = url.host_str().expect("no host").to_string();
-        let path = url.path();
-
-        // Decode %xx to binary values. Fixes #78
-        let binary = decode_binary(path.as_bytes());
-        let mut path = String::from_utf8_lossy(&binary).as_ref().to_owned();
+        let request = self.start_request();
+        let request_id = request.id;
 
         if !SETTINGS.read().unwrap().config.disable_history {
-            info!("fetch_url(): About to open URL {}", path);
-        }
-        if path.len() > 2 {
-            // TODO: check x[0] == / and x[1] == itemtype
-            path = path[2..].to_string();
-        } else {
-            path = "".to_string();
+            info!("fetch_url(): About to open URL {}", url.path());
         }
-
-        let server_details = format!("{}:{}", server, port);
+        let server = url.host_str().expect("no host").to_string();
+        let encoding = crate::text::encoding_for_host(&server);
 
         let request_id_ref = self.last_request_id.clone();
         let sender = self.sender.clone();
+        let token = self.new_cancel_token();
+        let transport = Cancellable::new(self.transport.clone(), token.clone());
 
         thread::spawn(move || {
-            let mut tls = false;
+            let _request = request;
+            // Large pages are shown while they are loading
             let mut buf = vec![];
-            // TLS-support. If non-standard-port, try to connect with TLS
-            if port != 70 {
-                if let Ok(connector) = TlsConnector::new() {
-                    let stream = TcpStream::connect(server_details.clone())
-                        .expect("Couldn't connect to the server...");
-                    match connector.connect(&server, stream) {
-                        Ok(mut stream) => {
-                            tls = true;
-                            info!("Connected with TLS");
-                            write!(stream, "{}\r\n", path).unwrap();
-
-                            loop {
-                                match stream.read_to_end(&mut buf) {
-                                    Ok(_) => break,
-                                    Err(e) => {
-                                        sender
-                                            .send(Box::new(move |app| {
-                                                let controller = app
-                                                    .user_data::<Controller>()
-                                                    .expect("controller missing");
-                                                controller
-                                                    .set_message(&format!("I/O error: {}", e));
-                                            }))
-                                            .unwrap();
-                                    }
-                                };
-                            }
-                        }
-                        Err(e) => {
-                            warn!("Could not open tls stream: {} to {}", e, server_details);
-                        }
+            let mut chunk = [0u8; 16384];
+            // bytes and lines shown so far
+            let mut shown = 0;
+            let mut lines_shown = 0;
+            let mut last_update = Instant::now();
+            // binary items and compressed data are downloaded instead
+            let mut download = false;
+            let result = gopher::connect(&transport, &url).and_then(|stream| {
+                let mut stream = ProgressReader::new(stream, sender.clone());
+                loop {
+                    let bytes_read = stream.read(&mut chunk)?;
+                    if bytes_read == 0 {
+                        break Ok(());
                     }
-                } else {
-                    info!("Could not establish tls connection");
-                }
-            }
-            if !tls {
-                match TcpStream::connect(server_details.clone()) {
-                    Ok(mut stream) => {
-                        write!(stream, "{}\r\n", path).unwrap();
-                        loop {
-                            match stream.read_to_end(&mut buf) {
-                                Ok(_) => break,
-                                Err(e) => {
-                                    sender
-                                        .send(Box::new(move |app| {
-                                            let controller = app
-                                                .user_data::<Controller>()
-                                                .expect("controller missing");
-                                            controller.set_message(&format!("I/O error: {}", e));
-                                        }))
-                                        .unwrap();
-                                }
-                            }
-                        }
+                    buf.extend_from_slice(&chunk[..bytes_read]);
+                    if item_type.is_download() || buf.starts_with(&[0x1f, 0x8b]) {
+                        download = true;
+                        break Ok(());
+                    }
+                    if last_update.elapsed() < STREAM_INTERVAL
+                        || request_id < *request_id_ref.lock().unwrap()
+                    {
+                        continue;
                     }
-                    Err(e) => {
+                    last_update = Instant::now();
+                    // only complete lines are shown, and only those
+                    // received since the last update are decoded
+                    if let Some(end) = buf[shown..].iter().rposition(|b| *b == b'\n') {
+                        let lines = &buf[shown..=shown + end];
+                        let s = crate::text::decode_as(lines, encoding.as_deref());
+                        let first = shown == 0;
+                        let first_line = lines_shown + 1;
+                        lines_shown += lines.iter().filter(|b| **b == b'\n').count();
+                        shown += end + 1;
                         sender
                             .send(Box::new(move |app| {
                                 let controller =
                                     app.user_data::<Controller>().expect("controller missing");
-                                controller
-                                    .set_message(&format!("Couldn't connect to server: {}", e));
+                                if first {
+                                    controller.set_gopher_content(item_type, s, index);
+                                } else {
+                                    controller.append_gopher_content(item_type, s, first_line);
+                                }
                             }))
                             .unwrap();
-                        return;
                     }
-                };
+                }
+            });
+            match result {
+                Ok(()) => (),
+                Err(_) if token.is_cancelled() => return,
+                Err(e) => {
+                    sender
+                        .send(Box::new(move |app| {
+                            let controller =
+                                app.user_data::<Controller>().expect("controller missing");
+                            let message = format!("Couldn't connect to server: {}", e);
+                            if !controller.try_next_mirror(&url, index, &message) {
+                                controller.set_message(&message);
+                            }
+                        }))
+                        .unwrap();
+                    return;
+                }
             }
 
             let guard = request_id_ref.lock().unwrap();
@@ -889,38 +1272,284 @@ impl Controller {
             }
             drop(guard);
 
-            let s = String::from_utf8_lossy(&buf).into_owned();
+            if download {
+                // the item is requested again and written to a file while
+                // it is received, instead of being buffered. Compressed
+                // text is shown decompressed when it has been downloaded.
+                sender
+                    .send(Box::new(move |app| {
+                        let filename = download_filename_from_url(&url);
+                        app.user_data::<Controller>()
+                            .expect("controller missing")
+                            .fetch_binary_url(url, item_type, filename);
+                    }))
+                    .unwrap();
+                return;
+            }
+
+            let s = crate::text::decode_as(&buf, encoding.as_deref());
             sender
                 .send(Box::new(move |app| {
+                    // keep the position the user scrolled to while loading
+                    let index = match shown > 0 {
+                        true => Controller::get_selected_item_index(app),
+                        false => index,
+                    };
                     let controller = app.user_data::<Controller>().expect("controller missing");
-                    controller.set_message(url.as_str());
+                    controller.set_loaded_message(&url);
                     controller.clear_search();
+                    controller.index_page(&url, &s, item_type.is_dir());
                     controller.set_gopher_content(item_type, s, index);
                 }))
                 .unwrap();
         });
     }
 
-    fn fetch_binary_url(&mut self, url: Url, item_type: ItemType, local_filename: String) {
-        self.set_message("Downloading binary file...");
-
-        let port = url.port().unwrap_or(70);
-        let server = url
-            .host()
-            .map_or("host.error".to_string(), |host| host.to_string());
-        let path = if url.path().len() > 2 {
-            // TODO: check x[0] == / and x[1] == itemtype
-            url.path()[2..].to_string()
-        } else {
-            String::new()
+    /// Saves data that has already been fetched to the download directory
+    fn save_download(&mut self, url: &Url, buf: Vec<u8>) {
+        let local_filename = download_filename_from_url(url);
+        let result = OpenOptions::new()
+            .write(true)
+            // make sure to not clobber downloaded files
+            .create_new(true)
+            .open(&local_filename)
+            .and_then(|mut file| file.write_all(&buf));
+        match result {
+            Ok(()) => {
+                self.download_started(local_filename.clone(), CancelToken::new());
+                self.download_finished(url, &local_filename, buf.len());
+                self.set_message(&format!(
+                    "File downloaded: {} ({} bytes)",
+                    local_filename,
+                    buf.len()
+                ));
+            }
+            Err(err) => self.set_message(&format!(
+                "Unable to open file: '{}' {}",
+                local_filename, err
+            )),
+        }
+    }
+
+    /// Asks the server of a Gopher+ item for its views and lets the user
+    /// choose one if there are several. Otherwise the item is opened as
+    /// usual.
+    fn open_gopher_plus(&mut self, url: Url) {
+        self.set_message("Loading ...");
+        let request = self.start_request();
+        let request_id = request.id;
+        let request_id_ref = self.last_request_id.clone();
+        let sender = self.sender.clone();
+        let transport = Cancellable::new(self.transport.clone(), self.new_cancel_token());
+        thread::spawn(move || {
+            let _request = request;
+            let views = gopher::fetch_views(&transport, &url).unwrap_or_else(|err| {
+                info!("Could not get Gopher+ views of {}: {}", url, err);
+                Vec::new()
+            });
+            if request_id < *request_id_ref.lock().unwrap() {
+                return;
+            }
+            sender
+                .send(Box::new(move |app| {
+                    if views.len() > 1 {
+                        app.user_data::<Controller>()
+                            .expect("controller missing")
+                            .set_message(url.as_str());
+                        crate::ui::dialogs::gopher_plus_views(app, url, views);
+                    } else {
+                        app.user_data::<Controller>()
+                            .expect("controller missing")
+                            .open_url(url, true, 0);
+                    }
+                }))
+                .unwrap();
+        });
+    }
+
+    /// Fetches a view of a Gopher+ item. Text and menus are shown, other
+    /// views are saved to the download directory.
+    pub fn fetch_gopher_plus_view(&mut self, url: Url, view: gopher::View) {
+        self.set_message("Loading ...");
+        let request = self.start_request();
+        let request_id = request.id;
+        let request_id_ref = self.last_request_id.clone();
+        let sender = self.sender.clone();
+        let transport = Cancellable::new(self.transport.clone(), self.new_cancel_token());
+        thread::spawn(move || {
+            let _request = request;
+            let result = gopher::fetch_view(&transport, &url, &view);
+            if request_id < *request_id_ref.lock().unwrap() {
+                return;
+            }
+            let item_type = if view.mime.starts_with("text/") {
+                ItemType::File
+            } else if view.mime.starts_with("application/gopher") {
+                ItemType::Dir
+            } else {
+                ItemType::Binary
+            };
+            sender
+                .send(Box::new(move |app| {
+                    Controller::show_gopher_plus_response(app, url, item_type, result);
+                }))
+                .unwrap();
+        });
+    }
+
+    /// Shows the response to a Gopher+ request if it is text or a menu,
+    /// otherwise saves it to the download directory
+    fn show_gopher_plus_response(
+        app: &mut Cursive,
+        url: Url,
+        item_type: ItemType,
+        result: std::io::Result<Vec<u8>>,
+    ) {
+        let index = Controller::get_selected_item_index(app);
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        let buf = match result {
+            Ok(buf) => buf,
+            Err(err) => {
+                controller.set_message(&format!("Could not fetch {}: {}", url, err));
+                return;
+            }
+        };
+        if !item_type.is_text() && !item_type.is_dir() {
+            controller.save_download(&url, buf);
+            return;
+        }
+        let encoding = crate::text::encoding_for_host(url.host_str().unwrap_or_default());
+        controller.add_to_history(url.clone(), index);
+        *controller.current_url.lock().unwrap() = url.clone();
+        let s = crate::text::decode_as(&buf, encoding.as_deref());
+        controller.set_loaded_message(&url);
+        controller.clear_search();
+        controller.index_page(&url, &s, item_type.is_dir());
+        controller.set_gopher_content(item_type, s, 0);
+    }
+
+    /// Shows the Gopher+ attributes of the selected gophermap item
+    pub fn item_info_action(app: &mut Cursive) {
+        let entry = app
+            .find_name::<SelectView<GopherMapEntry>>("content")
+            .and_then(|view| view.selection())
+            .filter(|entry| entry.gopher_plus);
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        let url = match entry {
+            Some(entry) => entry.url.clone(),
+            None => {
+                controller.set_message("The selected item is not a Gopher+ item");
+                return;
+            }
         };
+        controller.set_message("Loading ...");
+        let request = controller.start_request();
+        let request_id = request.id;
+        let request_id_ref = controller.last_request_id.clone();
+        let sender = controller.sender.clone();
+        let transport = Cancellable::new(controller.transport.clone(), controller.new_cancel_token());
+        thread::spawn(move || {
+            let _request = request;
+            let result = gopher::fetch_attributes(&transport, &url);
+            if request_id < *request_id_ref.lock().unwrap() {
+                return;
+            }
+            sender
+                .send(Box::new(move |app| match result {
+                    Ok(blocks) => {
+                        app.user_data::<Controller>()
+                            .expect("controller missing")
+                            .set_message(url.as_str());
+                        crate::ui::dialogs::item_info(app, url, blocks);
+                    }
+                    Err(err) => app
+                        .user_data::<Controller>()
+                        .expect("controller missing")
+                        .set_message(&format!("Could not get attributes of {}: {}", url, err)),
+                }))
+                .unwrap();
+        });
+    }
+
+    /// Asks the questions of a Gopher+ form before fetching the item
+    fn open_ask_form(&mut self, url: Url, item_type: ItemType) {
+        self.set_message("Loading ...");
+        let request = self.start_request();
+        let request_id = request.id;
+        let request_id_ref = self.last_request_id.clone();
+        let sender = self.sender.clone();
+        let transport = Cancellable::new(self.transport.clone(), self.new_cancel_token());
+        thread::spawn(move || {
+            let _request = request;
+            let questions = gopher::fetch_attributes(&transport, &url)
+                .map(|blocks| {
+                    blocks
+                        .iter()
+                        .find(|block| block.name == "ASK")
+                        .map(|block| gopher::parse_ask(&block.value))
+                        .unwrap_or_default()
+                })
+                .unwrap_or_else(|err| {
+                    info!("Could not get Gopher+ form of {}: {}", url, err);
+                    Vec::new()
+                });
+            if request_id < *request_id_ref.lock().unwrap() {
+                return;
+            }
+            sender
+                .send(Box::new(move |app| {
+                    app.user_data::<Controller>()
+                        .expect("controller missing")
+                        .set_message(url.as_str());
+                    if questions.is_empty() {
+                        app.user_data::<Controller>()
+                            .expect("controller missing")
+                            .open_url(url, true, 0);
+                    } else {
+                        crate::ui::dialogs::ask_form(app, url, item_type, questions);
+                    }
+                }))
+                .unwrap();
+        });
+    }
+
+    /// Sends the answers to a Gopher+ form and shows the response
+    pub fn submit_ask_form(
+        &mut self,
+        url: Url,
+        item_type: ItemType,
+        questions: Vec<gopher::Question>,
+        answers: Vec<String>,
+    ) {
+        self.set_message("Loading ...");
+        let request = self.start_request();
+        let request_id = request.id;
+        let request_id_ref = self.last_request_id.clone();
+        let sender = self.sender.clone();
+        let transport = Cancellable::new(self.transport.clone(), self.new_cancel_token());
+        thread::spawn(move || {
+            let _request = request;
+            let result = gopher::submit_form(&transport, &url, &questions, &answers);
+            if request_id < *request_id_ref.lock().unwrap() {
+                return;
+            }
+            sender
+                .send(Box::new(move |app| {
+                    Controller::show_gopher_plus_response(app, url, item_type, result);
+                }))
+                .unwrap();
+        });
+    }
+
+    fn fetch_binary_url(&mut self, url: Url, item_type: ItemType, local_filename: String) {
+        let token = CancelToken::new();
+        self.download_started(local_filename.clone(), token.clone());
 
-        let server_details = format!("{}:{}", server, port);
         let sender = self.sender.clone();
+        let transport = Cancellable::new(self.transport.clone(), token);
+        let download_limiter = self.download_limiter.clone();
 
         thread::spawn(move || {
-            // FIXME: Error handling!
-            let mut tls = false;
             let open = OpenOptions::new()
                 .write(true)
                 // make sure to not clobber downloaded files
@@ -932,96 +1561,79 @@ impl Controller {
                     let mut bw = BufWriter::new(file);
                     let mut buf = [0u8; 1024];
                     let mut total_written = 0;
-                    if port != 70 {
-                        if let Ok(connector) = TlsConnector::new() {
-                            let stream =
-                                TcpStream::connect(server_details.clone()).unwrap_or_else(|_| {
-                                    panic!("Couldn't connect to the server {}", server_details)
-                                });
-                            match connector.connect(&server, stream) {
-                                Ok(mut stream) => {
-                                    tls = true;
-                                    info!("Connected with TLS");
-                                    writeln!(stream, "{}", path).unwrap();
-                                    loop {
-                                        let bytes_read =
-                                            stream.read(&mut buf).expect("Could not read from TCP");
-                                        if bytes_read == 0 {
-                                            break;
-                                        }
-                                        let bytes_written = bw
-                                            .write(&buf[..bytes_read])
-                                            .expect("Could not write to file");
-                                        total_written += bytes_written;
-                                        sender
-                                            .send(Box::new(move |app| {
-                                                let controller = app
-                                                    .user_data::<Controller>()
-                                                    .expect("controller missing");
-                                                controller.set_message(&format!(
-                                                    "{} bytes read",
-                                                    total_written
-                                                ));
-                                            }))
-                                            .unwrap();
-                                    }
-                                }
-                                Err(e) => {
-                                    warn!("Could not open tls stream: {} to {}", e, server_details);
-                                }
-                            };
-                        } else {
-                            info!("Could not establish tls connection");
+                    let mut last_update = Instant::now();
+                    let stream = gopher::connect(&transport, &url)
+                        .map(|stream| ThrottledReader::new(stream, download_limiter));
+                    let result = stream.and_then(|mut stream| loop {
+                        let bytes_read = stream.read(&mut buf)?;
+                        if bytes_read == 0 {
+                            break bw.flush();
                         }
-                    }
-                    if !tls {
-                        let mut stream = TcpStream::connect(server_details.clone())
-                            .expect("Couldn't connect to the server...");
-                        writeln!(stream, "{}", path).unwrap();
-                        loop {
-                            let bytes_read =
-                                stream.read(&mut buf).expect("Could not read from TCP");
-                            if bytes_read == 0 {
-                                break;
-                            }
-                            let bytes_written = bw
-                                .write(&buf[..bytes_read])
-                                .expect("Could not write to file");
-                            total_written += bytes_written;
+                        bw.write_all(&buf[..bytes_read])?;
+                        total_written += bytes_read;
+                        if last_update.elapsed() >= PROGRESS_INTERVAL {
+                            last_update = Instant::now();
+                            let filename = local_filename.clone();
                             sender
                                 .send(Box::new(move |app| {
-                                    let controller =
-                                        app.user_data::<Controller>().expect("controller missing");
-                                    controller
-                                        .set_message(&format!("{} bytes read", total_written));
+                                    Controller::download_progress(app, &filename, total_written)
                                 }))
                                 .unwrap();
                         }
-                    }
-                    sender
-                        .send(Box::new(move |app| {
-                            let controller =
-                                app.user_data::<Controller>().expect("controller missing");
-                            controller.set_message(&format!(
-                                "File downloaded: {} ({} bytes)",
-                                local_filename, total_written
-                            ));
-                            if item_type == ItemType::Gif || item_type == ItemType::Image {
+                    });
+                    // gzipped files are shown as well if they contain text
+                    let text = match result {
+                        Ok(()) => crate::text::gunzip_text_file(Path::new(&local_filename)),
+                        Err(_) => None,
+                    };
+                    let encoding = url.host_str().and_then(crate::text::encoding_for_host);
+                    match result {
+                        Ok(()) => sender
+                            .send(Box::new(move |app| {
+                                let controller =
+                                    app.user_data::<Controller>().expect("controller missing");
+                                controller.download_finished(&url, &local_filename, total_written);
+                                controller.set_message(&format!(
+                                    "File downloaded: {} ({} bytes)",
+                                    local_filename, total_written
+                                ));
                                 let path = Path::new(&local_filename);
-                                controller.open_image_from_file(path).ok();
-                            }
-                        }))
-                        .unwrap();
+                                let current = *controller.current_url.lock().unwrap() == url;
+                                if let Some(text) = text.filter(|_| current) {
+                                    let s = crate::text::decode_as(&text, encoding.as_deref());
+                                    controller.clear_search();
+                                    controller.index_page(&url, &s, false);
+                                    controller.set_gopher_content(ItemType::File, s, 0);
+                                } else if path.starts_with(external::temp_dir()) {
+                                    Controller::view_image(app, path);
+                                } else if item_type.is_image() {
+                                    controller.open_image_from_file(path).ok();
+                                }
+                            }))
+                            .unwrap(),
+                        Err(err) => sender
+                            .send(Box::new(move |app| {
+                                let controller =
+                                    app.user_data::<Controller>().expect("controller missing");
+                                controller.download_failed(
+                                    &local_filename,
+                                    total_written,
+                                    err.to_string(),
+                                );
+                            }))
+                            .unwrap(),
+                    }
                 }
                 Err(err) => {
                     sender
                         .send(Box::new(move |app| {
                             let controller =
                                 app.user_data::<Controller>().expect("controller missing");
-                            controller.set_message(&format!(
-                                "Unable to open file: '{}' {}",
-                                local_filename, err
-                            ));
+                            controller.download_failed(
+                                &local_filename,
+                                0,
+                                format!("unable to open file: {}", err),
+                            );
                         }))
                         .unwrap();
                 }
@@ -1029,6 +1641,60 @@ impl Controller {
         });
     }
 
+    /// Plays a sound or video item in the media player while it is being
+    /// received
+    fn stream_media(&self, url: Url) {
+        let command = SETTINGS.read().unwrap().config.media_command.clone();
+        let sender = self.sender.clone();
+        let transport = self.transport.clone();
+
+        thread::spawn(move || {
+            let message = match gopher::connect(transport.as_ref(), &url)
+                .and_then(|stream| external::spawn_with_input(&command, stream))
+            {
+                Ok(()) => format!("Playing {}", url),
+                Err(err) => format!("Could not play {}: {}", url, err),
+            };
+            sender
+                .send(Box::new(move |app| {
+                    app.user_data::<Controller>()
+                        .expect("controller missing")
+                        .set_message(&message);
+                }))
+                .unwrap();
+        });
+    }
+
+    /// Fetches a uuencoded file and offers to view or save the decoded file
+    fn fetch_uuencoded(&self, url: Url) {
+        let sender = self.sender.clone();
+        let transport = self.transport.clone();
+
+        thread::spawn(move || {
+            let result = gopher::fetch(transport.as_ref(), &url)
+                .map_err(|e| e.to_string())
+                .and_then(|buf| {
+                    uuencode::decode(&String::from_utf8_lossy(&buf)).map_err(String::from)
+                });
+            sender
+                .send(Box::new(move |app| match result {
+                    Ok(file) => crate::ui::dialogs::uudecoded_file(app, file),
+                    Err(err) => app
+                        .user_data::<Controller>()
+                        .expect("controller missing")
+                        .set_message(&format!("Could not decode {}: {}", url, err)),
+                }))
+                .unwrap();
+        });
+    }
+
+    /// Shows a text for the current URL
+    pub(crate) fn show_text(&mut self, text: String) {
+        let url = self.current_url.lock().unwrap().clone();
+        self.clear_search();
+        self.set_gemini_content(url, GeminiType::Text, text, 0, None);
+    }
+
     pub fn open_url(&mut self, url: Url, add_to_history: bool, index: usize) {
         if !SETTINGS.read().unwrap().config.disable_history {
             info!("Open_url: {} position {}", url, index);
@@ -1036,67 +1702,78 @@ impl Controller {
         if add_to_history {
             self.add_to_history(url.clone(), index);
         }
+        self.previous_page = Some(PreviousPage {
+            url: self.current_url.lock().unwrap().clone(),
+            added: add_to_history && !SETTINGS.read().unwrap().config.disable_history,
+            content: self.content.lock().unwrap().clone(),
+            rendering: self.rendering.clone(),
+        });
         *self.current_url.lock().unwrap() = url.clone();
         match url.scheme() {
             "finger" => self.open_finger_address(url.clone(), index),
             "gopher" => self.open_gopher_address(url.clone(), ItemType::from_url(&url), index),
             "gemini" => self.open_gemini_address(url.clone(), index),
             "about" => self.open_about(url.clone()),
-            "http" | "https" => self.open_command("html_command", url.clone()).unwrap(),
+            "file" => self.open_file(url.clone(), index),
+            "http" | "https" => self.open_in_browser(url.clone()),
             scheme => self.set_message(format!("unknown scheme {}", scheme).as_str()),
         }
     }
 
-    fn fetch_finger_url(&self, url: Url, index: usize) {
-        // index is the position in the text (used when navigating back or reloading)
-        if !SETTINGS.read().unwrap().config.disable_history {
-            trace!("Controller::fetch_finger_url({})", url);
+    /// Opens the next mirror of a bookmark after `url` could not be
+    /// reached. Returns false if there is no further mirror to try.
+    fn try_next_mirror(&mut self, url: &Url, index: usize, error: &str) -> bool {
+        let next = self.bookmarks.lock().unwrap().next_mirror(url);
+        match next {
+            Some(mirror) => {
+                info!("{} failed ({}), trying mirror {}", url, error, mirror);
+                self.open_url(mirror, false, index);
+                true
+            }
+            None => false,
         }
+    }
 
-        let request_id = {
-            let mut guard = self.last_request_id.lock().unwrap();
-            *guard += 1;
-            *guard
-        };
+    /// Shows the URL of a loaded page in the statusbar, noting when it
+    /// is a mirror of a bookmark.
+    fn set_loaded_message(&self, url: &Url) {
+        let bookmarks = self.bookmarks.lock().unwrap();
+        match bookmarks.mirror_of(url) {
+            Some(b) => self.set_message(&format!("{} (mirror of {})", url, b.url)),
+            None => self.set_message(url.as_str()),
+        }
+    }
+
+    fn fetch_finger_url(&self, url: Url, index: usize) {
+        // index is the position in the text (used when navigating back or reloading)
+        if !SETTINGS.read().unwrap().config.disable_history {
+            trace!("Controller::fetch_finger_url({})", url);
+        }
+
+        let request = self.start_request();
+        let request_id = request.id;
 
-        let port = url.port().unwrap_or(79);
         let server = url.host_str().expect("no host").to_string();
-        let username = <&str>::clone(&url.username());
-        let path = match username.is_empty() {
-            true => url.path().trim_matches('/').to_string(),
-            false => username.to_string(),
-        };
-        let server_details = format!("{}:{}", server, port);
+        let encoding = crate::text::encoding_for_host(&server);
         let request_id_ref = self.last_request_id.clone();
         let sender = self.sender.clone();
+        let token = self.new_cancel_token();
+        let transport = Cancellable::new(self.transport.clone(), token.clone());
 
         thread::spawn(move || {
-            let mut buf = vec![];
-            match TcpStream::connect(server_details.clone()) {
-                Ok(mut stream) => {
-                    write!(stream, "{}\r\n", path).unwrap();
-                    loop {
-                        match stream.read_to_end(&mut buf) {
-                            Ok(_) => break,
-                            Err(e) => {
-                                sender
-                                    .send(Box::new(move |app| {
-                                        let controller = app
-                                            .user_data::<Controller>()
-                                            .expect("controller missing");
-                                        controller.set_message(&format!("I/O error: {}", e));
-                                    }))
-                                    .unwrap();
-                            }
-                        }
-                    }
-                }
+            let _request = request;
+            let buf = match finger::fetch(&transport, &url) {
+                Ok(buf) => buf,
+                Err(_) if token.is_cancelled() => return,
                 Err(e) => {
                     sender
                         .send(Box::new(move |app| {
                             let controller =
                                 app.user_data::<Controller>().expect("controller missing");
-                            controller.set_message(&format!("Couldn't connect to server: {}", e));
+                            let message = format!("Couldn't connect to server: {}", e);
+                            if !controller.try_next_mirror(&url, index, &message) {
+                                controller.set_message(&message);
+                            }
                         }))
                         .unwrap();
                     return;
@@ -1109,24 +1786,97 @@ impl Controller {
             }
             drop(guard);
 
-            let s = String::from_utf8_lossy(&buf).into_owned();
+            let s = crate::text::decode_as(&buf, encoding.as_deref());
             sender
                 .send(Box::new(move |app| {
                     let controller = app.user_data::<Controller>().expect("controller missing");
-                    controller.set_message(url.as_str());
+                    controller.set_loaded_message(&url);
                     controller.clear_search();
+                    controller.index_page(&url, &s, false);
                     controller.set_finger_content(url, s, index);
                 }))
                 .unwrap();
         });
     }
 
+    /// Sends a query to a CSO phonebook server and shows the matching
+    /// entries as text
+    fn fetch_cso_url(&self, url: Url, query: String, index: usize) {
+        let request = self.start_request();
+        let request_id = request.id;
+
+        let request_id_ref = self.last_request_id.clone();
+        let sender = self.sender.clone();
+        let token = self.new_cancel_token();
+        let transport = Cancellable::new(self.transport.clone(), token.clone());
+
+        thread::spawn(move || {
+            let _request = request;
+            let result = cso::fetch(&transport, &url, &query)
+                .map_err(|e| format!("Couldn't connect to server: {}", e))
+                .and_then(|buf| cso::parse(&String::from_utf8_lossy(&buf)))
+                .map(|fields| cso::format(&fields));
+
+            let guard = request_id_ref.lock().unwrap();
+            if request_id < *guard || token.is_cancelled() {
+                return;
+            }
+            drop(guard);
+
+            sender
+                .send(Box::new(move |app| {
+                    let controller = app.user_data::<Controller>().expect("controller missing");
+                    match result {
+                        Ok(s) => {
+                            controller.set_loaded_message(&url);
+                            controller.set_finger_content(url, s, index);
+                        }
+                        Err(e) => controller.set_message(&format!("Query failed: {}", e)),
+                    }
+                }))
+                .unwrap();
+        });
+    }
+
+    /// Shows a local file as text, whatever its content
+    fn open_file(&mut self, url: Url, index: usize) {
+        let path = match url.to_file_path() {
+            Ok(path) => path,
+            Err(()) => {
+                self.set_message(&format!("Invalid file URL {}", url));
+                return;
+            }
+        };
+        match std::fs::read(&path) {
+            Ok(buf) => {
+                let s = crate::text::decode(&buf);
+                self.set_message(url.as_str());
+                self.clear_search();
+                let gemini_type = match crate::markdown::is_markdown(&url) {
+                    true => GeminiType::Markdown,
+                    false => GeminiType::Text,
+                };
+                self.set_gemini_content(url, gemini_type, s, index, None);
+            }
+            Err(err) => self.set_message(&format!("Could not read {}: {}", path.display(), err)),
+        }
+    }
+
     /// Show an internal page from the "about" URL scheme
     /// as defined in RFC 6694.
     fn open_about(&mut self, url: Url) {
         let content = match url.path() {
             "blank" => String::new(),
+            "dashboard" => self.dashboard(),
+            "bookmarks" => {
+                let gophermap = self.bookmarks.lock().unwrap().to_gophermap(None);
+                self.set_message("about:bookmarks");
+                self.set_gopher_content(ItemType::Dir, gophermap, 0);
+                self.clear_search();
+                return;
+            }
             "help" => include_str!("about/help.gmi").into(),
+            "keys" => keys_page(),
             "sites" => include_str!("about/sites.gmi").into(),
             "error" => "An error occured.".into(),
             "license" => concat!(
@@ -1144,9 +1894,86 @@ impl Controller {
         self.clear_search();
     }
 
+    /// Builds the dashboard page, combining pinned bookmarks, active
+    /// downloads and recent history. It is regenerated whenever it is shown.
+    fn dashboard(&self) -> String {
+        let mut page = String::from("# Dashboard\n");
+
+        page.push_str("\n## Pinned bookmarks\n");
+        let bookmarks = self.bookmarks.lock().unwrap().get_bookmarks();
+        let pinned = bookmarks
+            .iter()
+            .filter(|b| b.tags.iter().any(|t| t == "pinned"))
+            .collect::<Vec<_>>();
+        if pinned.is_empty() {
+            page.push_str("Bookmarks with the tag \"pinned\" are shown here.\n");
+        }
+        for b in pinned {
+            page.push_str(&format!("=> {} {}\n", b.url, b.title));
+        }
+        page.push_str("=> about:bookmarks All bookmarks\n");
+
+        let downloads: Vec<Download> = self
+            .downloads
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|d| d.is_active())
+            .cloned()
+            .collect();
+        if !downloads.is_empty() {
+            page.push_str("\n## Active downloads\n");
+            for download in downloads {
+                page.push_str(&format!("* {}\n", download.filename));
+            }
+        }
+
+        if !SETTINGS.read().unwrap().config.disable_history {
+            page.push_str("\n## Recent history\n");
+            let format = format_description::parse_borrowed::<1>("[year]-[month]-[day] [hour]:[minute]")
+                .expect("Could not parse timestamp format");
+            let entries = self
+                .history
+                .lock()
+                .unwrap()
+                .get_latest_history(HISTORY_LEN)
+                .unwrap_or_default();
+            for e in entries {
+                page.push_str(&format!(
+                    "=> {} {} {}\n",
+                    e.url,
+                    e.timestamp.format(&format).unwrap_or_default(),
+                    human_readable_url(&e.url)
+                ));
+            }
+        }
+        page
+    }
+
     pub fn open_gopher_address(&mut self, url: Url, item_type: ItemType, index: usize) {
         self.set_message("Loading ...");
-        if item_type.is_download() {
+        if item_type.is_cso() {
+            match cso::query(&url) {
+                Some(query) => self.fetch_cso_url(url, query, index),
+                None => self
+                    .sender
+                    .send(Box::new(move |app| crate::ui::dialogs::cso_query(app, url)))
+                    .unwrap(),
+            }
+        } else if item_type == ItemType::Uuencoded {
+            self.fetch_uuencoded(url);
+        } else if is_media(item_type, &url)
+            && !SETTINGS.read().unwrap().config.media_command.is_empty()
+        {
+            self.stream_media(url);
+        } else if item_type.is_image() && SETTINGS.read().unwrap().config.open_images {
+            let filename = download_filename_from_url(&url);
+            let filename = Path::new(&filename).file_name().unwrap_or_default();
+            let path = external::temp_dir().join(filename);
+            // a previous download of the same name is replaced
+            std::fs::remove_file(&path).ok();
+            self.fetch_binary_url(url, item_type, path.display().to_string());
+        } else if item_type.is_download() {
             let filename = download_filename_from_url(&url);
             self.fetch_binary_url(url, item_type, filename);
         } else {
@@ -1156,36 +1983,39 @@ impl Controller {
 
     /// Renders a gophermap
     fn set_gopher_content(&mut self, item_type: ItemType, content: String, index: usize) {
+        self.marked.clear();
         let mut guard = self.content.lock().unwrap();
         guard.clear();
         guard.push_str(content.as_str());
         drop(guard);
 
-        if item_type.is_text() {
+        if item_type.is_text() || item_type.is_html() {
             self.clear_search();
-            let human_url = human_readable_url(&self.current_url.lock().unwrap());
-
-            // Issue #210: Note: Lines beginning with periods must be
-            // prepended with an extra period to ensure that the
-            // transmission is not terminated early. The client should
-            // strip extra periods at the beginning of the line.
-            let content_without_dots = content.lines().map(|line| {
-                if line.len() > 0 && line.chars().next().unwrap() == '.' {
-                    line[1..].to_string()
-                } else {
-                    line[0..].to_string()
-                }
-            }).into_iter().collect::<Vec<String>>().join("\n");
-            self.set_gemini_content(
-                Url::parse(&human_url).unwrap(),
-                GeminiType::Text,
-                content_without_dots,
-                index,
-                None,
-            );
+            let url = self.current_url.lock().unwrap().clone();
+
+            let content_without_dots = without_dots(&content);
+            // HTML files served over gopher are converted to gemtext
+            if item_type.is_html() {
+                let content = crate::html::to_gemtext(&content_without_dots, &url);
+                self.set_gemini_content(url, GeminiType::Gemini, content, index, None);
+                return;
+            }
+            // Capsules mirrored to gopher holes are rendered as gemtext
+            let gemini_type = if crate::markdown::is_markdown(&url) {
+                GeminiType::Markdown
+            } else if url.path().ends_with(".gmi")
+                || ncgopher_core::gemini::looks_like_gemtext(&content_without_dots)
+            {
+                GeminiType::Gemini
+            } else {
+                GeminiType::Text
+            };
+            self.set_gemini_content(url, gemini_type, content_without_dots, index, None);
             return;
         }
 
+        self.rendering = Some(Rendering::Gopher(item_type));
+
         // ensure gopher view is focused before setting content
         self.sender
             .send(Box::new(|app| {
@@ -1193,160 +2023,284 @@ impl Controller {
                     .find_name::<Layout>("main")
                     .expect("main layout missing");
                 layout.set_view("content");
-                let human_url = human_readable_url(
+                let breadcrumb = breadcrumb(
                     &app.user_data::<Controller>()
                         .expect("controller missing")
                         .current_url
                         .lock()
                         .unwrap(),
                 );
-                layout.set_title("content".into(), human_url);
+                layout.set_title("content".into(), breadcrumb);
             }))
             .unwrap();
 
         self.sender
             .send(Box::new(move |app| {
-                let textwrap = SETTINGS
-                    .read()
-                    .unwrap()
-                    .config
-                    .textwrap
-                    .parse()
-                    .unwrap_or(usize::MAX);
-
-                let viewport_width = app.screen_size().x
-                // adjust for left margin
-                - 7;
-
-                let viewport_width = std::cmp::min(textwrap, viewport_width);
-
-                let mut view = app
-                    .find_name::<SelectView<GopherMapEntry>>("content")
-                    .expect("gopher content view missing");
-                view.clear();
-                let lines = content.lines();
-                let mut gophermap = Vec::new();
-                let mut first = true;
-                for l in lines {
-                    if first {
-                        if l.starts_with('/') {
-                            app.find_name::<Layout>("main")
-                                .expect("main layout missing")
-                                .set_title("content".into(), l.into());
-                        }
-                        first = false;
+                Controller::render_gophermap(app, &content, false);
+                app.find_name::<SelectView<GopherMapEntry>>("content")
+                    .expect("gopher content view missing")
+                    .set_selection(index);
+            }))
+            .unwrap();
+    }
+
+    /// Shows lines received while a page is loading below the lines shown
+    /// already, without rendering those again. The complete page is
+    /// rendered once it has been loaded.
+    fn append_gopher_content(&mut self, item_type: ItemType, content: String, first_line: usize) {
+        let content = match item_type.is_text() {
+            true => without_dots(&content),
+            false => content,
+        };
+        let mut guard = self.content.lock().unwrap();
+        if !guard.is_empty() && !guard.ends_with('\n') {
+            guard.push('\n');
+        }
+        guard.push_str(&content);
+        drop(guard);
+
+        match self.rendering.clone() {
+            // HTML can only be converted to gemtext as a whole
+            _ if item_type.is_html() => (),
+            Some(Rendering::Gopher(_)) => self
+                .sender
+                .send(Box::new(move |app| {
+                    Controller::render_gophermap(app, &content, true);
+                }))
+                .unwrap(),
+            Some(Rendering::Gemini {
+                url, gemini_type, ..
+            }) => self
+                .sender
+                .send(Box::new(move |app| {
+                    Controller::render_gemini(app, &url, &gemini_type, &content, first_line);
+                    crate::ui::toc::refresh(app);
+                }))
+                .unwrap(),
+            None => (),
+        }
+    }
+
+    /// Shows the entries of a gophermap. With `append`, they are added
+    /// below the entries shown already, e.g. while the page is loading.
+    fn render_gophermap(app: &mut Cursive, content: &str, append: bool) {
+        let textwrap = SETTINGS
+            .read()
+            .unwrap()
+            .config
+            .textwrap
+            .parse()
+            .unwrap_or(usize::MAX);
+
+        app.user_data::<Controller>()
+            .expect("controller missing")
+            .rendered_width = app.screen_size().x;
+        let show_type_labels = SETTINGS.read().unwrap().config.show_type_labels;
+        let label_width = SETTINGS.read().unwrap().config.type_label_width();
+        let split = app
+            .call_on_name("main", |v: &mut Layout| v.has_preview_beside("content"))
+            .unwrap_or_default();
+        // adjust for left margin
+        let margin = if show_type_labels { label_width + 2 } else { 0 };
+        let viewport_width = (app.screen_size().x / if split { 2 } else { 1 })
+            .saturating_sub(margin)
+            .max(1);
+
+        let viewport_width = std::cmp::min(textwrap, viewport_width);
+
+        let mut view = app
+            .find_name::<SelectView<GopherMapEntry>>("content")
+            .expect("gopher content view missing");
+        if !append {
+            view.clear();
+        }
+        let content = crate::text::sanitize(content);
+        let lines = content.lines();
+        let mut gophermap = Vec::new();
+        let mut first = !append;
+        for l in lines {
+            if first {
+                if l.starts_with('/') {
+                    app.find_name::<Layout>("main")
+                        .expect("main layout missing")
+                        .set_title("content".into(), l.into());
+                }
+                first = false;
+            }
+            if l != "." {
+                match GopherMapEntry::parse(l.to_string()) {
+                    Ok(gl) => {
+                        gophermap.push(gl);
                     }
-                    if l != "." {
-                        match GopherMapEntry::parse(l.to_string()) {
-                            Ok(gl) => {
-                                gophermap.push(gl);
-                            }
-                            Err(err) => {
-                                warn!("Invalid gophermap line: {}", err);
-                            }
-                        };
+                    Err(err) => {
+                        warn!("Invalid gophermap line: {}", err);
                     }
+                };
+            }
+        }
+        let mut template = SETTINGS.read().unwrap().config.gophermap_format.clone();
+        if !show_type_labels {
+            template = template.replace("{type}", "").trim_start().to_string();
+        }
+        // links are numbered to jump to them by typing the number
+        let shown_links = match append {
+            true => view.iter().filter(|(_, e)| !e.item_type.is_inline()).count(),
+            false => 0,
+        };
+        let links = shown_links + gophermap.iter().filter(|e| !e.item_type.is_inline()).count();
+        let number_width = match SETTINGS.read().unwrap().config.number_links {
+            true => links.to_string().len() + 1,
+            false => 0,
+        };
+        let viewport_width = viewport_width.saturating_sub(number_width).max(1);
+        // links to visited pages are colored to tell them apart
+        let visited_color = SETTINGS.read().unwrap().config.visited_color();
+        let urls: Vec<Url> = gophermap.iter().map(|e| e.url.clone()).collect();
+        let visited = match visited_color {
+            Some(_) => app
+                .user_data::<Controller>()
+                .expect("controller missing")
+                .history
+                .lock()
+                .unwrap()
+                .visited_urls(&urls)
+                .unwrap_or_else(|err| {
+                    warn!("Could not read visited URLs: {}", err);
+                    HashSet::new()
+                }),
+            None => HashSet::new(),
+        };
+        let mut link_number = shown_links;
+        for l in gophermap {
+            let entry = l.clone();
+            let type_label = SETTINGS.read().unwrap().config.type_label(entry.item_type);
+            let number = match number_width {
+                0 => String::new(),
+                _ if entry.item_type.is_inline() => " ".repeat(number_width),
+                _ => {
+                    link_number += 1;
+                    format!("{:>width$} ", link_number, width = number_width - 1)
                 }
-                for l in gophermap {
-                    let entry = l.clone();
-
-                    let label = entry.clone().label();
-                    if entry.item_type == ItemType::Inline && label.len() > viewport_width {
-                        for row in LinesIterator::new(&label, viewport_width) {
-                            let mut formatted = StyledString::new();
-                            let label = format!(
-                                "{}  {}",
-                                ItemType::as_str(entry.item_type),
-                                &label[row.start..row.end]
-                            );
-                            formatted.append(label);
-                            view.add_item(formatted, l.clone());
-                        }
-                    } else {
-                        let mut formatted = StyledString::new();
-                        let label =
-                            format!("{}  {}", ItemType::as_str(entry.item_type), entry.label());
-                        formatted.append(label);
-                        view.add_item(formatted, l.clone());
-                    }
+            };
+            let mut style = match show_type_labels {
+                true => Style::none(),
+                false => type_style(entry.item_type),
+            };
+            if let Some(color) = SETTINGS.read().unwrap().config.type_color(entry.item_type) {
+                style = style.combine(color);
+            }
+            if let Some(color) = visited_color.filter(|_| visited.contains(&entry.url)) {
+                style = style.combine(color);
+            }
+
+            let label = entry.clone().label();
+            if entry.item_type == ItemType::Inline && label.len() > viewport_width {
+                for row in LinesIterator::new(&label, viewport_width) {
+                    let mut formatted = StyledString::plain(&number);
+                    let row = &label[row.start..row.end];
+                    let text = entry.format(&template, &type_label, row);
+                    formatted.append_styled(text, style);
+                    view.add_item(formatted, l.clone());
                 }
-                view.set_on_submit(|app, entry| {
-                    let controller = app.user_data::<Controller>().expect("controller missing");
-                    if entry.item_type.is_download()
-                        || entry.item_type.is_text()
-                        || entry.item_type.is_dir()
-                    {
-                        controller.open_url(entry.url.clone(), true, 0);
-                    } else if entry.item_type.is_query() {
-                        // open query dialog
-                        let url = entry.url.clone();
-                        app.add_layer(
-                            Dialog::new()
-                                .title("Enter query:")
-                                .content(
-                                    EditView::new()
-                                        // Call `show_popup` when the user presses `Enter`
-                                        //FIXME: create closure with url: .on_submit(search)
-                                        .with_name("query")
-                                        .fixed_width(30),
-                                )
-                                .button("Cancel", |app| {
-                                    app.pop_layer();
-                                })
-                                .button("Ok", move |app| {
-                                    let mut url = url.clone();
-                                    let name =
-                                        app.find_name::<EditView>("query").unwrap().get_content();
-                                    let mut path = url.path().to_string();
-                                    path.push_str("%09");
-                                    path.push_str(&*name);
-                                    url.set_path(path.as_str());
-
-                                    app.pop_layer(); // Close search dialog
-                                    let controller =
-                                        app.user_data::<Controller>().expect("controller missing");
-                                    controller.set_message("Loading ...");
-                                    controller.fetch_url(url, ItemType::Dir, 0);
-                                }),
-                        );
-                    } else if entry.item_type.is_html() {
-                        controller
-                            .open_command("html_command", entry.url.clone())
-                            .unwrap();
-                    } else if entry.item_type.is_image() {
-                        controller
-                            .open_command("image_command", entry.url.clone())
-                            .unwrap();
-                    } else if entry.item_type.is_telnet() {
-                        controller
-                            .open_command("telnet_command", entry.url.clone())
-                            .unwrap();
-                    } else if entry.item_type.is_inline() {
-                        // Check if current line is text only. If yes, try to find
-                        // URL in text and open with appropriate function
-                        controller
-                            .open_link_in_label(entry.clone().label());
-                    }
-                });
-                view.set_selection(index);
-            }))
-            .unwrap();
+            } else {
+                let mut formatted = StyledString::plain(&number);
+                let text = entry.format(&template, &type_label, &label);
+                formatted.append_styled(text, style);
+                view.add_item(formatted, l.clone());
+            }
+        }
+        view.set_on_select(Controller::preview_action);
+        view.set_on_submit(|app, entry| {
+            let controller = app.user_data::<Controller>().expect("controller missing");
+            if entry.ask {
+                controller.open_ask_form(entry.url.clone(), entry.item_type);
+            } else if entry.item_type.is_download()
+                || entry.item_type.is_text()
+                || entry.item_type.is_dir()
+            {
+                if entry.gopher_plus {
+                    controller.open_gopher_plus(entry.url.clone());
+                } else {
+                    controller.open_url(entry.url.clone(), true, 0);
+                }
+            } else if entry.item_type.is_query() {
+                // open query dialog
+                let url = entry.url.clone();
+                app.add_layer(
+                    Dialog::new()
+                        .title("Enter query:")
+                        .content(
+                            EditView::new()
+                                // Call `show_popup` when the user presses `Enter`
+                                //FIXME: create closure with url: .on_submit(search)
+                                .with_name("query")
+                                .fixed_width(30),
+                        )
+                        .button("Cancel", |app| {
+                            app.pop_layer();
+                        })
+                        .button("Compose...", {
+                            let url = url.clone();
+                            move |app| {
+                                let name =
+                                    app.find_name::<EditView>("query").unwrap().get_content();
+                                app.pop_layer(); // Close search dialog
+                                let url = url.clone();
+                                crate::ui::composer::composer(
+                                    app,
+                                    "Enter query:",
+                                    &name,
+                                    move |app, query| {
+                                        // queries are sent on a single line
+                                        let query =
+                                            query.split_whitespace().collect::<Vec<_>>().join(" ");
+                                        Controller::query_action(app, url.clone(), &query);
+                                    },
+                                );
+                            }
+                        })
+                        .button("Ok", move |app| {
+                            let name =
+                                app.find_name::<EditView>("query").unwrap().get_content();
+                            app.pop_layer(); // Close search dialog
+                            Controller::query_action(app, url.clone(), &name);
+                        }),
+                );
+            } else if entry.item_type.is_cso() {
+                crate::ui::dialogs::cso_query(app, entry.url.clone());
+            } else if entry.item_type.is_html() {
+                match entry.url.scheme() {
+                    "http" | "https" => controller.open_in_browser(entry.url.clone()),
+                    // URL: selectors may point to any scheme
+                    _ => controller.open_url(entry.url.clone(), true, 0),
+                }
+            } else if entry.item_type.is_image() {
+                controller
+                    .open_command("image_command", entry.url.clone())
+                    .unwrap();
+            } else if entry.item_type.is_telnet() {
+                controller
+                    .open_command("telnet_command", entry.url.clone())
+                    .unwrap();
+            } else if entry.item_type.is_inline() {
+                // Check if current line is text only. If yes, try to find
+                // URL in text and open with appropriate function
+                controller
+                    .open_link_in_label(entry.clone().label());
+            }
+        });
     }
 
     fn open_link_in_label(&mut self, label: String) {
         self.sender
             .send(Box::new(move |app| {
-                let finder = LinkFinder::new();
-                let links: Vec<_> = finder.links(&label).collect();
-                if links.len() == 1 && links[0].kind() == &LinkKind::Url {
-                    let link = &links[0];
-                    if let Ok(url) = Url::parse(link.as_str()) {
-                        app.user_data::<Controller>()
-                            .expect("controller missing")
-                            .open_url(url, true, 0);
-                    }
+                let mut links = crate::text::find_links(&label);
+                if links.len() == 1 {
+                    app.user_data::<Controller>()
+                        .expect("controller missing")
+                        .open_url(links.remove(0), true, 0);
                 } else if links.len() > 1 {
-                    app.add_layer(Dialog::info("Found several links, not sure which one to open.\nDialog not implemented"));
+                    crate::ui::dialogs::choose_link(app, links);
                 }
             })).unwrap();
     }
@@ -1374,6 +2328,12 @@ impl Controller {
         guard.push_str(content.as_str());
         drop(guard);
 
+        self.rendering = Some(Rendering::Gemini {
+            url: url.clone(),
+            gemini_type: gemini_type.clone(),
+            cert_fingerprint: cert_fingerprint.clone(),
+        });
+
         let mut cert_common_name_label = String::new();
         if let Some(fingerprint) = cert_fingerprint {
             if let Some(cc) = self
@@ -1386,7 +2346,7 @@ impl Controller {
             }
         }
 
-        let human_url = human_readable_url(&url);
+        let breadcrumb = breadcrumb(&url);
         // ensure gemini view is focused before setting content
         self.sender
             .send(Box::new(move |app| {
@@ -1397,7 +2357,7 @@ impl Controller {
                 layout.set_view("gemini_content");
                 layout.set_title(
                     "gemini_content".into(),
-                    format!("{} {}", human_url, cert_common_name_label),
+                    format!("{} {}", breadcrumb, cert_common_name_label),
                 );
                 info!("set gemini view");
             }))
@@ -1405,57 +2365,109 @@ impl Controller {
 
         self.sender
             .send(Box::new(move |app| {
-                let textwrap = SETTINGS
-                    .read()
-                    .unwrap()
-                    .config
-                    .textwrap
-                    .parse()
-                    .unwrap_or(usize::MAX);
-
-                let viewport_width = app.screen_size().x
-                // adjust for left margin
-                - 8;
+                Controller::render_gemini(app, &url, &gemini_type, &content, 1);
+                app.find_name::<SelectView<Option<Url>>>("gemini_content")
+                    .expect("gemini content view missing")
+                    .set_selection(index);
+                crate::ui::toc::refresh(app);
+            }))
+            .unwrap();
+    }
 
-                let viewport_width = std::cmp::min(textwrap, viewport_width);
+    /// Shows a text page. Lines numbered from `first_line` on are added
+    /// below the rows shown already, e.g. while the page is loading; with
+    /// 1 the whole page is shown.
+    fn render_gemini(
+        app: &mut Cursive,
+        url: &Url,
+        gemini_type: &GeminiType,
+        content: &str,
+        first_line: usize,
+    ) {
+        let textwrap = SETTINGS
+            .read()
+            .unwrap()
+            .config
+            .textwrap
+            .parse()
+            .unwrap_or(usize::MAX);
 
-                let mut view = app
-                    .find_name::<SelectView<Option<Url>>>("gemini_content")
-                    .expect("gemini content view missing");
-                view.clear();
+        app.user_data::<Controller>()
+            .expect("controller missing")
+            .rendered_width = app.screen_size().x;
+        let split = app
+            .call_on_name("main", |v: &mut Layout| v.has_preview_beside("gemini_content"))
+            .unwrap_or_default();
+        // adjust for left margin
+        let viewport_width = (app.screen_size().x / if split { 2 } else { 1 })
+            .saturating_sub(8)
+            .max(1);
+
+        let viewport_width = std::cmp::min(textwrap, viewport_width);
+
+        let mut view = app
+            .find_name::<SelectView<Option<Url>>>("gemini_content")
+            .expect("gemini content view missing");
+        if first_line == 1 {
+            view.clear();
+        }
 
-                if gemini_type == GeminiType::Text {
+        let line_numbers = SETTINGS.read().unwrap().config.line_numbers;
+        if *gemini_type == GeminiType::Text && crate::ansi::has_escapes(content) {
+            // colors are shown, other escape sequences are dropped
+            let rows = crate::ansi::parse(content);
+            if line_numbers {
+                view.add_all(crate::text::number_rows(rows, first_line));
+            } else {
+                view.add_all(rows);
+            }
+        } else {
+            let content = crate::text::sanitize(content);
+            match gemini_type {
+                GeminiType::Text => {
                     let content = str::replace(&content, "\t", "        ");
-                    view.add_all(
-                        LinesIterator::new(&content, viewport_width)
-                            .map(|row| (&content[row.start..row.end], None))
-                            .collect::<Vec<_>>(),
-                    );
-                } else {
-                    view.add_all(crate::gemini::parse(&content, &url, viewport_width));
-                }
-                view.set_on_submit(|app, _entry| {
-                    let view = app
-                        .find_name::<SelectView<Option<Url>>>("gemini_content")
-                        .expect("gemini content view missing");
-                    if let Some(selected_id) = view.selected_id() {
-                        if let Some((label, entry)) = view.get_item(selected_id) {
-                            if let Some(url) = entry {
-                                app.user_data::<Controller>()
-                                    .expect("controller missing")
-                                    .open_url(url.clone(), true, 0)
-                            } else {
-                                let controller =
-                                    app.user_data::<Controller>().expect("controller missing");
-                                controller
-                                    .open_link_in_label(label.to_string());
-                            }
+                    match crate::highlight::highlight(url, &content) {
+                        Some(rows) if line_numbers => {
+                            view.add_all(crate::text::number_rows(rows, first_line))
+                        }
+                        Some(rows) => view.add_all(rows),
+                        None if line_numbers => view.add_all(crate::text::wrap_with_line_numbers(
+                            &content,
+                            viewport_width,
+                            first_line,
+                        )),
+                        None => {
+                            view.add_all(crate::text::wrap_with_links(&content, viewport_width))
                         }
                     }
-                });
-                view.set_selection(index);
-            }))
-            .unwrap();
+                }
+                GeminiType::Gemini => {
+                    view.add_all(crate::gemini::parse(&content, url, viewport_width))
+                }
+                GeminiType::Markdown => {
+                    view.add_all(crate::markdown::parse(&content, url, viewport_width))
+                }
+            }
+        }
+        view.set_on_submit(|app, _entry| {
+            let view = app
+                .find_name::<SelectView<Option<Url>>>("gemini_content")
+                .expect("gemini content view missing");
+            if let Some(selected_id) = view.selected_id() {
+                if let Some((label, entry)) = view.get_item(selected_id) {
+                    if let Some(url) = entry {
+                        app.user_data::<Controller>()
+                            .expect("controller missing")
+                            .open_url(url.clone(), true, 0)
+                    } else {
+                        let controller =
+                            app.user_data::<Controller>().expect("controller missing");
+                        controller
+                            .open_link_in_label(label.to_string());
+                    }
+                }
+            }
+        });
     }
 
     /// Renders finger content
@@ -1506,16 +2518,15 @@ impl Controller {
                     .menubar()
                     .find_subtree("History")
                     .expect("history menu missing");
-                if let Some(idx) = menu.find_position(&url.to_string()) {
-                    if idx >= 3 {
+                if let Some(idx) = menu.find_position(url.as_ref()) {
+                    if idx >= HISTORY_MENU_ITEMS {
                         menu.remove(idx);
                     }
                 }
-                // Add 3 for the two first menuitems + separator
-                if menu.len() > HISTORY_LEN + 3 {
+                if menu.len() > HISTORY_LEN + HISTORY_MENU_ITEMS {
                     menu.remove(menu.len() - 1);
                 }
-                menu.insert_leaf(3, h.title, move |app| {
+                menu.insert_leaf(HISTORY_MENU_ITEMS, h.title, move |app| {
                     app.user_data::<Controller>()
                         .expect("controller missing")
                         .open_url(url.clone(), true, 0);
@@ -1524,6 +2535,38 @@ impl Controller {
             .unwrap();
     }
 
+    /// Records the title of a fetched page in the history and adds its
+    /// text to the full-text index of visited pages. For gophermaps, only
+    /// the displayed labels are indexed.
+    fn index_page(&self, url: &Url, content: &str, gophermap: bool) {
+        if SETTINGS.read().unwrap().config.disable_history {
+            return;
+        }
+        let text = if gophermap {
+            content
+                .lines()
+                .filter_map(|l| GopherMapEntry::parse(l.to_string()).ok())
+                .map(|entry| entry.label())
+                .collect::<Vec<_>>()
+                .join("\n")
+        } else {
+            content.to_string()
+        };
+        // use the first gemtext heading as title if there is one
+        let title = text
+            .lines()
+            .find_map(|l| l.strip_prefix('#'))
+            .map(|heading| heading.trim_start_matches('#').trim().to_string())
+            .unwrap_or_else(|| human_readable_url(url));
+        let history = self.history.lock().unwrap();
+        if let Err(err) = history.set_title(url, &title) {
+            warn!("Could not record title of {}: {}", url, err);
+        }
+        if let Err(err) = history.index_page(url, &title, &text) {
+            warn!("Could not index page {}: {}", url, err);
+        }
+    }
+
     /// Purges the entire history
     /// TODO: Add option to clear only parts of the history
     pub fn clear_history(&mut self) {
@@ -1540,14 +2583,82 @@ impl Controller {
                     .menubar()
                     .find_subtree("History")
                     .expect("history menu missing");
-                // remove everything but the first three elements
-                while menu.len() > 3 {
-                    menu.remove(3);
+                // remove everything but the fixed menu items
+                while menu.len() > HISTORY_MENU_ITEMS {
+                    menu.remove(HISTORY_MENU_ITEMS);
                 }
             }))
             .unwrap();
     }
 
+    /// Returns a new token for aborting the transfer of the page to be
+    /// loaded. Transfers of pages requested before are aborted.
+    fn new_cancel_token(&self) -> CancelToken {
+        let token = CancelToken::new();
+        let mut guard = self.cancel_token.lock().unwrap();
+        guard.cancel();
+        *guard = token.clone();
+        token
+    }
+
+    /// Starts a request for a page: results of requests started before
+    /// are ignored. The returned request has to be kept until the thread
+    /// loading the page ends.
+    fn start_request(&self) -> PageRequest {
+        let id = {
+            let mut guard = self.last_request_id.lock().unwrap();
+            *guard += 1;
+            *guard
+        };
+        *self.loading.lock().unwrap() = Some(id);
+        PageRequest {
+            id,
+            sender: self.sender.clone(),
+        }
+    }
+
+    /// Aborts loading a page and goes back to the page shown before.
+    /// Returns false if no page is being loaded.
+    pub fn cancel_request_action(app: &mut Cursive) -> bool {
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        if controller.loading.lock().unwrap().take().is_none() {
+            return false;
+        }
+        controller.cancel_token.lock().unwrap().cancel();
+        // results that are still on their way are ignored
+        *controller.last_request_id.lock().unwrap() += 1;
+        if let Some(previous) = controller.previous_page.take() {
+            let mut index = 0;
+            if previous.added {
+                let mut history = controller.history.lock().unwrap();
+                history.stack.pop();
+                index = history.stack.last().map_or(0, |entry| entry.position);
+            }
+            *controller.current_url.lock().unwrap() = previous.url;
+            // large pages are shown while they are loading
+            let replaced = *controller.content.lock().unwrap() != previous.content;
+            match previous.rendering {
+                Some(Rendering::Gopher(item_type)) if replaced => {
+                    controller.set_gopher_content(item_type, previous.content, index)
+                }
+                Some(Rendering::Gemini {
+                    url,
+                    gemini_type,
+                    cert_fingerprint,
+                }) if replaced => controller.set_gemini_content(
+                    url,
+                    gemini_type,
+                    previous.content,
+                    index,
+                    cert_fingerprint,
+                ),
+                _ => (),
+            }
+        }
+        controller.set_message("Cancelled");
+        true
+    }
+
     /// Navigates to the previous page in history
     pub fn navigate_back(&mut self) {
         let mut guard = self.history.lock().unwrap();
@@ -1559,7 +2670,28 @@ impl Controller {
         }
     }
 
-    fn open_command(&mut self, command: &str, url: Url) -> Result<(), Box<dyn Error>> {
+    /// Goes forward again after going back
+    pub fn navigate_forward(&mut self) {
+        let entry = self.history.lock().unwrap().forward();
+        if let Some(h) = entry {
+            info!("NAVIGATE_FORWARD to index {}", h.position);
+            self.open_url(h.url, false, h.position);
+        }
+    }
+
+    /// Opens a web page with the html_command, after asking the user
+    /// unless confirm_browser is turned off
+    fn open_in_browser(&mut self, url: Url) {
+        if SETTINGS.read().unwrap().config.confirm_browser {
+            self.sender
+                .send(Box::new(move |app| crate::ui::dialogs::confirm_browser(app, url)))
+                .unwrap();
+        } else {
+            self.open_command("html_command", url).unwrap();
+        }
+    }
+
+    pub(crate) fn open_command(&mut self, command: &str, url: Url) -> Result<(), Box<dyn Error>> {
         // Opens a URL in an external application - if defined in settings
         let command = match command {
             "html_command" => SETTINGS.read().unwrap().config.html_command.clone(),
@@ -1568,30 +2700,49 @@ impl Controller {
             _ => panic!("unknown field"),
         };
 
-        if !command.is_empty() {
-            if let Err(err) = Command::new(&command).arg(url.to_string()).spawn() {
-                self.set_message(&format!("Command failed: {}: {}", err, command));
-            }
-        } else {
+        if command.is_empty() {
             self.set_message(&format!("No command for opening {} defined.", url));
-        }
-        Ok(())
-    }
-
-    fn open_image_from_file(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        } else if url.scheme() == "telnet" {
+            // telnet clients need the terminal
+            self.sender
+                .send(Box::new(move |app| {
+                    if let Err(err) = external::run_in_foreground(app, &command, url.as_str()) {
+                        app.user_data::<Controller>()
+                            .expect("controller missing")
+                            .set_message(&format!("Command failed: {}: {}", err, command));
+                    }
+                }))
+                .unwrap();
+        } else if let Err(err) = external::spawn(&command, url.as_str()) {
+            self.set_message(&format!("Command failed: {}: {}", err, command));
+        }
+        Ok(())
+    }
+
+    /// Shows an image in the image viewer with the terminal handed over
+    /// to it, so that terminal image viewers work as well
+    fn view_image(app: &mut Cursive, path: &Path) {
         let command = SETTINGS.read().unwrap().config.image_command.clone();
-        if !command.is_empty() {
-            if let Err(err) = Command::new(&command)
-                .arg(path.as_os_str().to_str().unwrap())
-                .spawn()
-            {
-                self.set_message(&format!("Command failed: {}: {}", err, command));
-            }
+        let message = if command.is_empty() {
+            format!("No command for opening {} defined.", path.display())
         } else {
-            self.set_message(&format!(
-                "No command for opening {} defined.",
-                path.as_os_str().to_str().unwrap()
-            ));
+            match external::run_in_foreground(app, &command, &path.display().to_string()) {
+                Ok(_) => return,
+                Err(err) => format!("Command failed: {}: {}", err, command),
+            }
+        };
+        app.user_data::<Controller>()
+            .expect("controller missing")
+            .set_message(&message);
+    }
+
+    fn open_image_from_file(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let command = SETTINGS.read().unwrap().config.image_command.clone();
+        let path = path.display().to_string();
+        if command.is_empty() {
+            self.set_message(&format!("No command for opening {} defined.", path));
+        } else if let Err(err) = external::spawn(&command, &path) {
+            self.set_message(&format!("Command failed: {}: {}", err, command));
         }
         Ok(())
     }
@@ -1607,7 +2758,7 @@ impl Controller {
             .write(true)
             // make sure to not clobber downloaded files
             .create_new(true)
-            .open(&path);
+            .open(path);
         match open {
             Ok(mut file) => {
                 if let Err(why) = file.write_all(content.as_bytes()) {
@@ -1707,11 +2858,35 @@ impl Controller {
         }
     }
 
+    /// Shows the progress of loading a page, unless loading has finished
+    /// in the meantime
+    fn show_progress(&self, progress: &str) {
+        if self.loading.lock().unwrap().is_some() {
+            // progress is not worth keeping in the message history
+            self.display_message(progress);
+        }
+    }
+
     /// Sets message for statusbar
     pub fn set_message(&self, msg: &str) {
+        let msg = crate::text::sanitize(msg);
+        if !msg.is_empty() {
+            let mut history = self.message_history.lock().unwrap();
+            if history.len() == MESSAGE_HISTORY_SIZE {
+                history.pop_front();
+            }
+            let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+            history.push_back((now, msg.clone()));
+        }
+        self.display_message(&msg);
+    }
+
+    /// Shows a message in the status bar
+    fn display_message(&self, msg: &str) {
         let mut message = self.message.write().unwrap();
         message.clear();
-        message.push_str(msg);
+        // messages may contain error messages of servers
+        message.push_str(&crate::text::sanitize(msg));
         self.sender
             .send(Box::new(move |app| {
                 // Send a no-op callback to trigger a refresh
@@ -1721,6 +2896,91 @@ impl Controller {
             .unwrap();
     }
 
+    /// Adds a download to the downloads panel
+    fn download_started(&self, local_filename: String, token: CancelToken) {
+        self.set_message(&format!(
+            "Downloading {} (press D to show downloads)",
+            local_filename
+        ));
+        let mut downloads = self.downloads.lock().unwrap();
+        // a failed download may be retried under the same name
+        downloads.retain(|d| d.filename != local_filename);
+        downloads.push(Download::new(local_filename, token));
+    }
+
+    /// Updates the number of bytes received of a download
+    fn download_progress(app: &mut Cursive, local_filename: &str, received: usize) {
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        if let Some(download) = controller
+            .downloads
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .find(|d| d.filename == local_filename)
+        {
+            download.received = received;
+        }
+        crate::ui::dialogs::refresh_downloads(app);
+    }
+
+    /// Sets the state of a download that is no longer active
+    fn download_ended(&self, local_filename: &str, received: usize, state: DownloadState) {
+        if let Some(download) = self
+            .downloads
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .find(|d| d.filename == local_filename)
+        {
+            download.received = received;
+            download.end(state);
+        }
+        self.sender
+            .send(Box::new(crate::ui::dialogs::refresh_downloads))
+            .unwrap();
+    }
+
+    /// Marks a download as finished and notifies the user if they have
+    /// moved on to another page in the meantime.
+    fn download_finished(&self, url: &Url, local_filename: &str, received: usize) {
+        self.download_ended(local_filename, received, DownloadState::Finished);
+        if *self.current_url.lock().unwrap() != *url {
+            notify(Event::Download, "Download finished", local_filename);
+        }
+    }
+
+    /// Marks a download as failed, or as cancelled if the user cancelled
+    /// it, in which case the partial file is removed
+    fn download_failed(&self, local_filename: &str, received: usize, error: String) {
+        let cancelled = self
+            .downloads
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|d| d.filename == local_filename && d.token.is_cancelled());
+        if cancelled {
+            std::fs::remove_file(local_filename).ok();
+            self.download_ended(local_filename, received, DownloadState::Cancelled);
+            self.set_message(&format!("Download of {} cancelled", local_filename));
+        } else {
+            self.download_ended(local_filename, received, DownloadState::Failed(error.clone()));
+            self.set_message(&format!("Download of {} failed: {}", local_filename, error));
+        }
+    }
+
+    /// Aborts an active download
+    pub(crate) fn cancel_download(&self, local_filename: &str) {
+        if let Some(download) = self
+            .downloads
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|d| d.filename == local_filename && d.is_active())
+        {
+            download.token.cancel();
+        }
+    }
+
     pub fn get_selected_item_index(app: &mut Cursive) -> usize {
         if let Some(content) = app.find_name::<SelectView<GopherMapEntry>>("content") {
             content.selected_id()
@@ -1732,9 +2992,318 @@ impl Controller {
         .unwrap_or(0)
     }
 
-    pub fn add_bookmark_action(&mut self, url: Url, title: String, tags: String) {
-        let tags = tags.as_str().split_whitespace().map(String::from).collect();
-        let b = Bookmark { title, url, tags };
+    /// Wraps the current content again when the terminal width has
+    /// changed and keeps the selected line visible. A selected link stays
+    /// selected, other lines keep their relative position on the page.
+    pub fn reflow_action(app: &mut Cursive) {
+        let (len, link) = if let Some(view) =
+            app.find_name::<SelectView<GopherMapEntry>>("content")
+        {
+            let link = view
+                .selection()
+                .filter(|entry| !entry.item_type.is_inline())
+                .map(|entry| entry.url.clone());
+            (view.len(), link)
+        } else if let Some(view) = app.find_name::<SelectView<Option<Url>>>("gemini_content") {
+            (view.len(), view.selection().and_then(|url| (*url).clone()))
+        } else {
+            return;
+        };
+        let index = Controller::get_selected_item_index(app);
+        let width = app.screen_size().x;
+
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        if width == controller.rendered_width {
+            return;
+        }
+        let content = controller.content.lock().unwrap().clone();
+        match controller.rendering.clone() {
+            Some(Rendering::Gopher(item_type)) => {
+                controller.set_gopher_content(item_type, content, index)
+            }
+            Some(Rendering::Gemini {
+                url,
+                gemini_type,
+                cert_fingerprint,
+            }) => controller.set_gemini_content(url, gemini_type, content, index, cert_fingerprint),
+            None => return,
+        }
+
+        // runs after the content has been rendered
+        controller
+            .sender
+            .send(Box::new(move |app| {
+                fn reselect<T: 'static>(
+                    app: &mut Cursive,
+                    name: &str,
+                    is_link: impl Fn(&T) -> bool,
+                    index: usize,
+                    len: usize,
+                ) {
+                    let mut view = match app.find_name::<SelectView<T>>(name) {
+                        Some(view) => view,
+                        None => return,
+                    };
+                    let new_index = view
+                        .iter()
+                        .position(|(_, item)| is_link(item))
+                        .unwrap_or_else(|| index * view.len() / len.max(1));
+                    view.set_selection(new_index);
+                    let mut scroll_view = app
+                        .find_name::<ScrollView<ResizedView<NamedView<SelectView<T>>>>>(
+                            &format!("{}_scroll", name),
+                        )
+                        .expect("scroll view missing");
+                    scroll_view.set_offset(cursive::Vec2::new(0, new_index));
+                }
+                reselect(
+                    app,
+                    "content",
+                    |entry: &GopherMapEntry| Some(&entry.url) == link.as_ref(),
+                    index,
+                    len,
+                );
+                reselect(
+                    app,
+                    "gemini_content",
+                    |url: &Option<Url>| link.is_some() && url == &link,
+                    index,
+                    len,
+                );
+            }))
+            .unwrap();
+    }
+
+    /// Shows or hides the item type column of gophermaps
+    pub fn toggle_type_labels_action(app: &mut Cursive) {
+        let mut settings = SETTINGS.write().unwrap();
+        settings.config.show_type_labels = !settings.config.show_type_labels;
+        drop(settings);
+        Controller::render_again(app);
+    }
+
+    /// Turns reading mode on or off. It hides the menubar, title and
+    /// statusbar and centers the text at the textwrap width.
+    pub fn toggle_reading_mode_action(app: &mut Cursive) {
+        let (reading, current_view) = app
+            .call_on_name("main", |v: &mut Layout| {
+                (v.reading_column().is_some(), v.get_current_view())
+            })
+            .expect("main layout missing");
+        let column = if reading {
+            None
+        } else {
+            let settings = SETTINGS.read().unwrap();
+            // the text and its left margin, as when rendering
+            let margin = match current_view.as_str() {
+                "content" if settings.config.show_type_labels => {
+                    settings.config.type_label_width() + 2
+                }
+                "content" => 0,
+                _ => 8,
+            };
+            let textwrap = settings.config.textwrap.parse().unwrap_or(usize::MAX);
+            Some(textwrap.saturating_add(margin))
+        };
+        app.call_on_name("main", |v: &mut Layout| v.set_reading_column(column));
+        let autohide_menu = column.is_some() || SETTINGS.read().unwrap().config.autohide_menu;
+        app.set_autohide_menu(autohide_menu);
+        // the right pane is hidden, so the text may be wider
+        Controller::render_again(app);
+    }
+
+    /// Shows or hides the line numbers of text files
+    pub fn toggle_line_numbers_action(app: &mut Cursive) {
+        let mut settings = SETTINGS.write().unwrap();
+        settings.config.line_numbers = !settings.config.line_numbers;
+        drop(settings);
+        Controller::render_again(app);
+    }
+
+    /// Opens the bookmark in the quick access slot of a digit
+    pub fn open_bookmark_slot_action(app: &mut Cursive, digit: char) {
+        let slot = match digit.to_digit(10) {
+            Some(slot @ 1..=9) => slot as u8,
+            _ => return,
+        };
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        let url = controller
+            .bookmarks
+            .lock()
+            .unwrap()
+            .in_slot(slot)
+            .map(|b| b.url.clone());
+        match url {
+            Some(url) => controller.open_url(url, true, 0),
+            None => controller.set_message(&format!("No bookmark in slot {}", slot)),
+        }
+    }
+
+    /// Selects the gophermap link with the number typed so far, so that
+    /// Enter opens it. Digits typed within a second of each other form
+    /// one number.
+    pub fn link_number_action(app: &mut Cursive, digit: char) {
+        if !SETTINGS.read().unwrap().config.number_links {
+            Controller::open_bookmark_slot_action(app, digit);
+            return;
+        }
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        let mut number = match controller.typed_link_number.take() {
+            Some((number, typed)) if typed.elapsed() < LINK_NUMBER_TIMEOUT => number,
+            _ => String::new(),
+        };
+        number.push(digit);
+        controller.typed_link_number = Some((number.clone(), Instant::now()));
+        let n: usize = number.parse().unwrap_or_default();
+
+        let mut view = match app.find_name::<SelectView<GopherMapEntry>>("content") {
+            Some(view) => view,
+            None => return,
+        };
+        let index = view
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, entry))| !entry.item_type.is_inline())
+            .nth(n.wrapping_sub(1))
+            .map(|(index, _)| index);
+        let message = match index {
+            Some(index) => {
+                view.set_selection(index);
+                drop(view);
+                app.find_name::<ScrollView<ResizedView<NamedView<SelectView<GopherMapEntry>>>>>(
+                    "content_scroll",
+                )
+                .expect("gopher scroll view missing")
+                .set_offset(cursive::Vec2::new(0, index));
+                format!("Link {}", n)
+            }
+            None => format!("No link {}", n),
+        };
+        app.user_data::<Controller>()
+            .expect("controller missing")
+            .set_message(&message);
+    }
+
+    /// Splits the screen to show text files selected in the gophermap
+    /// next to it, or closes the split
+    pub fn toggle_split_action(app: &mut Cursive) {
+        let split = app
+            .call_on_name("main", |v: &mut Layout| {
+                if v.has_preview_beside("content") {
+                    v.remove_preview();
+                    false
+                } else {
+                    let preview = TextView::new("").with_name("preview").scrollable();
+                    v.set_preview(preview, "Preview".to_string(), "content");
+                    true
+                }
+            })
+            .expect("main layout missing");
+        if split {
+            let selection = app
+                .find_name::<SelectView<GopherMapEntry>>("content")
+                .and_then(|view| view.selection());
+            if let Some(entry) = selection {
+                Controller::preview_action(app, &entry);
+            }
+        }
+        Controller::render_again(app);
+    }
+
+    /// Shows a gophermap entry in the preview pane, if the screen is split
+    pub(crate) fn preview_action(app: &mut Cursive, entry: &GopherMapEntry) {
+        let split = app
+            .call_on_name("main", |v: &mut Layout| v.has_preview_beside("content"))
+            .unwrap_or_default();
+        if !split {
+            return;
+        }
+        let title = entry.clone().label();
+        app.call_on_name("main", |v: &mut Layout| v.set_preview_title(title));
+        if !entry.item_type.is_text() {
+            let text = match entry.item_type {
+                ItemType::Inline => String::new(),
+                item_type => format!("{}\n\n{}", ItemType::as_str(item_type), entry.url),
+            };
+            app.call_on_name("preview", |v: &mut TextView| v.set_content(text));
+            return;
+        }
+        app.call_on_name("preview", |v: &mut TextView| v.set_content("Loading ..."));
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        controller.fetch_preview(entry.url.clone());
+    }
+
+    /// Fetches the beginning of a text file for the preview pane. The
+    /// transfer of the file previewed before is aborted.
+    fn fetch_preview(&self, url: Url) {
+        let sender = self.sender.clone();
+        let token = CancelToken::new();
+        {
+            let mut guard = self.preview_token.lock().unwrap();
+            guard.cancel();
+            *guard = token.clone();
+        }
+        let transport = Cancellable::new(self.transport.clone(), token.clone());
+
+        thread::spawn(move || {
+            let mut buf = vec![];
+            let fetched = gopher::connect(&transport, &url)
+                .and_then(|stream| stream.take(PREVIEW_SIZE).read_to_end(&mut buf));
+            if token.is_cancelled() {
+                return;
+            }
+            let text = match fetched {
+                Ok(_) => crate::text::sanitize(&crate::text::decode(&buf)),
+                Err(err) => format!("Could not fetch {}: {}", url, err),
+            };
+            sender
+                .send(Box::new(move |app| {
+                    // the selection may have moved on in the meantime
+                    let selected = app
+                        .find_name::<SelectView<GopherMapEntry>>("content")
+                        .and_then(|view| view.selection())
+                        .is_some_and(|entry| entry.url == url);
+                    if selected {
+                        app.call_on_name("preview", |v: &mut TextView| v.set_content(text));
+                    }
+                }))
+                .unwrap();
+        });
+    }
+
+    /// Renders the current page again, e.g. after display settings have
+    /// changed
+    pub(crate) fn render_again(app: &mut Cursive) {
+        app.user_data::<Controller>()
+            .expect("controller missing")
+            .rendered_width = 0;
+        Controller::reflow_action(app);
+    }
+
+    /// Downloads gophermap entries to the download directory
+    pub fn download_entries(&mut self, entries: Vec<GopherMapEntry>) {
+        for entry in entries {
+            let item_type = entry.item_type;
+            if entry.url.scheme() != "gopher"
+                || item_type.is_inline()
+                || item_type.is_query()
+                || item_type.is_telnet()
+            {
+                continue;
+            }
+            let filename = download_filename_from_url(&entry.url);
+            self.fetch_binary_url(entry.url, item_type, filename);
+        }
+    }
+
+    pub fn add_bookmark_action(
+        &mut self,
+        url: Url,
+        title: String,
+        tags: String,
+        mirrors: Vec<Url>,
+    ) {
+        let b = Controller::new_bookmark(url, title, &tags, mirrors);
 
         let mut bookmarks = self.bookmarks.lock().unwrap();
 
@@ -1750,9 +3319,9 @@ impl Controller {
                     .expect("bookmarks menu missing");
                 if let Some(i) = index {
                     // replace element
-                    // add 3 to account for "Edit..." etc.
-                    menu.remove(i + 3);
-                    menu.insert_leaf(i + 3, &b.title, move |app| {
+                    // account for "Edit..." etc.
+                    menu.remove(i + BOOKMARK_MENU_ITEMS);
+                    menu.insert_leaf(i + BOOKMARK_MENU_ITEMS, &b.title, move |app| {
                         app.user_data::<Controller>()
                             .expect("controller missing")
                             .open_url(url.clone(), true, 0);
@@ -1769,32 +3338,144 @@ impl Controller {
             .unwrap();
     }
 
+    /// Replaces the bookmark with the URL `original` by an edited one and
+    /// refreshes the bookmarks menu
+    pub fn update_bookmark_action(
+        app: &mut Cursive,
+        original: &Url,
+        url: Url,
+        title: String,
+        tags: String,
+        mirrors: Vec<Url>,
+    ) {
+        let b = Controller::new_bookmark(url, title, &tags, mirrors);
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        let mut guard = controller.bookmarks.lock().unwrap();
+        guard.replace(original, b.clone());
+        let bookmarks = guard.entries.clone();
+        drop(guard);
+        controller.set_message(&format!("Bookmark {} updated", b.title));
+        crate::ui::setup::setup_bookmark_menu(app, &bookmarks);
+    }
+
+    /// Tags may be separated by commas or whitespace
+    fn new_bookmark(url: Url, title: String, tags: &str, mirrors: Vec<Url>) -> Bookmark {
+        let tags = tags
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|tag| !tag.is_empty())
+            .map(String::from)
+            .collect();
+        Bookmark {
+            title,
+            url,
+            tags,
+            mirrors,
+            slot: None,
+        }
+    }
+
+    /// Saves the encrypted history every HISTORY_SAVE_INTERVAL, as it is
+    /// kept in memory otherwise
+    fn save_history_periodically(&self) {
+        let sender = self.sender.clone();
+        thread::spawn(move || loop {
+            thread::sleep(HISTORY_SAVE_INTERVAL);
+            let saved = sender.send(Box::new(|app| {
+                let controller = app.user_data::<Controller>().expect("controller missing");
+                let result = controller.history.lock().unwrap().save_in_background();
+                if let Err(err) = result {
+                    warn!("Could not save history: {}", err);
+                }
+            }));
+            // the main loop has ended
+            if saved.is_err() {
+                break;
+            }
+        });
+    }
+
+    /// Syncs the bookmarks with another computer in the background using
+    /// the bookmark_sync_pull and bookmark_sync_push commands
+    pub fn sync_bookmarks(&self) {
+        let (pull, push) = {
+            let settings = SETTINGS.read().unwrap();
+            (
+                settings.config.bookmark_sync_pull.clone(),
+                settings.config.bookmark_sync_push.clone(),
+            )
+        };
+        if pull.is_empty() || push.is_empty() {
+            self.set_message("Set bookmark_sync_pull and bookmark_sync_push to sync bookmarks");
+            return;
+        }
+        self.set_message("Syncing bookmarks...");
+        let bookmarks = self.bookmarks.clone();
+        let sender = self.sender.clone();
+        thread::spawn(move || {
+            let result = sync_bookmarks_with(&bookmarks, &pull, &push);
+            sender
+                .send(Box::new(move |app| {
+                    let controller = app.user_data::<Controller>().expect("controller missing");
+                    match result {
+                        Ok(changes) => controller
+                            .set_message(&format!("Bookmarks synced, {} changed", changes)),
+                        Err(err) => {
+                            controller.set_message(&format!("Could not sync bookmarks: {}", err))
+                        }
+                    }
+                    let entries = controller.bookmarks.lock().unwrap().get_bookmarks();
+                    crate::ui::setup::setup_bookmark_menu(app, &entries);
+                }))
+                .unwrap();
+        });
+    }
+
+    /// Moves a bookmark up or down in the order of the bookmarks and the
+    /// bookmarks menu. Returns its new position.
+    pub fn move_bookmark_action(app: &mut Cursive, url: &Url, offset: isize) -> Option<usize> {
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        let mut guard = controller.bookmarks.lock().unwrap();
+        let position = guard.move_by(url, offset);
+        let bookmarks = guard.entries.clone();
+        drop(guard);
+        crate::ui::setup::setup_bookmark_menu(app, &bookmarks);
+        position
+    }
+
+    /// Moves a bookmark to the trash
     pub fn remove_bookmark_action(app: &mut Cursive, b: Bookmark) {
-        let mut guard = app
-            .user_data::<Controller>()
-            .expect("controller missing")
-            .bookmarks
-            .lock()
-            .unwrap();
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        let mut guard = controller.bookmarks.lock().unwrap();
         guard.remove(&b.url);
         let bookmarks = guard.entries.clone();
         drop(guard);
+        controller.set_message(&format!(
+            "Moved bookmark {} to the trash. Press u to undo.",
+            b.title
+        ));
+        crate::ui::setup::setup_bookmark_menu(app, &bookmarks);
+    }
 
-        // redraw bookmark menu
-        let menutree = app
-            .menubar()
-            .find_subtree("Bookmarks")
-            .expect("bookmarks menu missing");
-        menutree.clear();
-        // re-add all bookmark entries
-        // respecting the order so add_bookmark_action works correctly
-        for entry in bookmarks.iter().rev() {
-            let url = entry.url.clone();
-            menutree.insert_leaf(3, &b.title, move |app| {
-                app.user_data::<Controller>()
-                    .expect("controller missing")
-                    .open_url(url.clone(), true, 0);
-            });
+    /// Restores a bookmark from the trash
+    pub fn restore_bookmark_action(app: &mut Cursive, url: &Url) {
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        let mut guard = controller.bookmarks.lock().unwrap();
+        let restored = guard.restore(url);
+        let bookmarks = guard.entries.clone();
+        drop(guard);
+        if let Some(b) = restored {
+            controller.set_message(&format!("Restored bookmark {}", b.title));
+        }
+        crate::ui::setup::setup_bookmark_menu(app, &bookmarks);
+    }
+
+    /// Restores the most recently deleted bookmark
+    pub fn undo_remove_bookmark_action(app: &mut Cursive) {
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        let last = controller.bookmarks.lock().unwrap().trash.last().cloned();
+        match last {
+            Some(b) => Controller::restore_bookmark_action(app, &b.url),
+            None => controller.set_message("The bookmark trash is empty"),
         }
     }
 
@@ -1835,6 +3516,177 @@ impl Controller {
         }
     }
 
+    /// Shows the selected gopher item as text even if it is a binary
+    /// file, e.g. a log file with the wrong item type
+    pub fn view_as_text_action(app: &mut Cursive) {
+        let selected = crate::ui::setup::selected_url(app);
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        let url = selected.unwrap_or_else(|| controller.current_url.lock().unwrap().clone());
+        match url.scheme() {
+            "gopher" => {
+                let index = Controller::get_selected_item_index(app);
+                let controller = app.user_data::<Controller>().expect("controller missing");
+                controller.add_to_history(url.clone(), index);
+                *controller.current_url.lock().unwrap() = url.clone();
+                controller.set_message("Loading ...");
+                controller.fetch_url(url, ItemType::File, 0);
+            }
+            "file" => controller.open_url(url, true, 0),
+            _ => controller.set_message("Only gopher items and local files can be viewed as text"),
+        }
+    }
+
+    /// Starts reading the current page aloud, or pauses or resumes
+    /// reading if it has already started
+    pub fn read_aloud_action(app: &mut Cursive) {
+        let gophermap = app
+            .find_name::<Layout>("main")
+            .expect("main layout missing")
+            .get_current_view()
+            == "content";
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        if let Some(reader) = controller.reader.as_ref().filter(|r| !r.is_stopped()) {
+            let message = if reader.toggle_pause() {
+                "Reading paused"
+            } else {
+                "Reading resumed"
+            };
+            controller.set_message(message);
+            return;
+        }
+        let command = SETTINGS.read().unwrap().config.tts_command.clone();
+        if command.is_empty() {
+            controller.set_message("No command for reading aloud defined.");
+            return;
+        }
+        let content = controller.content.lock().unwrap().clone();
+        let text = if gophermap {
+            // links are read as sentences of their own
+            content
+                .lines()
+                .filter_map(|line| GopherMapEntry::parse(line.to_string()).ok())
+                .map(|entry| match entry.item_type {
+                    ItemType::Inline => entry.name,
+                    _ => format!("\n{}\n", entry.name),
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        } else {
+            ncgopher_core::gemini::plain_text(&content)
+        };
+        controller.reader = Some(Reader::start(command, &text));
+        controller.set_message("Reading aloud. R: pause/resume, S: stop");
+    }
+
+    pub fn stop_reading_action(app: &mut Cursive) {
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        if let Some(reader) = controller.reader.take() {
+            reader.stop();
+            controller.set_message("Reading stopped");
+        }
+    }
+
+    /// Shows the rendered page in $PAGER, less by default, with the user
+    /// interface suspended
+    pub fn open_in_pager_action(app: &mut Cursive) {
+        let lines = if let Some(view) = app.find_name::<SelectView<GopherMapEntry>>("content") {
+            view.iter().map(|(label, _)| label.to_string()).collect::<Vec<_>>()
+        } else if let Some(view) = app.find_name::<SelectView<Option<Url>>>("gemini_content") {
+            view.iter().map(|(label, _)| label.to_string()).collect::<Vec<_>>()
+        } else {
+            return;
+        };
+        let pager = std::env::var("PAGER").unwrap_or_else(|_| "less".to_string());
+        let path = external::temp_dir().join("page.txt");
+        let result = std::fs::write(&path, lines.join("\n") + "\n")
+            .and_then(|_| external::run_in_foreground(app, &pager, &path.to_string_lossy()));
+        std::fs::remove_file(&path).ok();
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        match result {
+            Ok(status) if !status.success() => {
+                controller.set_message(&format!("{} exited with {}", pager, status))
+            }
+            Ok(_) => (),
+            Err(err) => controller.set_message(&format!("Could not open pager {}: {}", pager, err)),
+        }
+    }
+
+    /// Pins an encoding for the host of the current page and reloads it.
+    /// If `remember` is set, the encoding is saved in the configuration.
+    pub fn reload_with_encoding_action(app: &mut Cursive, encoding: &str, remember: bool) {
+        let index = Controller::get_selected_item_index(app);
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        let current_url = controller.current_url.lock().unwrap().clone();
+        let host = match current_url.host_str() {
+            Some(host) => host.to_string(),
+            None => {
+                controller.set_message("The current page has no host");
+                return;
+            }
+        };
+        SETTINGS
+            .write()
+            .unwrap()
+            .config
+            .encodings
+            .insert(host, encoding.to_string());
+        if remember {
+            if let Err(why) = SETTINGS.write().unwrap().write_settings_to_file() {
+                controller.set_message(&format!("Could not write config file: {}", why));
+            }
+        }
+        controller.open_url(current_url, false, index);
+    }
+
+    /// Switches the current host between code page 437 and the encoding
+    /// detected automatically, for the rest of the session
+    pub fn toggle_cp437_action(app: &mut Cursive) {
+        let index = Controller::get_selected_item_index(app);
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        let current_url = controller.current_url.lock().unwrap().clone();
+        let host = match current_url.host_str() {
+            Some(host) => host.to_string(),
+            None => {
+                controller.set_message("The current page has no host");
+                return;
+            }
+        };
+        {
+            // open_url reads the settings, so the lock must be released first
+            let encodings = &mut SETTINGS.write().unwrap().config.encodings;
+            if encodings.get(&host).map(String::as_str) == Some("cp437") {
+                encodings.remove(&host);
+            } else {
+                encodings.insert(host, "cp437".to_string());
+            }
+        }
+        controller.open_url(current_url, false, index);
+    }
+
+    /// Sends a query to a gopher index-search server (item type 7)
+    pub fn query_action(app: &mut Cursive, mut url: Url, query: &str) {
+        let mut path = url.path().to_string();
+        path.push_str("%09");
+        path.push_str(query);
+        url.set_path(path.as_str());
+
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        controller.set_message("Loading ...");
+        controller.fetch_url(url, ItemType::Dir, 0);
+    }
+
+    /// Opens the result of a CSO phonebook query. The query is added to
+    /// the selector after a tab, as for index searches.
+    pub fn cso_query_action(app: &mut Cursive, mut url: Url, query: &str) {
+        let mut path = url.path().split("%09").next().unwrap_or_default().to_string();
+        path.push_str("%09");
+        path.push_str(query.trim());
+        url.set_path(path.as_str());
+
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        controller.open_url(url, true, 0);
+    }
+
     pub fn open_url_action(app: &mut Cursive, url: &str) {
         let controller = app.user_data::<Controller>().expect("controller missing");
         match Url::parse(url) {
@@ -1843,32 +3695,139 @@ impl Controller {
         }
     }
 
+    /// Returns the navigation history with the selected line of each
+    /// page, `index` being the selected line of the current page
+    fn session(&self, index: usize) -> Session {
+        let mut history = self.history.lock().unwrap();
+        history.update_selected_item(index);
+        let mut page = history
+            .stack
+            .iter()
+            .map(|h| SessionPage {
+                url: h.url.clone(),
+                position: h.position,
+            })
+            .collect::<Vec<_>>();
+        drop(history);
+        if page.is_empty() {
+            // history is disabled
+            page.push(SessionPage {
+                url: self.current_url.lock().unwrap().clone(),
+                position: index,
+            });
+        }
+        Session { page }
+    }
+
+    /// Saves the navigation history with the selected line of each page
+    /// to a file
+    pub fn export_session_action(app: &mut Cursive, path: &str) {
+        let index = Controller::get_selected_item_index(app);
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        match controller.session(index).write(std::path::Path::new(path)) {
+            Ok(()) => controller.set_message(&format!("Session saved to {}", path)),
+            Err(err) => controller.set_message(&format!("Could not save session: {}", err)),
+        }
+    }
+
+    /// Replaces the navigation history with the one saved in a file and
+    /// opens its current page
+    pub fn import_session_action(app: &mut Cursive, path: &str) {
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        match Session::read(std::path::Path::new(path)) {
+            Ok(session) => controller.restore_session(session),
+            Err(err) => controller.set_message(&format!("Could not read session: {}", err)),
+        }
+    }
+
+    /// Saves the open pages when quitting, to restore them on the next
+    /// start if the restore_session setting is "ask" or "always"
+    pub fn save_last_session(app: &mut Cursive) {
+        if !matches!(SETTINGS.read().unwrap().config.restore_session.as_str(), "ask" | "always") {
+            return;
+        }
+        let index = Controller::get_selected_item_index(app);
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        if let Err(err) = controller.session(index).write_last(controller.vault.as_deref()) {
+            eprintln!("Could not save session: {}", err);
+        }
+    }
+
+    /// Restores the pages open when ncgopher last quit, or asks whether to
+    /// restore them, depending on the restore_session setting
+    pub fn offer_last_session(app: &mut Cursive) {
+        let mode = SETTINGS.read().unwrap().config.restore_session.clone();
+        if !matches!(mode.as_str(), "ask" | "always") {
+            return;
+        }
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        let session = match Session::read_last(controller.vault.as_deref()) {
+            Some(session) if !session.page.is_empty() => session,
+            _ => return,
+        };
+        if mode == "always" {
+            controller.restore_session(session);
+        } else {
+            crate::ui::dialogs::restore_last_session(app, session);
+        }
+    }
+
+    /// Replaces the navigation history with the one of a session and
+    /// opens its current page
+    pub(crate) fn restore_session(&mut self, session: Session) {
+        let current = match session.page.last() {
+            Some(page) => page.clone(),
+            None => {
+                self.set_message("The session is empty");
+                return;
+            }
+        };
+        if !SETTINGS.read().unwrap().config.disable_history {
+            let now = OffsetDateTime::now_local().unwrap_or(OffsetDateTime::now_utc());
+            self.history.lock().unwrap().stack = session
+                .page
+                .into_iter()
+                .map(|page| HistoryEntry {
+                    title: page.url.to_string(),
+                    url: page.url,
+                    timestamp: now,
+                    visited_count: 1,
+                    position: page.position,
+                })
+                .collect();
+        }
+        self.open_url(current.url, false, current.position);
+    }
+
     pub fn save_as_action(app: &mut Cursive, path: &str) {
         if !path.is_empty() {
             app.pop_layer();
+            app.user_data::<Controller>()
+                .expect("controller missing")
+                .save_page(path.to_string());
+        } else {
+            // do not pop the save dialog so user can make corrections
+            app.add_layer(Dialog::info("No filename given!"))
+        }
+    }
 
-            let path = path.to_string();
-            let controller = app.user_data::<Controller>().expect("controller missing");
-            controller.set_message(&format!("saving page as '{}'.", path));
+    /// Saves the current page to a file
+    pub fn save_page(&mut self, path: String) {
+        self.set_message(&format!("saving page as '{}'.", path));
 
-            let current_url = controller.current_url.lock().unwrap().clone();
+        let current_url = self.current_url.lock().unwrap().clone();
 
-            match current_url.scheme() {
-                "gopher" => {
-                    let item_type = ItemType::from_url(&current_url);
-                    match item_type {
-                        ItemType::Dir => controller.save_gophermap(path),
-                        ItemType::File => controller.save_textfile(path),
-                        _ => controller.set_message("cannot save this kind of page"),
-                    }
+        match current_url.scheme() {
+            "gopher" => {
+                let item_type = ItemType::from_url(&current_url);
+                match item_type {
+                    ItemType::Dir => self.save_gophermap(path),
+                    ItemType::File => self.save_textfile(path),
+                    _ => self.set_message("cannot save this kind of page"),
                 }
-                "about" | "gemini" => controller.save_gemini(path),
-                other => controller
-                    .set_message(&format!("failed to save page: unknown scheme {}", other)),
             }
-        } else {
-            // do not pop the save dialog so user can make corrections
-            app.add_layer(Dialog::info("No filename given!"))
+            "about" | "gemini" => self.save_gemini(path),
+            other => self.set_message(&format!("failed to save page: unknown scheme {}", other)),
         }
     }
 
@@ -1885,78 +3844,48 @@ impl Controller {
         self.client_certificates.lock().unwrap().update(cc, urls);
     }
 
+    /// Searches the current page and highlights the matches. The search
+    /// runs on every keystroke, so the selection stays on the selected
+    /// line as long as it matches and moves on to the next match otherwise.
     pub fn search(&mut self, search_str: String) {
+        let search_str = crate::text::normalize(&search_str);
         info!("Searching for {}", search_str);
         self.current_search = search_str.clone();
         let sender = self.sender.clone();
         sender
             .send(Box::new(move |app| {
-                let mut hits = Vec::new();
+                let mut originals = std::mem::take(
+                    &mut app
+                        .user_data::<Controller>()
+                        .expect("controller missing")
+                        .search_originals,
+                );
+                let hits;
                 if let Some(mut content) = app.find_name::<SelectView<GopherMapEntry>>("content") {
-                    for (index, listitem) in content.try_iter_mut().enumerate() {
-                        let (label, _item) = listitem; //(&mut SpannedString<Style>, &mut GopherMapEntry)
-                        let label_source = label.source();
-                        if !search_str.is_empty() && label_source.contains(&search_str) {
-                            hits.push(index);
-                            let split = label_source.split(&search_str);
-                            let mut l = StyledString::new();
-
-                            let vec: Vec<&str> = split.collect();
-                            for (pos, part) in vec.iter().enumerate() {
-                                l.append(*part);
-                                if pos != vec.len() - 1 {
-                                    //l.append_styled(&search_str, ColorStyle::new(Color::Dark(BaseColor::Red), ColorType::Palette(PaletteColor::Highlight)));
-                                    l.append_styled(&search_str, ColorStyle::highlight());
-                                }
-                            }
-                            *label = l.clone();
-                        } else {
-                            // This will remove previous search results
-                            let mut l = StyledString::new();
-                            l.append(label_source);
-                            *label = l.clone();
-                        }
-                    }
+                    hits = highlight_matches(&mut content, &mut originals, &search_str);
                     let scroll_view = app.find_name::<ScrollView<ResizedView<NamedView<SelectView<GopherMapEntry>>>>>(
                         "content_scroll",
                         ).expect("gopher scroll view missing");
-                    move_to_next_item(content, scroll_view, Direction::Next, hits.clone());
-                } else if let Some(mut content) = app.find_name::<SelectView<Option<Url>>>("gemini_content") {
-                    info!("Found gemini content!!!!");
-                    for (index, listitem) in content.try_iter_mut().enumerate() {
-                        let (label, _item) = listitem; //(&mut SpannedString<Style>, &mut GopherMapEntry)
-                        let label_source = label.source();
-                        if !search_str.is_empty() && label_source.contains(&search_str) {
-                            hits.push(index);
-                            let split = label_source.split(&search_str);
-                            let mut l = StyledString::new();
-
-                            let vec: Vec<&str> = split.collect();
-                            for (pos, part) in vec.iter().enumerate() {
-                                l.append(*part);
-                                if pos != vec.len() - 1 {
-                                    l.append_styled(&search_str, ColorStyle::highlight());
-                                }
-                            }
-                            *label = l.clone();
-                        } else {
-                            // This will remove previous search results
-                            let mut l = StyledString::new();
-                            l.append(label_source);
-                            *label = l.clone();
-                        }
+                    let selected = content.selected_id().unwrap_or(0);
+                    if !hits.contains(&selected) {
+                        move_to_next_item(content, scroll_view, Direction::Next, hits.clone());
                     }
+                } else if let Some(mut content) = app.find_name::<SelectView<Option<Url>>>("gemini_content") {
+                    hits = highlight_matches(&mut content, &mut originals, &search_str);
                     let scroll_view = app.find_name::<ScrollView<ResizedView<NamedView<SelectView<Option<Url>>>>>>(
                         "gemini_content_scroll",
                         ).expect("gemini scroll view missing");
-                    move_to_next_item(content, scroll_view, Direction::Next, hits.clone());
+                    let selected = content.selected_id().unwrap_or(0);
+                    if !hits.contains(&selected) {
+                        move_to_next_item(content, scroll_view, Direction::Next, hits.clone());
+                    }
                 } else {
                     unreachable!("view content and gemini_content missing");
                 }
                 info!("Found hits: {:?}", hits);
-                app.user_data::<Controller>()
-                    .expect("controller missing")
-                    .set_search_hits(hits.clone());
+                let controller = app.user_data::<Controller>().expect("controller missing");
+                controller.search_originals = originals;
+                controller.set_search_hits(hits);
             })).unwrap();
     }
 
@@ -0,0 +1,58 @@
+use std::time::Instant;
+
+use crate::transport::CancelToken;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DownloadState {
+    Active,
+    Finished,
+    Failed(String),
+    Cancelled,
+}
+
+/// A download of a binary file, shown in the downloads panel
+#[derive(Clone)]
+pub struct Download {
+    /// Local file the download is written to
+    pub filename: String,
+    /// Bytes received so far
+    pub received: usize,
+    pub state: DownloadState,
+    started: Instant,
+    ended: Option<Instant>,
+    /// Aborts the transfer
+    pub token: CancelToken,
+}
+
+impl Download {
+    pub fn new(filename: String, token: CancelToken) -> Self {
+        Download {
+            filename,
+            received: 0,
+            state: DownloadState::Active,
+            started: Instant::now(),
+            ended: None,
+            token,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.state == DownloadState::Active
+    }
+
+    /// Marks the download as no longer active
+    pub fn end(&mut self, state: DownloadState) {
+        self.state = state;
+        self.ended = Some(Instant::now());
+    }
+
+    /// Returns the average transfer rate in bytes per second
+    pub fn speed(&self) -> usize {
+        let end = self.ended.unwrap_or_else(Instant::now);
+        let seconds = end.duration_since(self.started).as_secs_f64();
+        if seconds < 0.1 {
+            return 0;
+        }
+        (self.received as f64 / seconds) as usize
+    }
+}
@@ -0,0 +1,243 @@
+use cursive::Cursive;
+use std::io::{self, BufRead, BufReader, Read};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::SETTINGS;
+
+/// Splits a command line into words. Single and double quotes and
+/// backslashes work like in a POSIX shell, but there are no expansions
+/// and the command is never passed to a shell.
+fn split_command_line(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    word.push(c);
+                }
+            }
+            '"' => {
+                in_word = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => word.extend(chars.next()),
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                word.extend(chars.next());
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// Builds the command for a command line from the settings. Every `%s`
+/// in the arguments is replaced by the target, if there is none the
+/// target is appended as last argument. The command runs in the
+/// download directory.
+pub fn command(command_line: &str, target: &str) -> io::Result<Command> {
+    let mut words = split_command_line(command_line).into_iter();
+    let program = words
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+    let mut args = words.collect::<Vec<_>>();
+    if args.iter().any(|arg| arg.contains("%s")) {
+        for arg in args.iter_mut() {
+            *arg = arg.replace("%s", target);
+        }
+    } else {
+        args.push(target.to_string());
+    }
+
+    let mut command = Command::new(program);
+    command.args(args);
+    let download_path = SETTINGS.read().unwrap().config.download_path.clone();
+    if Path::new(&download_path).is_dir() {
+        command.current_dir(download_path);
+    }
+    Ok(command)
+}
+
+/// Returns the directory for temporary files of this process, e.g.
+/// images shown in the image viewer. It is removed on exit.
+pub fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ncgopher-{}", std::process::id()));
+    if let Err(err) = fs::create_dir_all(&dir) {
+        warn!("Could not create temporary directory {}: {}", dir.display(), err);
+    }
+    dir
+}
+
+/// Removes the temporary files of this process
+pub fn remove_temp_dir() {
+    let dir = std::env::temp_dir().join(format!("ncgopher-{}", std::process::id()));
+    if dir.exists() {
+        fs::remove_dir_all(&dir)
+            .unwrap_or_else(|err| warn!("Could not remove {}: {}", dir.display(), err));
+    }
+}
+
+/// Returns the configured timeout for external commands, if any
+fn timeout() -> Option<Duration> {
+    match SETTINGS.read().unwrap().config.command_timeout {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    }
+}
+
+/// Waits for a child process, killing it once the timeout has passed.
+fn wait_with_timeout(child: &mut Child, timeout: Option<Duration>) -> io::Result<ExitStatus> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return child.wait(),
+    };
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if start.elapsed() > timeout {
+            warn!("Killing external command {} after {:?}", child.id(), timeout);
+            child.kill()?;
+            return child.wait();
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Writes everything a child process prints to the debug log
+fn log_output<R: Read + Send + 'static>(pid: u32, output: R) {
+    thread::spawn(move || {
+        for line in BufReader::new(output).lines().map_while(Result::ok) {
+            info!("[{}] {}", pid, line);
+        }
+    });
+}
+
+/// Runs an external command in the background, e.g. a browser or image
+/// viewer. Its output goes to the debug log.
+pub fn spawn(command_line: &str, target: &str) -> io::Result<()> {
+    let mut child = command(command_line, target)?
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let pid = child.id();
+    info!("Started external command {}: {} {}", pid, command_line, target);
+    if let Some(stdout) = child.stdout.take() {
+        log_output(pid, stdout);
+    }
+    if let Some(stderr) = child.stderr.take() {
+        log_output(pid, stderr);
+    }
+    thread::spawn(move || match wait_with_timeout(&mut child, timeout()) {
+        Ok(status) => info!("External command {} exited with {}", pid, status),
+        Err(err) => warn!("Could not wait for external command {}: {}", pid, err),
+    });
+    Ok(())
+}
+
+/// Runs an external command and waits for it, e.g. to copy a file. Its
+/// output goes to the debug log.
+pub fn run(command_line: &str, target: &str) -> io::Result<ExitStatus> {
+    let mut child = command(command_line, target)?
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let pid = child.id();
+    info!("Started external command {}: {} {}", pid, command_line, target);
+    if let Some(stdout) = child.stdout.take() {
+        log_output(pid, stdout);
+    }
+    if let Some(stderr) = child.stderr.take() {
+        log_output(pid, stderr);
+    }
+    let status = wait_with_timeout(&mut child, timeout())?;
+    info!("External command {} exited with {}", pid, status);
+    Ok(status)
+}
+
+/// Runs an external command in the background that reads data from its
+/// standard input, e.g. a media player playing a stream while it is
+/// being received. `-` is passed as target, which most players take as
+/// standard input.
+pub fn spawn_with_input<R: Read + Send + 'static>(
+    command_line: &str,
+    mut input: R,
+) -> io::Result<()> {
+    let mut child = command(command_line, "-")?
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let pid = child.id();
+    info!("Started external command {}: {} -", pid, command_line);
+    if let Some(stdout) = child.stdout.take() {
+        log_output(pid, stdout);
+    }
+    if let Some(stderr) = child.stderr.take() {
+        log_output(pid, stderr);
+    }
+    let mut stdin = child.stdin.take().expect("stdin not piped");
+    thread::spawn(move || {
+        // fails with a broken pipe when the player is closed early
+        match io::copy(&mut input, &mut stdin) {
+            Ok(bytes) => info!("Streamed {} bytes to external command {}", bytes, pid),
+            Err(err) => info!("Stopped streaming to external command {}: {}", pid, err),
+        }
+        drop(stdin);
+        match child.wait() {
+            Ok(status) => info!("External command {} exited with {}", pid, status),
+            Err(err) => warn!("Could not wait for external command {}: {}", pid, err),
+        }
+    });
+    Ok(())
+}
+
+/// Runs an interactive external command, e.g. a telnet client or an
+/// editor, with the terminal handed over to it. There is no timeout as
+/// the user is in control. The user interface is restored afterwards,
+/// even if the command fails.
+pub fn run_in_foreground(
+    app: &mut Cursive,
+    command_line: &str,
+    target: &str,
+) -> io::Result<ExitStatus> {
+    let mut command = command(command_line, target)?;
+    crate::ui::terminal::suspend();
+    let status = command
+        .spawn()
+        .and_then(|mut child| child.wait());
+    crate::ui::terminal::resume(app);
+    info!("External command {} exited with {:?}", command_line, status);
+    status
+}
@@ -7,9 +7,13 @@ use url::Url;
 pub enum GeminiType {
     Text,
     Gemini,
+    Markdown,
 }
 
+/// Renders gemtext as rows wrapped to the viewport width. Rows of links
+/// carry their URL.
 pub fn parse(text: &str, base_url: &Url, viewport_width: usize) -> Vec<(String, Option<Url>)> {
+    let mut alt_texts = ncgopher_core::gemini::preformatted_alt_texts(text).into_iter();
     let mut nodes = gemtext::parse(text);
     nodes
         .drain(..)
@@ -91,10 +95,16 @@ pub fn parse(text: &str, base_url: &Url, viewport_width: usize) -> Vec<(String,
                 }
                 Node::ListItem(text) => continuation_lines("*", &text, None),
                 Node::Preformatted(lines) => {
+                    // show the alt-text as a caption above the block, it is
+                    // the only readable description of e.g. ASCII art
+                    let caption = alt_texts
+                        .next()
+                        .filter(|alt| !alt.is_empty())
+                        .map(|alt| (format!("  ```  {}", alt), None));
                     // preformatted lines should not be wrapped
-                    lines
-                        .lines()
-                        .map(|line| (format!("    @  {}", line), None))
+                    caption
+                        .into_iter()
+                        .chain(lines.lines().map(|line| (format!("    @  {}", line), None)))
                         .collect()
                 }
             }
@@ -0,0 +1,76 @@
+use cursive::utils::markup::StyledString;
+use url::Url;
+
+#[cfg(feature = "syntax-highlighting")]
+mod syntect_impl {
+    use cursive::theme::{Color, Style};
+    use cursive::utils::markup::StyledString;
+    use lazy_static::lazy_static;
+    use syntect::easy::HighlightLines;
+    use syntect::highlighting::ThemeSet;
+    use syntect::parsing::{SyntaxReference, SyntaxSet};
+    use syntect::util::LinesWithEndings;
+    use url::Url;
+
+    lazy_static! {
+        static ref SYNTAXES: SyntaxSet = SyntaxSet::load_defaults_newlines();
+        static ref THEMES: ThemeSet = ThemeSet::load_defaults();
+    }
+
+    /// Extensions of text files that are not source code
+    const PLAIN_EXTENSIONS: &[&str] = &["txt", "text", "md", "markdown", "gmi", "gemini"];
+
+    fn syntax_for(url: &Url) -> Option<&'static SyntaxReference> {
+        let name = url.path_segments()?.next_back()?;
+        let (_, extension) = name.rsplit_once('.')?;
+        let extension = extension.to_lowercase();
+        if PLAIN_EXTENSIONS.contains(&extension.as_str()) {
+            return None;
+        }
+        SYNTAXES.find_syntax_by_extension(&extension)
+    }
+
+    pub fn highlight(url: &Url, text: &str) -> Option<Vec<(StyledString, Option<Url>)>> {
+        let syntax = syntax_for(url)?;
+        let theme = match crate::SETTINGS.read().unwrap().config.theme.as_str() {
+            "lightmode" => &THEMES.themes["InspiredGitHub"],
+            _ => &THEMES.themes["base16-ocean.dark"],
+        };
+        let mut highlighter = HighlightLines::new(syntax, theme);
+        let mut rows = vec![];
+        for line in LinesWithEndings::from(text) {
+            let ranges = match highlighter.highlight_line(line, &SYNTAXES) {
+                Ok(ranges) => ranges,
+                Err(err) => {
+                    warn!("Could not highlight {}: {}", url, err);
+                    return None;
+                }
+            };
+            let mut row = StyledString::new();
+            for (style, piece) in ranges {
+                let piece = piece.trim_end_matches(['\n', '\r']);
+                let color = style.foreground;
+                row.append_styled(piece, Style::from(Color::Rgb(color.r, color.g, color.b)));
+            }
+            rows.push((row, None));
+        }
+        Some(rows)
+    }
+}
+
+/// Highlights the syntax of source code, judging the language by the
+/// extension of the URL. Returns `None` for other text, if highlighting
+/// is turned off in the settings, or if ncgopher was built without
+/// syntax highlighting. Lines are not wrapped.
+pub fn highlight(url: &Url, text: &str) -> Option<Vec<(StyledString, Option<Url>)>> {
+    if !crate::SETTINGS.read().unwrap().config.syntax_highlighting {
+        return None;
+    }
+    #[cfg(feature = "syntax-highlighting")]
+    return syntect_impl::highlight(url, text);
+    #[cfg(not(feature = "syntax-highlighting"))]
+    {
+        let _ = (url, text);
+        None
+    }
+}
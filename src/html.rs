@@ -0,0 +1,304 @@
+use url::Url;
+
+/// Converts an HTML document to gemtext, so that it can be read and its
+/// links followed in the gemini view. This is no HTML parser: tags are
+/// stripped, block elements start new lines, and the links of a block
+/// are listed after it.
+pub fn to_gemtext(html: &str, base_url: &Url) -> String {
+    let mut converter = Converter {
+        base_url,
+        output: String::new(),
+        line: String::new(),
+        links: vec![],
+        link: None,
+        prefix: "",
+        skip: None,
+        pre: false,
+    };
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        converter.text(&rest[..start]);
+        rest = &rest[start..];
+        if rest.starts_with("<!--") {
+            rest = rest.find("-->").map_or("", |end| &rest[end + 3..]);
+            continue;
+        }
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        converter.tag(&rest[1..end]);
+        rest = &rest[end + 1..];
+    }
+    converter.text(rest);
+    converter.end_block();
+    converter.output.trim().to_string() + "\n"
+}
+
+struct Converter<'a> {
+    base_url: &'a Url,
+    output: String,
+    /// Text of the current block
+    line: String,
+    /// Links of the current block with their text
+    links: Vec<(String, String)>,
+    /// Target and text of the link being read
+    link: Option<(String, String)>,
+    /// Gemtext line type of the current block, e.g. "## "
+    prefix: &'static str,
+    /// Element whose content is skipped, e.g. script
+    skip: Option<String>,
+    pre: bool,
+}
+
+impl Converter<'_> {
+    fn text(&mut self, text: &str) {
+        if self.skip.is_some() || text.is_empty() {
+            return;
+        }
+        let text = decode_entities(text);
+        if self.pre {
+            for (i, line) in text.split('\n').enumerate() {
+                if i > 0 {
+                    self.output.push_str(&self.line);
+                    self.output.push('\n');
+                    self.line.clear();
+                }
+                self.line.push_str(line);
+            }
+            return;
+        }
+        for word in text.split_whitespace() {
+            if !self.line.is_empty() && !self.line.ends_with(' ') {
+                self.line.push(' ');
+            }
+            self.line.push_str(word);
+            if let Some((_, label)) = &mut self.link {
+                if !label.is_empty() {
+                    label.push(' ');
+                }
+                label.push_str(word);
+            }
+        }
+        if text.ends_with(char::is_whitespace) && !self.line.is_empty() {
+            self.line.push(' ');
+        }
+    }
+
+    /// Writes the current block and its links
+    fn end_block(&mut self) {
+        let line = self.line.trim();
+        if !line.is_empty() {
+            // text must not be mistaken for gemtext markup
+            if self.prefix.is_empty()
+                && ["=>", "#", "*", ">", "```"].iter().any(|m| line.starts_with(m))
+            {
+                self.output.push(' ');
+            }
+            self.output.push_str(self.prefix);
+            self.output.push_str(line);
+            self.output.push('\n');
+        }
+        for (target, label) in self.links.drain(..) {
+            match self.base_url.join(&target) {
+                Ok(url) => self.output.push_str(&format!("=> {} {}\n", url, label)),
+                Err(_) => warn!("Invalid link {} in HTML", target),
+            }
+        }
+        self.line.clear();
+        self.prefix = "";
+    }
+
+    fn blank_line(&mut self) {
+        self.end_block();
+        if !self.output.is_empty() && !self.output.ends_with("\n\n") {
+            self.output.push('\n');
+        }
+    }
+
+    fn tag(&mut self, tag: &str) {
+        let closing = tag.starts_with('/');
+        let tag = tag.trim_start_matches('/');
+        let name = tag
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+
+        if let Some(skipped) = &self.skip {
+            if closing && *skipped == name {
+                self.skip = None;
+            }
+            return;
+        }
+
+        match (name.as_str(), closing) {
+            ("script", false) | ("style", false) | ("noscript", false) | ("template", false) => {
+                self.skip = Some(name)
+            }
+            ("title", false) => {
+                self.end_block();
+                self.prefix = "# ";
+            }
+            ("title", true) => self.blank_line(),
+            ("h1", false) | ("h2", false) | ("h3", false) | ("h4", false) | ("h5", false)
+            | ("h6", false) => {
+                self.blank_line();
+                self.prefix = match name.as_str() {
+                    "h1" => "# ",
+                    "h2" => "## ",
+                    _ => "### ",
+                };
+            }
+            ("li", false) => {
+                self.end_block();
+                self.prefix = "* ";
+            }
+            ("blockquote", false) => {
+                self.blank_line();
+                self.prefix = "> ";
+            }
+            ("pre", false) => {
+                self.blank_line();
+                self.output.push_str("```\n");
+                self.pre = true;
+            }
+            ("pre", true) => {
+                self.output.push_str(&self.line);
+                self.line.clear();
+                if !self.output.ends_with('\n') {
+                    self.output.push('\n');
+                }
+                self.output.push_str("```\n");
+                self.pre = false;
+                self.blank_line();
+            }
+            ("br", _) => {
+                if self.pre {
+                    self.text("\n");
+                } else {
+                    self.end_block();
+                }
+            }
+            ("a", false) => {
+                self.link = attribute(tag, "href").map(|href| (href, String::new()));
+            }
+            ("a", true) => {
+                if let Some((target, label)) = self.link.take() {
+                    if !target.starts_with('#') && !target.starts_with("javascript:") {
+                        let label = if label.is_empty() { target.clone() } else { label };
+                        self.links.push((target, label));
+                    }
+                }
+            }
+            ("img", _) => {
+                if let Some(alt) = attribute(tag, "alt").filter(|alt| !alt.is_empty()) {
+                    self.text(&format!("[{}]", alt));
+                }
+            }
+            ("p", _) | ("div", _) | ("ul", _) | ("ol", _) | ("dl", _) | ("table", _)
+            | ("section", _) | ("article", _) | ("header", _) | ("footer", _) | ("nav", _)
+            | ("main", _) | ("aside", _) | ("form", _) | ("hr", _) | ("h1", true)
+            | ("h2", true) | ("h3", true) | ("h4", true) | ("h5", true) | ("h6", true)
+            | ("blockquote", true) => self.blank_line(),
+            ("tr", _) | ("dt", _) | ("dd", _) | ("li", true) => self.end_block(),
+            ("td", false) | ("th", false) => self.text(" "),
+            _ => (),
+        }
+    }
+}
+
+/// Returns the value of an attribute of a tag
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    // unlike to_lowercase, this keeps the byte offsets of the tag
+    let lower = tag.to_ascii_lowercase();
+    let mut position = 0;
+    while let Some(found) = lower[position..].find(name) {
+        let start = position + found;
+        position = start + name.len();
+        // the name must be a whole word followed by =
+        if !lower[..start].ends_with(char::is_whitespace) {
+            continue;
+        }
+        let rest = lower[position..].trim_start();
+        if !rest.starts_with('=') {
+            continue;
+        }
+        let offset = tag.len() - rest.len() + 1;
+        let value = tag[offset..].trim_start();
+        let value = match value.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => {
+                value[1..].split(quote).next().unwrap_or_default()
+            }
+            _ => value.split(char::is_whitespace).next().unwrap_or_default(),
+        };
+        return Some(decode_entities(value));
+    }
+    None
+}
+
+/// Replaces character references like &amp; and &#8212;
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find(';') {
+            Some(end) if end <= 10 => end,
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let entity = &rest[1..end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            "mdash" => Some('—'),
+            "ndash" => Some('–'),
+            "hellip" => Some('…'),
+            "copy" => Some('©'),
+            _ => match entity.strip_prefix('#') {
+                Some(number) => match number.strip_prefix(|c| c == 'x' || c == 'X') {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => number.parse().ok(),
+                }
+                .and_then(char::from_u32),
+                None => None,
+            },
+        };
+        match c {
+            Some(c) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attribute_with_non_ascii_values() {
+        // İ is longer in lower case
+        let tag = "img alt=\"İstanbul\" title='Ⱥ' SRC=\"/ä.png\"";
+        assert_eq!(attribute(tag, "alt").as_deref(), Some("İstanbul"));
+        assert_eq!(attribute(tag, "title").as_deref(), Some("Ⱥ"));
+        assert_eq!(attribute(tag, "src").as_deref(), Some("/ä.png"));
+        assert_eq!(attribute(tag, "href"), None);
+    }
+}
@@ -7,7 +7,6 @@ extern crate dirs;
 extern crate idna;
 extern crate pem;
 extern crate percent_encoding;
-extern crate rusqlite;
 extern crate serde;
 extern crate serde_derive;
 extern crate toml;
@@ -19,25 +18,40 @@ use ::time::format_description::well_known::Rfc3339;
 use clap::Parser;
 use controller::Controller;
 use lazy_static::lazy_static;
+use ncgopher_core::{
+    bookmarks, certificates, clientcertificates, cso, finger, gopher, gophermap, history, server,
+    transport, uuencode, vault,
+};
 use settings::Settings;
 use std::fs::File;
 use std::io::{stdout, Write};
-use std::sync::RwLock;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use transport::{DnsCache, Proxied, RateLimiter, Recorder, Replay, Throttled, Transport};
 use url::Url;
+use vault::Vault;
 
-mod bookmarks;
-mod certificates;
-mod clientcertificates;
+mod ansi;
 mod controller;
+mod downloads;
+mod external;
 mod gemini;
-mod gophermap;
-mod history;
+mod highlight;
+mod html;
+mod markdown;
+mod notifications;
+mod session;
 mod settings;
+mod text;
+mod tts;
 mod ui;
 mod url_tools;
 
 lazy_static! {
     static ref SETTINGS: RwLock<Settings> = RwLock::new(Settings::new());
+    /// Name of the cursive backend in use
+    static ref BACKEND: RwLock<String> = RwLock::new(String::new());
 }
 
 struct Logger {
@@ -86,10 +100,73 @@ struct Args {
     #[clap(short, long)]
     debug: Option<String>,
 
+    /// Terminal backend to use (pancurses, ncurses, termion or crossterm,
+    /// depending on the features ncgopher was built with)
+    #[clap(short, long)]
+    backend: Option<String>,
+
+    /// Record gopher, gemini and finger sessions to fixture files in this
+    /// directory
+    #[clap(long, value_name = "DIR", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Replay gopher, gemini and finger sessions from fixture files in this
+    /// directory instead of using the network
+    #[clap(long, value_name = "DIR")]
+    replay: Option<PathBuf>,
+
+    /// Serve a local directory over gopher and open it, to preview a
+    /// gopher hole before uploading it
+    #[clap(long, value_name = "DIR", conflicts_with = "url")]
+    serve: Option<PathBuf>,
+
     /// Url to open after startup
     url: Option<String>,
 }
 
+/// Asks for the passphrase of the encrypted bookmarks and history. The
+/// passphrase is checked against the existing encrypted files, or has to
+/// be entered twice if there are none yet.
+fn unlock_profile() -> Vault {
+    let encrypted = [
+        bookmarks::Bookmarks::encrypted_path(),
+        history::History::encrypted_path(),
+    ];
+    let existing = encrypted.iter().find(|path| path.exists());
+    for _ in 0..3 {
+        let passphrase = read_passphrase("Passphrase for bookmarks and history: ");
+        let vault = Vault::new(passphrase.clone());
+        match existing {
+            Some(path) => match vault.read(path) {
+                Ok(_) => return vault,
+                Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                    eprintln!("Wrong passphrase.")
+                }
+                Err(e) => {
+                    eprintln!("Could not read {}: {}", path.display(), e);
+                    std::process::exit(1);
+                }
+            },
+            None => {
+                let repeated = read_passphrase("Repeat passphrase: ");
+                if passphrase == repeated {
+                    return vault;
+                }
+                eprintln!("The passphrases do not match.");
+            }
+        }
+    }
+    std::process::exit(1);
+}
+
+/// Reads a passphrase from the terminal, exiting if that fails
+fn read_passphrase(prompt: &str) -> String {
+    rpassword::prompt_password(prompt).unwrap_or_else(|e| {
+        eprintln!("Could not read passphrase: {}", e);
+        std::process::exit(1);
+    })
+}
+
 fn main() {
     let args = Args::parse();
 
@@ -114,6 +191,15 @@ fn main() {
         eprintln!("logging into file {}", log_file);
     }
 
+    let homepage = match args.serve.as_ref() {
+        Some(dir) => {
+            let addr = server::serve(dir.clone(), 0)
+                .unwrap_or_else(|e| panic!("could not serve {}: {}", dir.display(), e));
+            Url::parse(&format!("gopher://localhost:{}/1/", addr.port())).unwrap()
+        }
+        None => homepage,
+    };
+
     // get default hook that prints to stdout
     let default_hook = std::panic::take_hook();
     // set new hook overwriting default hook
@@ -124,14 +210,61 @@ fn main() {
         default_hook(info);
     }));
 
-    let mut app = cursive::default();
+    let vault = if SETTINGS.read().unwrap().config.encrypt_profile {
+        Some(Arc::new(unlock_profile()))
+    } else {
+        None
+    };
+
+    let backend = args
+        .backend
+        .unwrap_or_else(|| SETTINGS.read().unwrap().config.backend.clone());
+    let mut app = ui::terminal::create_runner(&backend).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
     let theme = SETTINGS.read().unwrap().config.theme.clone();
-    app.load_toml(SETTINGS.read().unwrap().get_theme_by_name(theme))
-        .unwrap();
-    Controller::setup(&mut app, homepage).expect("could not create controller");
+    ui::theme::load_theme(&mut app, &theme);
+    let limiter = Arc::new(RateLimiter::new(|| {
+        SETTINGS.read().unwrap().config.bandwidth_limit * 1024
+    }));
+    let dns_cache_ttl = SETTINGS.read().unwrap().config.dns_cache_ttl;
+    let dns_cache = Arc::new(DnsCache::new(Duration::from_secs(dns_cache_ttl)));
+    let network = Proxied::new(
+        |host| SETTINGS.read().unwrap().config.proxy_for(host),
+        dns_cache.clone(),
+    );
+    let transport: Arc<dyn Transport> = if let Some(dir) = args.replay {
+        Arc::new(Replay::new(dir))
+    } else if let Some(dir) = args.record {
+        let recorder = Recorder::new(network, dir).expect("could not create fixture directory");
+        Arc::new(Throttled::new(recorder, limiter))
+    } else {
+        Arc::new(Throttled::new(network, limiter))
+    };
+    // the terminal is only set up by app.run()
+    Controller::setup(&mut app, homepage, transport, vault.clone()).unwrap_or_else(|err| {
+        eprintln!("Could not start: {}", err);
+        std::process::exit(1);
+    });
+    // pages given on the command line are opened instead
+    if args.url.is_none() && args.serve.is_none() {
+        Controller::offer_last_session(&mut app);
+    }
     // required so async updates to the status bar get shown
     app.run();
+    Controller::save_last_session(&mut app);
+    if let Some(controller) = app.user_data::<Controller>() {
+        if let Err(e) = controller.history.lock().unwrap().save() {
+            eprintln!("Could not save history: {}", e);
+        }
+    }
+    // bookmarks may still be encrypted in the background
+    if let Some(vault) = vault {
+        vault.flush();
+    }
     print!("\x1B[?1002l");
     stdout().flush().expect("could not flush stdout");
-    pancurses::endwin();
+    ui::terminal::shutdown();
+    external::remove_temp_dir();
 }
@@ -0,0 +1,262 @@
+use cursive::theme::{Effect, Style};
+use cursive::utils::lines::simple::LinesIterator;
+use cursive::utils::markup::StyledString;
+use std::ops::Range;
+use url::Url;
+
+/// Text of a block with the styles and links of its parts, as byte
+/// ranges of the text
+#[derive(Default)]
+struct Inline {
+    text: String,
+    styles: Vec<(Range<usize>, Style)>,
+    links: Vec<(Range<usize>, Url)>,
+}
+
+impl Inline {
+    fn push_styled(&mut self, text: &str, style: Style) {
+        let start = self.text.len();
+        self.text.push_str(text);
+        self.styles.push((start..self.text.len(), style));
+    }
+
+    fn push_link(&mut self, text: &str, url: Option<Url>) {
+        let start = self.text.len();
+        self.push_styled(text, Effect::Underline.into());
+        if let Some(url) = url {
+            self.links.push((start..self.text.len(), url));
+        }
+    }
+}
+
+/// Returns the position of the closing delimiter of an inline element
+/// starting at `text`, which must not be empty
+fn closing(text: &str, delimiter: &str) -> Option<usize> {
+    let end = text.find(delimiter)?;
+    if end == 0 {
+        None
+    } else {
+        Some(end)
+    }
+}
+
+/// Parses emphasis, inline code and links of a block of text
+fn parse_inline(text: &str, base_url: &Url) -> Inline {
+    let mut inline = Inline::default();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let after = &rest[c.len_utf8()..];
+        if c == '\\' && after.starts_with(|c: char| c.is_ascii_punctuation()) {
+            inline.text.push_str(&after[..1]);
+            rest = &after[1..];
+            continue;
+        }
+        if rest.starts_with("**") || rest.starts_with("__") {
+            if let Some(end) = closing(&rest[2..], &rest[..2]) {
+                inline.push_styled(&rest[2..2 + end], Effect::Bold.into());
+                rest = &rest[end + 4..];
+                continue;
+            }
+        }
+        // underscores within words are no emphasis
+        let word_start = !inline.text.ends_with(|c: char| c.is_alphanumeric());
+        if c == '*' || (c == '_' && word_start) {
+            if let Some(end) = closing(after, &rest[..1]) {
+                inline.push_styled(&after[..end], Effect::Italic.into());
+                rest = &after[end + 1..];
+                continue;
+            }
+        }
+        if c == '`' {
+            if let Some(end) = closing(after, "`") {
+                inline.push_styled(&after[..end], Effect::Reverse.into());
+                rest = &after[end + 1..];
+                continue;
+            }
+        }
+        // [text](url) and ![alt](url)
+        let image = c == '!' && after.starts_with('[');
+        let link = if image { &after[1..] } else { after };
+        if c == '[' || image {
+            if let Some(text_end) = link.find("](") {
+                if let Some(url_end) = link[text_end..].find(')') {
+                    let label = &link[..text_end];
+                    let target = link[text_end + 2..text_end + url_end].trim();
+                    // titles are written after the URL
+                    let target = target.split_whitespace().next().unwrap_or_default();
+                    let url = base_url.join(target).ok();
+                    if image {
+                        inline.text.push_str("[IMG] ");
+                    }
+                    inline.push_link(if label.is_empty() { target } else { label }, url);
+                    rest = &link[text_end + url_end + 1..];
+                    continue;
+                }
+            }
+        }
+        // <https://example.org>
+        if c == '<' {
+            if let Some(end) = after.find('>') {
+                if let Ok(url) = Url::parse(&after[..end]) {
+                    inline.push_link(&after[..end], Some(url));
+                    rest = &after[end + 1..];
+                    continue;
+                }
+            }
+        }
+        inline.text.push(c);
+        rest = after;
+    }
+    inline
+}
+
+/// Wraps a block and returns its rows. The prefix is shown in the left
+/// margin of the first row. Rows point to the first link they contain.
+fn block_rows(
+    prefix: &str,
+    inline: Inline,
+    style: Style,
+    viewport_width: usize,
+) -> Vec<(StyledString, Option<Url>)> {
+    let text = if inline.text.is_empty() { " " } else { &inline.text };
+    LinesIterator::new(text, viewport_width)
+        .enumerate()
+        .map(|(i, row)| {
+            let mut line = StyledString::plain(format!(
+                "{:>5}  ",
+                if i == 0 { prefix } else { "" }
+            ));
+            // split the row where the styles of its parts change
+            let mut position = row.start;
+            while position < row.end {
+                let mut part_style = style;
+                let mut end = row.end;
+                for (range, s) in &inline.styles {
+                    if range.contains(&position) {
+                        part_style = part_style.combine(*s);
+                        end = end.min(range.end);
+                    } else if range.start > position {
+                        end = end.min(range.start);
+                    }
+                }
+                line.append_styled(&text[position..end], part_style);
+                position = end;
+            }
+            let url = inline
+                .links
+                .iter()
+                .find(|(range, _)| range.start < row.end && range.end > row.start)
+                .map(|(_, url)| url.clone());
+            (line, url)
+        })
+        .collect()
+}
+
+/// Returns the level of a heading line and its text
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if (1..=6).contains(&level) && line[level..].starts_with(' ') {
+        Some((level, line[level..].trim().trim_end_matches('#').trim_end()))
+    } else {
+        None
+    }
+}
+
+/// Returns the marker of a list item and its text
+fn list_item(line: &str) -> Option<(String, &str)> {
+    let line = line.trim_start();
+    for bullet in &["- ", "* ", "+ "] {
+        if let Some(text) = line.strip_prefix(bullet) {
+            return Some(("*".to_string(), text));
+        }
+    }
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 && line[digits..].starts_with(". ") {
+        return Some((line[..digits + 1].to_string(), &line[digits + 2..]));
+    }
+    None
+}
+
+fn is_rule(line: &str) -> bool {
+    let line: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    line.len() >= 3
+        && (line.chars().all(|c| c == '-')
+            || line.chars().all(|c| c == '*')
+            || line.chars().all(|c| c == '_'))
+}
+
+/// Renders markdown with styles for the gemini view. Headings, list
+/// items, quotes and code blocks are marked in the left margin like in
+/// gemini documents.
+pub fn parse(
+    text: &str,
+    base_url: &Url,
+    viewport_width: usize,
+) -> Vec<(StyledString, Option<Url>)> {
+    let mut rows = vec![];
+    let mut paragraph: Vec<&str> = vec![];
+    let mut code_block = false;
+
+    let flush = |paragraph: &mut Vec<&str>, rows: &mut Vec<(StyledString, Option<Url>)>| {
+        if !paragraph.is_empty() {
+            let text = paragraph.join(" ");
+            let inline = parse_inline(&text, base_url);
+            rows.extend(block_rows("", inline, Style::none(), viewport_width));
+            paragraph.clear();
+        }
+    };
+
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") || line.trim_start().starts_with("~~~") {
+            flush(&mut paragraph, &mut rows);
+            code_block = !code_block;
+            continue;
+        }
+        if code_block {
+            // code is not wrapped
+            rows.push((StyledString::plain(format!("    @  {}", line)), None));
+            continue;
+        }
+        if line.trim().is_empty() {
+            flush(&mut paragraph, &mut rows);
+            rows.push((StyledString::new(), None));
+        } else if let Some((level, text)) = heading(line) {
+            flush(&mut paragraph, &mut rows);
+            let style = match level {
+                1 => Style::from(Effect::Bold).combine(Effect::Underline),
+                _ => Style::from(Effect::Bold),
+            };
+            let inline = parse_inline(text, base_url);
+            rows.extend(block_rows(&"#".repeat(level), inline, style, viewport_width));
+        } else if is_rule(line) {
+            flush(&mut paragraph, &mut rows);
+            let width = viewport_width.min(40);
+            rows.push((StyledString::plain(format!("       {}", "─".repeat(width))), None));
+        } else if let Some((marker, text)) = list_item(line) {
+            flush(&mut paragraph, &mut rows);
+            let inline = parse_inline(text, base_url);
+            rows.extend(block_rows(&marker, inline, Style::none(), viewport_width));
+        } else if let Some(text) = line.trim_start().strip_prefix('>') {
+            flush(&mut paragraph, &mut rows);
+            let inline = parse_inline(text.trim(), base_url);
+            rows.extend(block_rows(">", inline, Effect::Italic.into(), viewport_width));
+        } else if line.starts_with("    ") || line.starts_with('\t') {
+            // indented code
+            flush(&mut paragraph, &mut rows);
+            rows.push((StyledString::plain(format!("    @  {}", line.trim_start())), None));
+        } else {
+            paragraph.push(line.trim());
+        }
+    }
+    flush(&mut paragraph, &mut rows);
+    rows
+}
+
+/// Returns whether a text resource should be rendered as markdown,
+/// judging by its name or because the settings force it
+pub fn is_markdown(url: &Url) -> bool {
+    let path = url.path().to_lowercase();
+    path.ends_with(".md")
+        || path.ends_with(".markdown")
+        || crate::SETTINGS.read().unwrap().config.force_markdown
+}
@@ -0,0 +1,64 @@
+use std::io::{stdout, Write};
+
+/// Events the user can be notified about when they finish in the
+/// background.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// A binary download has finished
+    Download,
+}
+
+impl Event {
+    /// Name of the event in the notifications setting
+    fn name(self) -> &'static str {
+        match self {
+            Event::Download => "download",
+        }
+    }
+}
+
+/// Notifies the user about a finished event as configured in the
+/// notifications setting: "bell" rings the terminal bell, "desktop"
+/// sends a desktop notification, "both" does both and "none" nothing.
+pub fn notify(event: Event, summary: &str, body: &str) {
+    let method = crate::SETTINGS
+        .read()
+        .unwrap()
+        .config
+        .notifications
+        .get(event.name())
+        .cloned()
+        .unwrap_or_else(|| "bell".to_string());
+    info!("Notification for {} ({}): {}", event.name(), method, summary);
+    match method.as_str() {
+        "bell" => bell(),
+        "desktop" => desktop(summary, body),
+        "both" => {
+            bell();
+            desktop(summary, body);
+        }
+        _ => (),
+    }
+}
+
+fn bell() {
+    print!("\x07");
+    stdout().flush().ok();
+}
+
+#[cfg(feature = "desktop-notifications")]
+fn desktop(summary: &str, body: &str) {
+    if let Err(err) = notify_rust::Notification::new()
+        .appname(env!("CARGO_PKG_NAME"))
+        .summary(summary)
+        .body(body)
+        .show()
+    {
+        warn!("Could not send desktop notification: {}", err);
+    }
+}
+
+#[cfg(not(feature = "desktop-notifications"))]
+fn desktop(_summary: &str, _body: &str) {
+    warn!("ncgopher was built without desktop notification support");
+}
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+use crate::vault::Vault;
+
+/// A page of the navigation history
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionPage {
+    pub url: Url,
+    /// The selected line of the page
+    #[serde(default)]
+    pub position: usize,
+}
+
+/// The state of the browser that can be saved to a file and restored
+/// later, possibly on another machine. The last page is the current one.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(default)]
+    pub page: Vec<SessionPage>,
+}
+
+impl Session {
+    pub fn read(path: &Path) -> Result<Session, Box<dyn Error>> {
+        info!("Reading session from {:?}", path);
+        let session = toml::from_str(&fs::read_to_string(path)?)?;
+        Ok(session)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        info!("Saving session to {:?}", path);
+        fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Returns the file the session is saved to on quitting, which is
+    /// encrypted with the vault of an encrypted profile
+    fn last_session_path(encrypted: bool) -> Option<PathBuf> {
+        let mut path = dirs::config_dir()?;
+        path.push(env!("CARGO_PKG_NAME"));
+        path.push(if encrypted { "session.age" } else { "session.toml" });
+        Some(path)
+    }
+
+    /// Reads the session saved when ncgopher last quit, if any
+    pub fn read_last(vault: Option<&Vault>) -> Option<Session> {
+        let path = Session::last_session_path(vault.is_some())?;
+        if !path.exists() {
+            return None;
+        }
+        info!("Reading last session from {:?}", path);
+        let data = match vault {
+            Some(vault) => vault.read(&path),
+            None => fs::read(&path),
+        };
+        let session = data
+            .map_err(|err| err.to_string())
+            .and_then(|data| {
+                toml::from_str(&String::from_utf8_lossy(&data)).map_err(|err| err.to_string())
+            });
+        match session {
+            Ok(session) => Some(session),
+            Err(err) => {
+                warn!("Could not read last session {:?}: {}", path, err);
+                None
+            }
+        }
+    }
+
+    /// Saves the session to restore it on the next start
+    pub fn write_last(&self, vault: Option<&Vault>) -> Result<(), Box<dyn Error>> {
+        let path =
+            Session::last_session_path(vault.is_some()).ok_or("no configuration directory")?;
+        info!("Saving last session to {:?}", path);
+        let data = toml::to_string(self)?;
+        match vault {
+            Some(vault) => vault.write(&path, data.as_bytes())?,
+            None => fs::write(&path, data)?,
+        }
+        Ok(())
+    }
+}
@@ -2,9 +2,15 @@ use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
 use std::env;
 use std::fs::{self, DirBuilder, File as FsFile};
-use std::io::Write;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use toml::Value;
+
+use crate::gophermap::ItemType;
+use cursive::theme::Color;
+use crate::transport::Proxy;
+use crate::ui::theme::{degrade_color, ColorDepth};
+use unicode_width::UnicodeWidthStr;
 //use cursive::theme::{Theme, BorderStyle};
 //use cursive::theme::BaseColor::*;
 //use cursive::theme::Color::*;
@@ -28,10 +34,24 @@ pub struct NewConfig {
     pub theme: String,
     #[serde(default = "default_html_command", deserialize_with = "ok_or_default")]
     pub html_command: String,
+    /// Ask before opening web pages in the browser
+    #[serde(default = "default_confirm_browser", deserialize_with = "ok_or_default")]
+    pub confirm_browser: bool,
     #[serde(default = "default_image_command", deserialize_with = "ok_or_default")]
     pub image_command: String,
     #[serde(default = "default_telnet_command", deserialize_with = "ok_or_default")]
     pub telnet_command: String,
+    /// Media player for sound and video items, which is fed the stream
+    /// on its standard input, e.g. "mpv"
+    #[serde(default = "default_media_command", deserialize_with = "ok_or_default")]
+    pub media_command: String,
+    /// Show image items in the image viewer right away instead of
+    /// downloading them
+    #[serde(default = "default_open_images", deserialize_with = "ok_or_default")]
+    pub open_images: bool,
+    /// Text to speech command for reading pages aloud, e.g. espeak-ng
+    #[serde(default = "default_tts_command", deserialize_with = "ok_or_default")]
+    pub tts_command: String,
     #[serde(default = "default_textwrap", deserialize_with = "ok_or_default")]
     pub textwrap: String,
     #[serde(
@@ -44,6 +64,211 @@ pub struct NewConfig {
         deserialize_with = "ok_or_default"
     )]
     pub disable_identities: bool,
+    #[serde(default = "default_max_redirects", deserialize_with = "ok_or_default")]
+    pub max_redirects: u8,
+    #[serde(default = "default_trust_on_first_use", deserialize_with = "ok_or_default")]
+    pub trust_on_first_use: bool,
+    #[serde(default = "default_clock_format", deserialize_with = "ok_or_default")]
+    pub clock_format: String,
+    #[serde(default = "default_statusbar_format", deserialize_with = "ok_or_default")]
+    pub statusbar_format: String,
+    /// Colors of gophermap items defined by the current theme
+    #[serde(skip)]
+    pub theme_type_colors: HashMap<String, String>,
+    #[serde(default = "default_autohide_menu", deserialize_with = "ok_or_default")]
+    pub autohide_menu: bool,
+    #[serde(default = "default_color_depth", deserialize_with = "ok_or_default")]
+    pub color_depth: String,
+    #[serde(default = "default_backend", deserialize_with = "ok_or_default")]
+    pub backend: String,
+    /// Maps event names to "bell", "desktop", "both" or "none"
+    #[serde(default = "default_notifications", deserialize_with = "ok_or_default")]
+    pub notifications: HashMap<String, String>,
+    /// Maps host names to the text encoding used for their content
+    #[serde(default = "default_encodings", deserialize_with = "ok_or_default")]
+    pub encodings: HashMap<String, String>,
+    #[serde(default = "default_gophermap_format", deserialize_with = "ok_or_default")]
+    pub gophermap_format: String,
+    /// Highlight the syntax of source code files, e.g. .rs or .py
+    #[serde(default = "default_syntax_highlighting", deserialize_with = "ok_or_default")]
+    pub syntax_highlighting: bool,
+    /// Render all text resources as markdown, not only .md files
+    #[serde(default = "default_force_markdown", deserialize_with = "ok_or_default")]
+    pub force_markdown: bool,
+    /// Show the item type column ([MAP], [TXT], ...) of gophermaps. When
+    /// hidden, types are told apart by effects, e.g. bold directories.
+    #[serde(default = "default_show_type_labels", deserialize_with = "ok_or_default")]
+    pub show_type_labels: bool,
+    /// Key bindings, "default" or "vim"
+    #[serde(default = "default_keymap", deserialize_with = "ok_or_default")]
+    pub keymap: String,
+    /// Number the links of gophermaps to select them by typing the number
+    #[serde(default = "default_number_links", deserialize_with = "ok_or_default")]
+    pub number_links: bool,
+    /// Show line numbers in front of the lines of text files
+    #[serde(default = "default_line_numbers", deserialize_with = "ok_or_default")]
+    pub line_numbers: bool,
+    /// Show icons instead of the bracketed item type labels
+    #[serde(default = "default_type_icons", deserialize_with = "ok_or_default")]
+    pub type_icons: bool,
+    /// Maps gopher item types, e.g. "1", to the label shown for them
+    #[serde(default = "default_type_labels", deserialize_with = "ok_or_default")]
+    pub type_labels: HashMap<String, String>,
+    /// Maps gopher item types to the color of their items, e.g. "blue",
+    /// "light red" or "#ff8800". "none" keeps the default color.
+    #[serde(default = "default_type_colors", deserialize_with = "ok_or_default")]
+    pub type_colors: HashMap<String, String>,
+    /// Seconds after which external commands are killed, 0 for no limit
+    #[serde(default = "default_command_timeout", deserialize_with = "ok_or_default")]
+    pub command_timeout: u64,
+    /// Maximum combined download rate of all transfers in KiB per
+    /// second, 0 for no limit
+    #[serde(default = "default_bandwidth_limit", deserialize_with = "ok_or_default")]
+    pub bandwidth_limit: u64,
+    /// Restore the pages open when quitting on the next start: "never",
+    /// "ask" or "always"
+    #[serde(default = "default_restore_session", deserialize_with = "ok_or_default")]
+    pub restore_session: String,
+    /// Command downloading the bookmarks of another computer to the file
+    /// `%s` for syncing, e.g. with scp or curl. Syncing is off if empty.
+    #[serde(
+        default = "default_bookmark_sync_pull",
+        deserialize_with = "ok_or_default"
+    )]
+    pub bookmark_sync_pull: String,
+    /// Command uploading the synced bookmarks in the file `%s`
+    #[serde(
+        default = "default_bookmark_sync_push",
+        deserialize_with = "ok_or_default"
+    )]
+    pub bookmark_sync_push: String,
+    /// Maximum combined rate of binary downloads in KiB per second, 0
+    /// for no limit. Pages keep loading at full speed.
+    #[serde(
+        default = "default_download_rate_limit",
+        deserialize_with = "ok_or_default"
+    )]
+    pub download_rate_limit: u64,
+    /// Encrypt bookmarks and history with a passphrase asked for on
+    /// startup
+    #[serde(
+        default = "default_encrypt_profile",
+        deserialize_with = "ok_or_default"
+    )]
+    pub encrypt_profile: bool,
+    /// Seconds for which the addresses of hosts are remembered, 0 to
+    /// look them up for every request
+    #[serde(default = "default_dns_cache_ttl", deserialize_with = "ok_or_default")]
+    pub dns_cache_ttl: u64,
+    /// SOCKS5 proxy for all connections, empty for direct connections
+    #[serde(default = "default_proxy_host", deserialize_with = "ok_or_default")]
+    pub proxy_host: String,
+    #[serde(default = "default_proxy_port", deserialize_with = "ok_or_default")]
+    pub proxy_port: u16,
+    #[serde(default = "default_proxy_username", deserialize_with = "ok_or_default")]
+    pub proxy_username: String,
+    #[serde(default = "default_proxy_password", deserialize_with = "ok_or_default")]
+    pub proxy_password: String,
+    /// SOCKS5 proxy of Tor as host:port, used for .onion hosts
+    #[serde(default = "default_tor_proxy", deserialize_with = "ok_or_default")]
+    pub tor_proxy: String,
+}
+
+impl NewConfig {
+    /// Returns the label shown for an item type in gophermaps, padded
+    /// to the width of the widest label
+    pub fn type_label(&self, item_type: ItemType) -> String {
+        let label = self.unpadded_type_label(item_type);
+        let padding = self.type_label_width().saturating_sub(label.width());
+        label + &" ".repeat(padding)
+    }
+
+    /// Returns the width of the item type column of gophermaps
+    pub fn type_label_width(&self) -> usize {
+        let custom = self.type_labels.keys().filter_map(|k| k.chars().next());
+        ALL_ITEM_TYPES
+            .iter()
+            .copied()
+            .chain(custom)
+            .map(|c| self.unpadded_type_label(ItemType::decode(c)).width())
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Returns the color of gophermap items of a type, if any
+    pub fn type_color(&self, item_type: ItemType) -> Option<Color> {
+        self.item_color(&item_type.encode().to_string(), default_type_color(item_type))
+    }
+
+    /// Returns the color of gophermap items linking to visited pages,
+    /// which is set with the key "visited" of the type colors
+    pub fn visited_color(&self) -> Option<Color> {
+        self.item_color("visited", "light black")
+    }
+
+    /// Returns a color of the type colors setting or the theme
+    fn item_color(&self, key: &str, default: &str) -> Option<Color> {
+        let color = match self.type_colors.get(key) {
+            Some(color) => color.as_str(),
+            None => match self.theme_type_colors.get(key) {
+                Some(color) => color.as_str(),
+                None => default,
+            },
+        };
+        match color {
+            "none" => None,
+            color => {
+                // hex colors are approximated on terminals without truecolor
+                let depth = ColorDepth::from_setting(&self.color_depth);
+                Color::parse(&degrade_color(color, depth)).or_else(|| {
+                    warn!("Invalid color {} for gophermap items {}", color, key);
+                    None
+                })
+            }
+        }
+    }
+
+    fn unpadded_type_label(&self, item_type: ItemType) -> String {
+        if let Some(label) = self.type_labels.get(&item_type.encode().to_string()) {
+            return label.clone();
+        }
+        if self.type_icons {
+            return type_icon(item_type).to_string();
+        }
+        ItemType::as_str(item_type).trim_end().to_string()
+    }
+
+    /// Returns the SOCKS5 proxy to connect to `host` through, if any.
+    /// Onion services are only reached through the Tor proxy, so that
+    /// they are never looked up over the clearnet.
+    pub fn proxy_for(&self, host: &str) -> io::Result<Option<Proxy>> {
+        if host.trim_end_matches('.').ends_with(".onion") {
+            let (tor_host, port) = self.tor_proxy.rsplit_once(':').ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "set tor_proxy to open .onion addresses",
+                )
+            })?;
+            let port = port.parse().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, "invalid port in tor_proxy")
+            })?;
+            return Ok(Some(Proxy {
+                host: tor_host.to_string(),
+                port,
+                username: String::new(),
+                password: String::new(),
+            }));
+        }
+        if self.proxy_host.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Proxy {
+            host: self.proxy_host.clone(),
+            port: self.proxy_port,
+            username: self.proxy_username.clone(),
+            password: self.proxy_password.clone(),
+        }))
+    }
 }
 
 fn ok_or_default<'a, T, D>(deserializer: D) -> Result<T, D::Error>
@@ -61,10 +286,8 @@ fn default_download_path() -> String {
         Some([&home, "Downloads"].iter().collect::<PathBuf>())
     } else if let Ok(tmp) = env::var("TMP") {
         Some(PathBuf::from(tmp))
-    } else if let Ok(cwd) = env::current_exe() {
-        Some(cwd)
     } else {
-        None
+        env::current_exe().ok()
     };
 
     if let Some(dl_dir) = dl_dir {
@@ -84,7 +307,16 @@ fn default_theme() -> String {
     "lightmode".to_owned()
 }
 fn default_html_command() -> String {
-    "".to_owned()
+    if cfg!(target_os = "macos") {
+        "open".to_owned()
+    } else if cfg!(windows) {
+        "".to_owned()
+    } else {
+        "xdg-open".to_owned()
+    }
+}
+fn default_confirm_browser() -> bool {
+    true
 }
 fn default_image_command() -> String {
     "".to_owned()
@@ -92,6 +324,15 @@ fn default_image_command() -> String {
 fn default_telnet_command() -> String {
     "".to_owned()
 }
+fn default_media_command() -> String {
+    "".to_owned()
+}
+fn default_open_images() -> bool {
+    false
+}
+fn default_tts_command() -> String {
+    "".to_owned()
+}
 fn default_textwrap() -> String {
     "80".to_owned()
 }
@@ -101,6 +342,145 @@ fn default_disable_history() -> bool {
 fn default_disable_identities() -> bool {
     false
 }
+fn default_max_redirects() -> u8 {
+    5
+}
+fn default_trust_on_first_use() -> bool {
+    true
+}
+fn default_clock_format() -> String {
+    "".to_owned()
+}
+fn default_statusbar_format() -> String {
+    "{message}{right}{position}  {time}".to_owned()
+}
+fn default_restore_session() -> String {
+    "never".to_owned()
+}
+fn default_autohide_menu() -> bool {
+    false
+}
+fn default_color_depth() -> String {
+    "auto".to_owned()
+}
+fn default_backend() -> String {
+    "".to_owned()
+}
+fn default_command_timeout() -> u64 {
+    0
+}
+fn default_bandwidth_limit() -> u64 {
+    0
+}
+fn default_dns_cache_ttl() -> u64 {
+    300
+}
+fn default_proxy_port() -> u16 {
+    1080
+}
+fn default_syntax_highlighting() -> bool {
+    true
+}
+fn default_show_type_labels() -> bool {
+    true
+}
+fn default_encodings() -> HashMap<String, String> {
+    HashMap::new()
+}
+fn default_force_markdown() -> bool {
+    false
+}
+fn default_number_links() -> bool {
+    false
+}
+fn default_line_numbers() -> bool {
+    false
+}
+fn default_type_icons() -> bool {
+    false
+}
+fn default_type_labels() -> HashMap<String, String> {
+    HashMap::new()
+}
+fn default_type_colors() -> HashMap<String, String> {
+    HashMap::new()
+}
+fn default_bookmark_sync_pull() -> String {
+    "".to_owned()
+}
+fn default_bookmark_sync_push() -> String {
+    "".to_owned()
+}
+fn default_download_rate_limit() -> u64 {
+    0
+}
+fn default_encrypt_profile() -> bool {
+    false
+}
+fn default_proxy_host() -> String {
+    "".to_owned()
+}
+fn default_proxy_username() -> String {
+    "".to_owned()
+}
+fn default_proxy_password() -> String {
+    "".to_owned()
+}
+fn default_tor_proxy() -> String {
+    "".to_owned()
+}
+/// Gopher item types with a label of their own
+const ALL_ITEM_TYPES: &[char] = &[
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', '+', 'T', 'g', 'I', 'h', 'd', ';', 'M',
+    'c', 's', 'i',
+];
+
+fn type_icon(item_type: ItemType) -> &'static str {
+    match item_type {
+        ItemType::File => "📄",
+        ItemType::Dir => "📁",
+        ItemType::CsoServer => "☎",
+        ItemType::Error => "⚠",
+        ItemType::BinHex | ItemType::Dos | ItemType::Uuencoded | ItemType::Binary => "📦",
+        ItemType::IndexServer => "🔍",
+        ItemType::Telnet | ItemType::Tn3270 => "💻",
+        ItemType::RedundantServer => "🔁",
+        ItemType::Gif | ItemType::Image => "🖼",
+        ItemType::Html => "🌐",
+        ItemType::Document => "📃",
+        ItemType::Video => "🎞",
+        ItemType::Mime => "✉",
+        ItemType::Calendar => "📅",
+        ItemType::Sound => "🎵",
+        ItemType::Inline => "",
+        ItemType::Other(_) => "?",
+    }
+}
+
+fn default_type_color(item_type: ItemType) -> &'static str {
+    match item_type {
+        ItemType::Dir => "blue",
+        ItemType::IndexServer | ItemType::CsoServer => "yellow",
+        ItemType::Error => "red",
+        ItemType::Gif | ItemType::Image => "green",
+        ItemType::Html => "magenta",
+        ItemType::Telnet | ItemType::Tn3270 => "cyan",
+        t if t.is_download() => "red",
+        _ => "none",
+    }
+}
+
+fn default_keymap() -> String {
+    "default".to_owned()
+}
+fn default_gophermap_format() -> String {
+    "{type}  {title}".to_owned()
+}
+fn default_notifications() -> HashMap<String, String> {
+    let mut notifications = HashMap::new();
+    notifications.insert("download".to_owned(), "bell".to_owned());
+    notifications
+}
 
 impl Settings {
     pub fn new() -> Settings {
@@ -140,6 +520,18 @@ impl Settings {
             "lightmode".to_string(),
             include_str!("themes/lightmode.toml").to_string(),
         );
+        themes.insert(
+            "solarized".to_string(),
+            include_str!("themes/solarized.toml").to_string(),
+        );
+        themes.insert(
+            "gruvbox".to_string(),
+            include_str!("themes/gruvbox.toml").to_string(),
+        );
+        if let Some(mut dir) = dirs::config_dir() {
+            dir.push(env!("CARGO_PKG_NAME"));
+            load_user_themes(&mut themes, &dir);
+        }
 
         let mut config_string = String::new();
         if Path::new(confdir.as_str()).exists() {
@@ -160,10 +552,7 @@ impl Settings {
         // Create a path to the desired file
         let path = Path::new(&filename);
 
-        let mut file = match FsFile::create(&path) {
-            Err(why) => return Err(why),
-            Ok(file) => file,
-        };
+        let mut file = FsFile::create(path)?;
 
         file.write_all(b"# Automatically generated by ncgopher.\n")?;
 
@@ -171,6 +560,37 @@ impl Settings {
         file.write_all(toml.as_bytes())
     }
 
+    /// Changes a setting, given by its name in the config file. The value
+    /// is read as TOML, or as a string if it is no valid TOML value.
+    pub fn set_option(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let mut config = Value::try_from(&self.config).map_err(|err| err.to_string())?;
+        let table = config.as_table_mut().ok_or("invalid configuration")?;
+        if !table.contains_key(key) {
+            return Err(format!("unknown setting {}", key));
+        }
+        let value = toml::from_str::<toml::Table>(&format!("value = {}", value))
+            .ok()
+            .and_then(|mut parsed| parsed.remove("value"))
+            .unwrap_or_else(|| Value::String(value.to_string()));
+        table.insert(key.to_string(), value.clone());
+        let config: NewConfig = config.try_into().map_err(|err: toml::de::Error| err.to_string())?;
+        // invalid values are replaced by defaults when deserializing
+        let stored = Value::try_from(&config).ok().and_then(|c| c.get(key).cloned());
+        if stored.as_ref() != Some(&value) {
+            return Err(format!("invalid value for {}", key));
+        }
+        let theme_type_colors = std::mem::take(&mut self.config.theme_type_colors);
+        self.config = config;
+        self.config.theme_type_colors = theme_type_colors;
+        Ok(())
+    }
+
+    /// Returns the value of a setting as TOML
+    pub fn get_option(&self, key: &str) -> Option<String> {
+        let config = Value::try_from(&self.config).ok()?;
+        config.get(key).map(Value::to_string)
+    }
+
     /*
     pub fn set<T>(&mut self, key: &str, value: T) -> Result<&mut Config, ConfigError>
     where
@@ -221,7 +641,42 @@ impl Settings {
     }
     */
 
-    pub fn get_theme_by_name(&self, name: String) -> &str {
-        self.themes[&name].as_str()
+    pub fn get_theme_by_name(&self, name: &str) -> Option<&str> {
+        self.themes.get(name).map(String::as_str)
+    }
+
+    /// Returns the names of the built-in and user themes
+    pub fn theme_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.themes.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+/// Adds the user themes from the config dir: theme.toml as "user" and
+/// every file in the themes directory by its name, e.g. themes/solarized.toml
+/// as "solarized". User themes can replace the built-in ones.
+fn load_user_themes(themes: &mut HashMap<String, String>, config_dir: &Path) {
+    let mut files = vec![("user".to_string(), config_dir.join("theme.toml"))];
+    if let Ok(entries) = fs::read_dir(config_dir.join("themes")) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "toml") {
+                if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    files.push((name.to_string(), path.clone()));
+                }
+            }
+        }
+    }
+    for (name, path) in files {
+        if !path.exists() {
+            continue;
+        }
+        match fs::read_to_string(&path) {
+            Ok(theme) => {
+                themes.insert(name, theme);
+            }
+            Err(err) => warn!("Could not read theme {}: {}", path.display(), err),
+        }
     }
 }
@@ -0,0 +1,423 @@
+use cursive::theme::ColorStyle;
+use cursive::utils::lines::simple::LinesIterator;
+use cursive::utils::markup::StyledString;
+use encoding_rs::Encoding;
+use flate2::read::GzDecoder;
+use linkify::{LinkFinder, LinkKind};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use unicode_normalization::UnicodeNormalization;
+use unicode_width::UnicodeWidthStr;
+use url::Url;
+
+/// Limit for decompressed data, to not run out of memory on gzip bombs
+const MAX_DECOMPRESSED: u64 = 64 * 1024 * 1024;
+
+/// Encodings offered in the "Reload with encoding" dialog
+pub const COMMON_ENCODINGS: &[&str] = &[
+    "utf-8",
+    "iso-8859-1",
+    "iso-8859-15",
+    "windows-1252",
+    "windows-1251",
+    "koi8-r",
+    "shift_jis",
+    "euc-jp",
+    "gbk",
+    "big5",
+    "euc-kr",
+    "cp437",
+];
+
+/// Labels of the IBM PC code page 437, which encoding_rs does not know
+const CP437_LABELS: &[&str] = &["cp437", "ibm437", "437", "ibm-437", "cspc8codepage437"];
+
+/// Characters 0x80 to 0xff of code page 437
+const CP437_HIGH: &str = "ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜ¢£¥₧ƒáíóúñÑªº¿⌐¬½¼¡«»\
+    ░▒▓│┤╡╢╖╕╣║╗╝╜╛┐└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀\
+    αßΓπΣσµτΦΘΩδ∞φε∩≡±≥≤⌠⌡÷≈°∙·√ⁿ²■\u{a0}";
+
+fn is_cp437(label: &str) -> bool {
+    CP437_LABELS.contains(&label.to_lowercase().as_str())
+}
+
+/// Decodes text in code page 437, the character set of DOS and BBS
+/// art with its box drawing and block characters. Control characters
+/// are kept, as ANSI art needs the escape character.
+fn decode_cp437(buf: &[u8]) -> String {
+    let high: Vec<char> = CP437_HIGH.chars().collect();
+    buf.iter()
+        .map(|&b| match b {
+            0x7f => '⌂',
+            0x80..=0xff => high[b as usize - 0x80],
+            _ => b as char,
+        })
+        .collect()
+}
+
+/// Decodes text received from a server for display. Invalid UTF-8 is
+/// replaced and the result is normalized to NFC, so that decomposed
+/// characters are displayed correctly and match what the user types
+/// when searching.
+pub fn decode(buf: &[u8]) -> String {
+    normalize(&String::from_utf8_lossy(buf))
+}
+
+/// Normalizes a string to Unicode normalization form C
+pub fn normalize(text: &str) -> String {
+    text.nfc().collect()
+}
+
+/// Decodes text in the given encoding. Falls back to UTF-8 if no
+/// encoding is given or the encoding is unknown.
+pub fn decode_as(buf: &[u8], encoding: Option<&str>) -> String {
+    if encoding.is_some_and(is_cp437) {
+        return decode_cp437(buf);
+    }
+    match encoding.and_then(|label| Encoding::for_label(label.as_bytes())) {
+        Some(encoding) => {
+            let (text, malformed) = encoding.decode_without_bom_handling(buf);
+            if malformed {
+                info!("Text is not valid {}", encoding.name());
+            }
+            normalize(&text)
+        }
+        None => decode(buf),
+    }
+}
+
+/// Returns true if text in the encoding with the given label can be decoded
+pub fn is_known_encoding(label: &str) -> bool {
+    is_cp437(label) || Encoding::for_label(label.as_bytes()).is_some()
+}
+
+/// Returns the encoding pinned for a host in the settings, overriding
+/// automatic detection
+pub fn encoding_for_host(host: &str) -> Option<String> {
+    crate::SETTINGS
+        .read()
+        .unwrap()
+        .config
+        .encodings
+        .get(host)
+        .cloned()
+}
+
+/// Returns whether data looks like text, i.e. the beginning contains no
+/// NUL bytes and hardly any other control characters.
+pub fn looks_like_text(buf: &[u8]) -> bool {
+    let sample = &buf[..buf.len().min(4096)];
+    let control = sample
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\n' | b'\r' | b'\t' | 0x0c | 0x1b))
+        .count();
+    !sample.contains(&0) && control * 100 <= sample.len()
+}
+
+/// Decompresses a gzip file if the decompressed data is text. Returns
+/// `None` for other files.
+pub fn gunzip_text_file(path: &Path) -> Option<Vec<u8>> {
+    let mut file = File::open(path)
+        .map_err(|err| warn!("Could not open {}: {}", path.display(), err))
+        .ok()?;
+    let mut magic = [0u8; 2];
+    file.read_exact(&mut magic).ok()?;
+    if magic != [0x1f, 0x8b] {
+        return None;
+    }
+    file.seek(SeekFrom::Start(0)).ok()?;
+    gunzip_text_from(BufReader::new(file))
+}
+
+/// Decompresses gzip data, stopping at the beginning if it is not text
+fn gunzip_text_from(data: impl Read) -> Option<Vec<u8>> {
+    let failed = |err: std::io::Error| warn!("Could not decompress gzip data: {}", err);
+    let mut decoder = GzDecoder::new(data).take(MAX_DECOMPRESSED);
+    let mut text = Vec::new();
+    decoder
+        .by_ref()
+        .take(4096)
+        .read_to_end(&mut text)
+        .map_err(failed)
+        .ok()?;
+    // looks_like_text only checks the beginning, so archives and other
+    // binary data are not decompressed any further
+    if !looks_like_text(&text) {
+        return None;
+    }
+    decoder.read_to_end(&mut text).map_err(failed).ok()?;
+    Some(text)
+}
+
+/// Removes terminal escape sequences and other control characters
+/// except newlines and tabs from text received from a server, so that
+/// it cannot move the cursor, change the window title or otherwise
+/// take over the terminal when it is displayed.
+pub fn sanitize(text: &str) -> String {
+    let mut sanitized = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                // CSI: parameters and intermediate bytes, then a final byte
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('\x40'..='\x7e').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // OSC, DCS and other strings: terminated by BEL or ST
+                Some(']') | Some('P') | Some('X') | Some('^') | Some('_') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                _ => (),
+            },
+            '\n' | '\t' => sanitized.push(c),
+            c if c.is_control() => (),
+            c => sanitized.push(c),
+        }
+    }
+    sanitized
+}
+
+/// URL schemes that are opened from links found in plain text
+const LINK_SCHEMES: &[&str] = &["gopher", "gemini", "finger", "http", "https"];
+
+/// Returns the URLs in a text that ncgopher can open
+pub fn find_links(text: &str) -> Vec<Url> {
+    LinkFinder::new()
+        .links(text)
+        .filter(|link| link.kind() == &LinkKind::Url)
+        .filter_map(|link| Url::parse(link.as_str()).ok())
+        .filter(|url| LINK_SCHEMES.contains(&url.scheme()))
+        .collect()
+}
+
+/// Returns the leading whitespace of a line
+fn indentation(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+/// Wraps a line, indenting all rows like the first one
+fn wrap_line(indent: &str, text: &str, viewport_width: usize) -> Vec<(String, Option<Url>)> {
+    let mut links = find_links(text);
+    let url = match links.len() {
+        1 => links.pop(),
+        // several links are offered to choose from when the line is
+        // selected
+        _ => None,
+    };
+    // very deep indentation is not kept, to leave room for the text
+    let indent = if indent.width() * 2 > viewport_width { "" } else { indent };
+    LinesIterator::new(text, viewport_width - indent.width())
+        .map(|row| (format!("{}{}", indent, &text[row.start..row.end]), url.clone()))
+        .collect()
+}
+
+/// Returns whether a line is prose that may be reflowed together with
+/// the lines around it, i.e. not a list item or a row of a table or of
+/// aligned columns
+fn is_prose(line: &str) -> bool {
+    let line = line.trim();
+    let bullet = ["- ", "* ", "+ ", "• "].iter().any(|b| line.starts_with(b));
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    let numbered = digits > 0 && [". ", ") "].iter().any(|s| line[digits..].starts_with(s));
+    let columns = line.contains('|') || line.contains('\t') || line.contains("  ");
+    !(bullet || numbered || columns)
+}
+
+/// Wraps plain text to the viewport width, keeping the indentation of
+/// wrapped lines. Paragraphs of prose, i.e. lines with the same
+/// indentation, that do not fit are reflowed as a whole instead of
+/// breaking every line, which makes text hard-wrapped at a larger width
+/// readable. Lists, tables and preformatted blocks between ``` lines
+/// are wrapped line by line. The rows of a line with a single link point
+/// to its URL, so that it can be opened like a link of a gemini page.
+pub fn wrap_with_links(text: &str, viewport_width: usize) -> Vec<(String, Option<Url>)> {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut preformatted = false;
+    let prose: Vec<bool> = lines
+        .iter()
+        .map(|line| {
+            if line.trim_start().starts_with("```") {
+                preformatted = !preformatted;
+                return false;
+            }
+            !preformatted && is_prose(line)
+        })
+        .collect();
+    let mut rows = vec![];
+    let mut i = 0;
+    while i < lines.len() {
+        if lines[i].trim().is_empty() {
+            rows.push((String::new(), None));
+            i += 1;
+            continue;
+        }
+        let indent = indentation(lines[i]);
+        if !prose[i] {
+            rows.extend(wrap_line(indent, lines[i].trim_start(), viewport_width));
+            i += 1;
+            continue;
+        }
+        let mut end = i + 1;
+        while end < lines.len()
+            && prose[end]
+            && !lines[end].trim().is_empty()
+            && indentation(lines[end]) == indent
+        {
+            end += 1;
+        }
+        let paragraph = &lines[i..end];
+        if paragraph.len() > 1 && paragraph.iter().any(|line| line.width() > viewport_width) {
+            let text = paragraph.iter().map(|line| line.trim()).collect::<Vec<_>>().join(" ");
+            rows.extend(wrap_line(indent, &text, viewport_width));
+        } else {
+            for line in paragraph {
+                rows.extend(wrap_line(indent, line.trim_start(), viewport_width));
+            }
+        }
+        i = end;
+    }
+    rows
+}
+
+/// Wraps plain text like `wrap_with_links`, but with a line number in
+/// front of every line, starting at `first_line`. Lines are wrapped one
+/// by one, so that the numbers match the lines of the file.
+pub fn wrap_with_line_numbers(
+    text: &str,
+    viewport_width: usize,
+    first_line: usize,
+) -> Vec<(StyledString, Option<Url>)> {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let gutter = (first_line + lines.len() - 1).to_string().len();
+    let viewport_width = viewport_width.saturating_sub(gutter + 1).max(1);
+    let mut rows = vec![];
+    for (number, line) in lines.iter().enumerate() {
+        let mut wrapped = wrap_line(indentation(line), line.trim_start(), viewport_width);
+        if wrapped.is_empty() {
+            wrapped.push((String::new(), None));
+        }
+        for (i, (row, url)) in wrapped.into_iter().enumerate() {
+            let number = if i == 0 {
+                format!("{:>1$} ", first_line + number, gutter)
+            } else {
+                " ".repeat(gutter + 1)
+            };
+            let mut styled = StyledString::styled(number, ColorStyle::secondary());
+            styled.append_plain(row);
+            rows.push((styled, url));
+        }
+    }
+    rows
+}
+
+/// Puts line numbers, starting at `first_line`, in front of rows that
+/// are not wrapped, e.g. of highlighted source code
+pub fn number_rows(
+    rows: Vec<(StyledString, Option<Url>)>,
+    first_line: usize,
+) -> Vec<(StyledString, Option<Url>)> {
+    let gutter = (first_line + rows.len() - 1).to_string().len();
+    rows.into_iter()
+        .enumerate()
+        .map(|(number, (row, url))| {
+            let number = format!("{:>1$} ", first_line + number, gutter);
+            let mut styled = StyledString::styled(number, ColorStyle::secondary());
+            styled.append(row);
+            (styled, url)
+        })
+        .collect()
+}
+
+/// Returns the headings of a text with their level: markdown and gemtext
+/// headings, lines underlined with = or -, and short lines standing alone
+/// between blank lines
+pub fn outline(text: &str) -> Vec<(usize, String)> {
+    let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    let blank = |i: usize| lines.get(i).is_none_or(|line| line.trim().is_empty());
+    let underline = |i: usize, c: char| {
+        lines
+            .get(i)
+            .is_some_and(|line| line.len() >= 3 && line.chars().all(|x| x == c))
+    };
+    let mut headings = Vec::new();
+    let mut preformatted = false;
+    for (i, line) in lines.iter().enumerate() {
+        if line.starts_with("```") {
+            preformatted = !preformatted;
+            continue;
+        }
+        if preformatted || line.trim().is_empty() {
+            continue;
+        }
+        let hashes = line.chars().take_while(|c| *c == '#').count();
+        if (1..=3).contains(&hashes) {
+            let title = line[hashes..].trim();
+            if !title.is_empty() {
+                headings.push((hashes, title.to_string()));
+            }
+        } else if underline(i + 1, '=') {
+            headings.push((1, line.trim().to_string()));
+        } else if underline(i + 1, '-') {
+            headings.push((2, line.trim().to_string()));
+        } else if (i == 0 || blank(i - 1))
+            && blank(i + 1)
+            && (3..=60).contains(&line.trim().width())
+            && line.chars().any(char::is_alphabetic)
+            && !line.starts_with(char::is_whitespace)
+            && !line.ends_with(|c| ".,;!?".contains(c))
+            && !["=>", "* ", "- ", "> "].iter().any(|m| line.starts_with(m))
+            && !line.chars().all(|c| c == '=' || c == '-')
+        {
+            headings.push((1, line.trim().to_string()));
+        }
+    }
+    headings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrapped(text: &str, viewport_width: usize) -> Vec<String> {
+        wrap_with_links(text, viewport_width)
+            .into_iter()
+            .map(|(row, _)| row)
+            .collect()
+    }
+
+    #[test]
+    fn prose_is_reflowed() {
+        let text = "one two\nthree four five six seven";
+        assert_eq!(
+            wrapped(text, 16),
+            ["one two three ", "four five six ", "seven"]
+        );
+    }
+
+    #[test]
+    fn list_items_are_not_joined() {
+        let text = "- one\n- two three four five six\n1. seven";
+        assert_eq!(
+            wrapped(text, 16),
+            ["- one", "- two three four", "five six", "1. seven"]
+        );
+    }
+
+    #[test]
+    fn preformatted_lines_are_not_joined() {
+        let text = "```\nlet x = 1;\nlet y = x + 1; // two\n```";
+        assert_eq!(
+            wrapped(text, 16),
+            ["```", "let x = 1;", "let y = x + ", "1; // two", "```"]
+        );
+    }
+}
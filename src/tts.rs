@@ -0,0 +1,127 @@
+use std::process::{Child, Stdio};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::external;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Reading,
+    Paused,
+    Stopped,
+}
+
+/// Reads text aloud with an external text-to-speech command like
+/// espeak-ng or say, which is called once per sentence.
+#[derive(Clone)]
+pub struct Reader {
+    state: Arc<(Mutex<State>, Condvar)>,
+    child: Arc<Mutex<Option<Child>>>,
+}
+
+/// Splits text into sentences. Blank lines end a sentence, so headings
+/// and list items are read separately.
+fn sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    for paragraph in text.split("\n\n") {
+        let mut sentence = String::new();
+        for word in paragraph.split_whitespace() {
+            if !sentence.is_empty() {
+                sentence.push(' ');
+            }
+            sentence.push_str(word);
+            if word.ends_with(&['.', '!', '?', ':'][..]) {
+                sentences.push(std::mem::take(&mut sentence));
+            }
+        }
+        if !sentence.is_empty() {
+            sentences.push(sentence);
+        }
+    }
+    sentences
+}
+
+impl Reader {
+    /// Starts reading the text in the background
+    pub fn start(command: String, text: &str) -> Reader {
+        let reader = Reader {
+            state: Arc::new((Mutex::new(State::Reading), Condvar::new())),
+            child: Arc::new(Mutex::new(None)),
+        };
+        let sentences = sentences(text);
+        let r = reader.clone();
+        thread::spawn(move || {
+            for sentence in sentences {
+                let (lock, cvar) = &*r.state;
+                let mut state = lock.lock().unwrap();
+                while *state == State::Paused {
+                    state = cvar.wait(state).unwrap();
+                }
+                if *state == State::Stopped {
+                    return;
+                }
+                drop(state);
+
+                let child = external::command(&command, &sentence).and_then(|mut command| {
+                    command
+                        .stdin(Stdio::null())
+                        .stdout(Stdio::null())
+                        .stderr(Stdio::null())
+                        .spawn()
+                });
+                match child {
+                    Ok(child) => *r.child.lock().unwrap() = Some(child),
+                    Err(err) => {
+                        warn!("Could not run text to speech command {}: {}", command, err);
+                        break;
+                    }
+                }
+                // Poll instead of waiting, so that stop can kill the command
+                loop {
+                    let mut guard = r.child.lock().unwrap();
+                    match guard.as_mut().map(|child| child.try_wait()) {
+                        Some(Ok(None)) => (),
+                        _ => {
+                            *guard = None;
+                            break;
+                        }
+                    }
+                    drop(guard);
+                    thread::sleep(Duration::from_millis(50));
+                }
+            }
+            r.stop();
+        });
+        reader
+    }
+
+    /// Pauses after the current sentence or resumes reading. Returns
+    /// whether the reader is paused now.
+    pub fn toggle_pause(&self) -> bool {
+        let (lock, cvar) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        *state = match *state {
+            State::Reading => State::Paused,
+            State::Paused => State::Reading,
+            State::Stopped => State::Stopped,
+        };
+        cvar.notify_all();
+        *state == State::Paused
+    }
+
+    /// Stops reading immediately
+    pub fn stop(&self) {
+        let (lock, cvar) = &*self.state;
+        *lock.lock().unwrap() = State::Stopped;
+        cvar.notify_all();
+        if let Some(mut child) = self.child.lock().unwrap().take() {
+            child.kill().ok();
+            child.wait().ok();
+        }
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        *self.state.0.lock().unwrap() == State::Stopped
+    }
+}
@@ -0,0 +1,158 @@
+use crate::controller::Controller;
+use crate::ui::dialogs;
+use crate::SETTINGS;
+use cursive::Cursive;
+use url::Url;
+
+/// Commands of the command line and their arguments
+const COMMANDS: &[(&str, &str)] = &[
+    ("open", "URL"),
+    ("back", ""),
+    ("forward", ""),
+    ("reload", ""),
+    ("up", ""),
+    ("goto", "LINE|PERCENT%"),
+    ("bookmark", "[TITLE]"),
+    ("save", "FILE"),
+    ("search", "TEXT"),
+    ("set", "KEY [VALUE]"),
+    ("history", ""),
+    ("sync", ""),
+    ("downloads", ""),
+    ("messages", ""),
+    ("split", ""),
+    ("toc", ""),
+    ("reading", ""),
+    ("keys", ""),
+    ("quit", ""),
+];
+
+/// Runs a command typed after ':', e.g. "open gemini://example.org".
+/// Commands may be abbreviated as long as they are unambiguous.
+pub fn run(app: &mut Cursive, line: &str) {
+    let line = line.trim();
+    let (name, argument) = match line.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (line, ""),
+    };
+    if name.is_empty() {
+        return;
+    }
+    // ":42" goes to line 42
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        crate::ui::setup::go_to_line(app, line);
+        return;
+    }
+    let matches: Vec<&(&str, &str)> = COMMANDS
+        .iter()
+        .filter(|(command, _)| command.starts_with(name))
+        .collect();
+    let (command, usage) = match matches.as_slice() {
+        [command] => **command,
+        _ => match COMMANDS.iter().find(|(command, _)| *command == name) {
+            Some(command) => *command,
+            None => {
+                let message = match matches.len() {
+                    0 => format!("Unknown command {}. Commands: {}", name, command_names()),
+                    _ => format!("Ambiguous command {}", name),
+                };
+                set_message(app, &message);
+                return;
+            }
+        },
+    };
+    if usage.starts_with(char::is_uppercase) && argument.is_empty() {
+        set_message(app, &format!("Usage: :{} {}", command, usage));
+        return;
+    }
+
+    let controller = app.user_data::<Controller>().expect("controller missing");
+    match command {
+        "open" => Controller::open_url_action(app, argument),
+        "back" => controller.navigate_back(),
+        "forward" => controller.navigate_forward(),
+        "reload" => {
+            let index = Controller::get_selected_item_index(app);
+            let controller = app.user_data::<Controller>().expect("controller missing");
+            let current_url = controller.current_url.lock().unwrap().clone();
+            controller.open_url(current_url, false, index);
+        }
+        "up" => dialogs::go_up(app),
+        "goto" => crate::ui::setup::go_to_line(app, argument),
+        "bookmark" => {
+            let current_url = controller.current_url.lock().unwrap().clone();
+            if argument.is_empty() {
+                dialogs::add_bookmark(app, current_url);
+            } else {
+                controller.add_bookmark_action(
+                    current_url,
+                    argument.to_string(),
+                    String::new(),
+                    Vec::new(),
+                );
+                controller.set_message(&format!("Bookmark {} added", argument));
+            }
+        }
+        "save" => controller.save_page(argument.to_string()),
+        "search" => controller.search(argument.to_string()),
+        "set" => set(app, argument),
+        "history" => dialogs::edit_history(app),
+        "sync" => controller.sync_bookmarks(),
+        "downloads" => dialogs::downloads(app),
+        "messages" => dialogs::message_history(app),
+        "split" => Controller::toggle_split_action(app),
+        "toc" => crate::ui::toc::toggle_toc(app),
+        "reading" => Controller::toggle_reading_mode_action(app),
+        "keys" => controller.open_url(Url::parse("about:keys").unwrap(), true, 0),
+        "quit" => app.quit(),
+        _ => unreachable!("command {} not handled", command),
+    }
+}
+
+/// Shows or changes a setting, e.g. ":set number_links true"
+fn set(app: &mut Cursive, argument: &str) {
+    let (key, value) = match argument.split_once(char::is_whitespace) {
+        Some((key, value)) => (key, value.trim()),
+        None => {
+            let message = match SETTINGS.read().unwrap().get_option(argument) {
+                Some(value) => format!("{} = {}", argument, value),
+                None => format!("Unknown setting {}", argument),
+            };
+            set_message(app, &message);
+            return;
+        }
+    };
+    let result = SETTINGS.write().unwrap().set_option(key, value);
+    if let Err(err) = result {
+        set_message(app, &format!("Could not set {}: {}", key, err));
+        return;
+    }
+    match key {
+        "theme" => crate::ui::theme::load_theme(app, value.trim_matches('"')),
+        "autohide_menu" => {
+            let autohide = SETTINGS.read().unwrap().config.autohide_menu;
+            app.set_autohide_menu(autohide);
+        }
+        _ => (),
+    }
+    Controller::render_again(app);
+    let message = match SETTINGS.write().unwrap().write_settings_to_file() {
+        Ok(()) => format!("{} = {}", key, value),
+        Err(err) => format!("Could not write config file: {}", err),
+    };
+    set_message(app, &message);
+}
+
+fn command_names() -> String {
+    COMMANDS
+        .iter()
+        .map(|(command, _)| *command)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn set_message(app: &mut Cursive, message: &str) {
+    app.user_data::<Controller>()
+        .expect("controller missing")
+        .set_message(message);
+}
@@ -0,0 +1,81 @@
+use cursive::{
+    event::Event,
+    view::{Nameable, Resizable},
+    views::{Dialog, LinearLayout, OnEventView, TextArea, TextView},
+    Cursive,
+};
+use std::env;
+use std::fs;
+use std::sync::Arc;
+
+/// Opens a dialog with a multi-line text editor, which the single-line
+/// EditView cannot provide. Besides the usual editing keys of the text
+/// area, Ctrl-E opens the text in the external editor from $VISUAL or
+/// $EDITOR. `on_submit` is called with the text when the user confirms.
+pub fn composer<F>(app: &mut Cursive, title: &str, content: &str, on_submit: F)
+where
+    F: Fn(&mut Cursive, String) + 'static + Send + Sync,
+{
+    let on_submit = Arc::new(on_submit);
+    let editor = OnEventView::new(TextArea::new().content(content).with_name("composer"))
+        .on_event(Event::CtrlChar('e'), open_in_external_editor);
+    app.add_layer(
+        Dialog::new()
+            .title(title)
+            .content(
+                LinearLayout::vertical()
+                    .child(editor.min_width(60).min_height(8))
+                    .child(TextView::new("Ctrl-E: edit in external editor")),
+            )
+            .button("Cancel", |app| {
+                app.pop_layer();
+            })
+            .button("External editor", open_in_external_editor)
+            .button("Ok", move |app| {
+                let text = app
+                    .find_name::<TextArea>("composer")
+                    .expect("composer missing")
+                    .get_content()
+                    .to_string();
+                app.pop_layer();
+                on_submit(app, text);
+            }),
+    );
+}
+
+fn open_in_external_editor(app: &mut Cursive) {
+    let content = app
+        .find_name::<TextArea>("composer")
+        .expect("composer missing")
+        .get_content()
+        .to_string();
+    match edit_externally(app, &content) {
+        Ok(text) => {
+            app.find_name::<TextArea>("composer")
+                .expect("composer missing")
+                .set_content(text.trim_end_matches('\n'));
+        }
+        Err(err) => app.add_layer(Dialog::info(format!("Could not run editor: {}", err))),
+    }
+}
+
+/// Writes the content to a temporary file, lets the user edit it with the
+/// external editor and returns the edited text.
+fn edit_externally(app: &mut Cursive, content: &str) -> std::io::Result<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+
+    let path = env::temp_dir().join(format!("ncgopher-{}.txt", std::process::id()));
+    fs::write(&path, content)?;
+
+    let status = crate::external::run_in_foreground(app, &editor, &path.display().to_string());
+    let text = fs::read_to_string(&path);
+    fs::remove_file(&path).ok();
+
+    let status = status?;
+    if !status.success() {
+        warn!("Editor {} exited with {}", editor, status);
+    }
+    text
+}
@@ -1,16 +1,29 @@
 use crate::bookmarks::Bookmark;
 use crate::clientcertificates::ClientCertificate;
-use crate::history::HistoryEntry;
-use crate::url_tools::download_filename_from_url;
+use crate::controller::{human_readable_size, CertificateInfo};
+use crate::downloads::{Download, DownloadState};
+use crate::external;
+use crate::gopher;
+use crate::text::sanitize;
+use crate::gophermap::{lint, GopherMapEntry, ItemType, LintIssue};
+use crate::history::{HistoryEntry, PageMatch};
+use crate::url_tools::{ancestors, breadcrumb, download_filename_from_url, human_readable_url};
+use crate::session::Session;
+use crate::uuencode::UudecodedFile;
 use crate::{Controller, SETTINGS};
 use cursive::{
+    event::Key,
+    theme::{BaseColor, Color, ColorStyle},
+    utils::markup::StyledString,
     view::{Nameable, Resizable, Scrollable},
     views::{
-        Button, Checkbox, Dialog, DummyView, EditView, LinearLayout, RadioButton, RadioGroup,
-        SelectView, TextArea, TextView,
+        Button, Checkbox, Dialog, DummyView, EditView, LinearLayout, OnEventView, RadioButton,
+        RadioGroup, SelectView, TextArea, TextView,
     },
     Cursive,
 };
+use qrcode::{render::unicode, QrCode};
+use std::path::Path;
 use std::time::SystemTime;
 use std::vec::Vec;
 use time::{format_description, Date, OffsetDateTime};
@@ -23,13 +36,75 @@ pub(super) fn add_bookmark_current_url(app: &mut Cursive) {
 }
 
 pub(crate) fn add_bookmark(app: &mut Cursive, url: Url) {
-    edit_bookmark(app, url, "", "");
+    edit_bookmark(app, None, url, "", "", "");
 }
 
-pub fn edit_bookmark(app: &mut Cursive, url: Url, title: &str, tags: &str) {
+/// Edits the bookmark of the current page
+pub(super) fn edit_current_bookmark(app: &mut Cursive) {
+    match current_bookmark(app) {
+        Some(b) => edit_existing_bookmark(app, b),
+        None => app
+            .user_data::<Controller>()
+            .expect("controller missing")
+            .set_message("This page is not bookmarked"),
+    }
+}
+
+/// Moves the bookmark of the current page to the trash
+pub(super) fn delete_current_bookmark(app: &mut Cursive) {
+    match current_bookmark(app) {
+        Some(b) => Controller::remove_bookmark_action(app, b),
+        None => app
+            .user_data::<Controller>()
+            .expect("controller missing")
+            .set_message("This page is not bookmarked"),
+    }
+}
+
+fn current_bookmark(app: &mut Cursive) -> Option<Bookmark> {
+    let controller = app.user_data::<Controller>().expect("controller missing");
+    let current_url = controller.current_url.lock().unwrap().clone();
+    let bookmarks = controller.bookmarks.lock().unwrap();
+    bookmarks
+        .entries
+        .iter()
+        .find(|b| b.url == current_url)
+        .cloned()
+}
+
+fn edit_existing_bookmark(app: &mut Cursive, b: Bookmark) {
+    let mirrors = b
+        .mirrors
+        .iter()
+        .map(Url::as_str)
+        .collect::<Vec<_>>()
+        .join(" ");
+    edit_bookmark(
+        app,
+        Some(b.url.clone()),
+        b.url.clone(),
+        &b.title,
+        &b.tags.join(","),
+        &mirrors,
+    );
+}
+
+/// Adds a bookmark, or edits the one with the URL `original`
+pub fn edit_bookmark(
+    app: &mut Cursive,
+    original: Option<Url>,
+    url: Url,
+    title: &str,
+    tags: &str,
+    mirrors: &str,
+) {
+    let dialog_title = match original {
+        Some(_) => "Edit Bookmark",
+        None => "Add Bookmark",
+    };
     app.add_layer(
         Dialog::new()
-            .title("Add Bookmark")
+            .title(dialog_title)
             .content(
                 LinearLayout::vertical()
                     .child(TextView::new("URL:"))
@@ -52,20 +127,39 @@ pub fn edit_bookmark(app: &mut Cursive, url: Url, title: &str, tags: &str) {
                             .content(tags)
                             .with_name("tags")
                             .fixed_width(30),
+                    )
+                    .child(TextView::new("Mirrors (space separated URLs):"))
+                    .child(
+                        EditView::new()
+                            .content(mirrors)
+                            .with_name("mirrors")
+                            .fixed_width(30),
                     ),
             )
-            .button("Ok", |app| {
+            .button("Ok", move |app| {
                 let url = app.find_name::<EditView>("url").unwrap().get_content();
                 let title = app.find_name::<EditView>("title").unwrap().get_content();
                 let tags = app.find_name::<EditView>("tags").unwrap().get_content();
+                let mirrors = app.find_name::<EditView>("mirrors").unwrap().get_content();
+                let mirrors = mirrors
+                    .split_whitespace()
+                    .map(Url::parse)
+                    .collect::<Result<Vec<Url>, _>>();
 
                 // Validate URL
-                if let Ok(url) = Url::parse(&url) {
+                if let (Ok(url), Ok(mirrors)) = (Url::parse(&url), mirrors) {
                     // close edit bookmark
                     app.pop_layer();
-                    app.user_data::<Controller>()
-                        .expect("controller missing")
-                        .add_bookmark_action(url, (*title).clone(), (*tags).clone());
+                    let (title, tags) = ((*title).clone(), (*tags).clone());
+                    match &original {
+                        Some(original) => Controller::update_bookmark_action(
+                            app, original, url, title, tags, mirrors,
+                        ),
+                        None => app
+                            .user_data::<Controller>()
+                            .expect("controller missing")
+                            .add_bookmark_action(url, title, tags, mirrors),
+                    }
                 } else {
                     // do not close the dialog so the user can make
                     // corrections
@@ -78,21 +172,284 @@ pub fn edit_bookmark(app: &mut Cursive, url: Url, title: &str, tags: &str) {
     );
 }
 
-pub(crate) fn certificate_changed(app: &mut Cursive, url: Url, fingerprint: String) {
+/// Lets the user choose one of the views of a Gopher+ item
+pub(crate) fn gopher_plus_views(app: &mut Cursive, url: Url, views: Vec<gopher::View>) {
+    let mut view: SelectView<gopher::View> = SelectView::new();
+    for v in views {
+        let label = format!(
+            "{:<30} {:<8} {:>8}",
+            v.mime,
+            v.language.as_deref().unwrap_or_default(),
+            v.size.as_deref().unwrap_or_default()
+        );
+        view.add_item(label, v);
+    }
+    view.set_on_submit(move |app, v: &gopher::View| {
+        app.pop_layer();
+        app.user_data::<Controller>()
+            .expect("controller missing")
+            .fetch_gopher_plus_view(url.clone(), v.clone());
+    });
     app.add_layer(
         Dialog::new()
-            .title("Certificate warning")
-            .content(TextView::new(format!("The certificate for the following domain has changed:\n{}\nDo you want to continue?", url.host_str().unwrap())))
+            .title("Choose a view")
+            .content(view.scrollable())
             .button("Cancel", |app| {
-                app.pop_layer(); // Close dialog
+                app.pop_layer();
+            }),
+    );
+}
+
+/// Lets the user choose one of several links found in a line of text
+pub(crate) fn choose_link(app: &mut Cursive, links: Vec<Url>) {
+    let mut view: SelectView<Url> = SelectView::new();
+    for url in links {
+        view.add_item(url.to_string(), url);
+    }
+    view.set_on_submit(|app, url: &Url| {
+        app.pop_layer();
+        app.user_data::<Controller>()
+            .expect("controller missing")
+            .open_url(url.clone(), true, 0);
+    });
+    app.add_layer(
+        Dialog::new()
+            .title("Open link")
+            .content(view.scrollable())
+            .button("Cancel", |app| {
+                app.pop_layer();
+            }),
+    );
+}
+
+/// Shows the Gopher+ attributes of an item
+pub(crate) fn item_info(app: &mut Cursive, url: Url, blocks: Vec<gopher::AttributeBlock>) {
+    let mut text = String::new();
+    for block in blocks {
+        text.push_str(&format!("{}:\n{}\n\n", block.name, block.value));
+    }
+    app.add_layer(
+        Dialog::new()
+            .title(url.as_str())
+            .content(TextView::new(sanitize(text.trim_end())).scrollable())
+            .button("Close", |app| {
+                app.pop_layer();
+            }),
+    );
+}
+
+/// Asks the questions of a Gopher+ form and sends the answers
+pub(crate) fn ask_form(
+    app: &mut Cursive,
+    url: Url,
+    item_type: ItemType,
+    questions: Vec<gopher::Question>,
+) {
+    let mut form = LinearLayout::vertical();
+    for (i, question) in questions.iter().enumerate() {
+        let name = format!("ask_{}", i);
+        match question {
+            gopher::Question::Note(text) => form.add_child(TextView::new(sanitize(text))),
+            gopher::Question::Ask { prompt, default } => {
+                form.add_child(TextView::new(sanitize(prompt)));
+                form.add_child(
+                    EditView::new()
+                        .content(default.as_str())
+                        .with_name(name)
+                        .fixed_width(50),
+                );
+            }
+            gopher::Question::AskPassword { prompt } => {
+                form.add_child(TextView::new(sanitize(prompt)));
+                form.add_child(EditView::new().secret().with_name(name).fixed_width(50));
+            }
+            gopher::Question::AskLong { prompt } => {
+                form.add_child(TextView::new(sanitize(prompt)));
+                form.add_child(TextArea::new().with_name(name).fixed_size((70, 5)));
+            }
+            gopher::Question::Select { prompt, default } => form.add_child(
+                LinearLayout::horizontal()
+                    .child(Checkbox::new().with_checked(*default).with_name(name))
+                    .child(DummyView)
+                    .child(TextView::new(sanitize(prompt))),
+            ),
+            gopher::Question::Choose { prompt, options } => {
+                let mut choice: SelectView<String> = SelectView::new().popup();
+                choice.add_all_str(options.iter().map(|o| sanitize(o)));
+                form.add_child(TextView::new(sanitize(prompt)));
+                form.add_child(choice.with_name(name));
+            }
+        }
+    }
+    app.add_layer(
+        Dialog::new()
+            .title("Gopher+ form")
+            .content(form.scrollable())
+            .button("Cancel", |app| {
+                app.pop_layer();
             })
-            .button("Accept the risk", move |app| {
-                app.pop_layer(); // Close dialog
+            .button("Ok", move |app| {
+                let mut answers = Vec::new();
+                for (i, question) in questions.iter().enumerate() {
+                    let name = format!("ask_{}", i);
+                    let answer = match question {
+                        gopher::Question::Note(_) => continue,
+                        gopher::Question::Select { .. } => {
+                            let checked = app.find_name::<Checkbox>(&name).unwrap().is_checked();
+                            String::from(if checked { "1" } else { "0" })
+                        }
+                        gopher::Question::Choose { .. } => app
+                            .find_name::<SelectView<String>>(&name)
+                            .unwrap()
+                            .selection()
+                            .map(|choice| (*choice).clone())
+                            .unwrap_or_default(),
+                        gopher::Question::AskLong { .. } => {
+                            app.find_name::<TextArea>(&name).unwrap().get_content().to_string()
+                        }
+                        _ => app.find_name::<EditView>(&name).unwrap().get_content().to_string(),
+                    };
+                    answers.push(answer);
+                }
+                app.pop_layer();
+                app.user_data::<Controller>()
+                    .expect("controller missing")
+                    .submit_ask_form(url.clone(), item_type, questions.clone(), answers);
+            }),
+    );
+}
+
+/// Asks for the search terms of a CSO phonebook query
+pub(crate) fn cso_query(app: &mut Cursive, url: Url) {
+    app.add_layer(
+        Dialog::new()
+            .title("Phonebook query")
+            .content(
+                LinearLayout::vertical()
+                    .child(TextView::new("Name or field=value, e.g. name=smith"))
+                    .child(
+                        EditView::new()
+                            .on_submit({
+                                let url = url.clone();
+                                move |app, query| {
+                                    app.pop_layer();
+                                    Controller::cso_query_action(app, url.clone(), query);
+                                }
+                            })
+                            .with_name("cso_query")
+                            .fixed_width(40),
+                    ),
+            )
+            .button("Cancel", |app| {
+                app.pop_layer();
+            })
+            .button("Ok", move |app| {
+                let query = app
+                    .find_name::<EditView>("cso_query")
+                    .expect("query field missing")
+                    .get_content();
+                app.pop_layer();
+                Controller::cso_query_action(app, url.clone(), &query);
+            }),
+    );
+}
+
+/// Asks before opening a web page in the browser
+pub(crate) fn confirm_browser(app: &mut Cursive, url: Url) {
+    app.add_layer(
+        Dialog::new()
+            .title("Open in browser")
+            .content(
+                LinearLayout::vertical()
+                    .child(TextView::new(format!(
+                        "Open the following page in the browser?\n{}\n",
+                        url
+                    )))
+                    .child(
+                        LinearLayout::horizontal()
+                            .child(Checkbox::new().with_name("confirm_browser_off"))
+                            .child(DummyView)
+                            .child(TextView::new("Do not ask again")),
+                    ),
+            )
+            .button("Cancel", |app| {
+                app.pop_layer();
+            })
+            .button("Open", move |app| {
+                let dont_ask = app
+                    .find_name::<Checkbox>("confirm_browser_off")
+                    .unwrap()
+                    .is_checked();
+                app.pop_layer();
+                if dont_ask {
+                    SETTINGS.write().unwrap().config.confirm_browser = false;
+                    if let Err(why) = SETTINGS.write().unwrap().write_settings_to_file() {
+                        app.add_layer(Dialog::info(format!("Could not write config file: {}", why)));
+                    }
+                }
+                app.user_data::<Controller>()
+                    .expect("controller missing")
+                    .open_command("html_command", url.clone())
+                    .unwrap();
+            }),
+    );
+}
+
+/// Asks whether to trust an unknown or changed server certificate
+pub(crate) fn certificate(app: &mut Cursive, url: Url, info: CertificateInfo, changed: bool) {
+    let mut text = match changed {
+        true => StyledString::styled(
+            format!(
+                "The certificate of {} has changed!\nSomeone may be intercepting \
+                 the connection, or the server has a new certificate.\n\n",
+                info.server
+            ),
+            Color::Light(BaseColor::Red),
+        ),
+        false => StyledString::plain(format!(
+            "The certificate of {} is not known yet.\n\n",
+            info.server
+        )),
+    };
+    let issuer = match info.issuer == info.subject {
+        true => format!("{} (self-signed)", info.issuer),
+        false => info.issuer.clone(),
+    };
+    text.append_plain(format!(
+        "Subject:     {}\nIssuer:      {}\nExpires:     {}{}\nFingerprint: {}",
+        info.subject,
+        issuer,
+        info.expires,
+        if info.expired { " (expired)" } else { "" },
+        info.fingerprint
+    ));
+    let once = url.clone();
+    let fingerprint = info.fingerprint.clone();
+    app.add_layer(
+        Dialog::new()
+            .title(if changed { "Certificate changed" } else { "Unknown certificate" })
+            .content(TextView::new(text))
+            .button("Abort", |app| {
+                app.pop_layer();
+            })
+            .button("Accept once", move |app| {
+                app.pop_layer();
+                let controller = app.user_data::<Controller>().expect("controller missing");
+                controller
+                    .accepted_certificates
+                    .lock()
+                    .unwrap()
+                    .insert((info.server.clone(), info.fingerprint.clone()));
+                controller.open_url(once.clone(), true, 0);
+            })
+            .button("Accept permanently", move |app| {
+                app.pop_layer();
                 Controller::certificate_changed_action(app, &url, fingerprint.clone());
                 app.user_data::<Controller>()
                     .expect("controller missing")
                     .open_url(url.clone(), true, 0);
             })
+            .max_width(90),
     );
 }
 
@@ -106,16 +463,15 @@ pub(super) fn edit_bookmarks(app: &mut Cursive) {
         .get_bookmarks();
     let mut view: SelectView<Bookmark> = SelectView::new();
     for b in bookmarks {
-        let mut title = format!("{:<20}", b.title.clone().as_str());
-        title.truncate(20);
-        let mut url = format!("{:<50}", b.url.clone().as_str());
-        url.truncate(50);
-        view.add_item(format!("{} | {}", title, url), b);
+        view.add_item(bookmark_label(&b), b);
     }
     app.add_layer(
         Dialog::new()
             .title("Edit bookmarks")
             .content(LinearLayout::vertical().child(view.with_name("bookmarks").scrollable()))
+            .button("Slot...", bookmark_slot)
+            .button("Move up", |app| move_selected_bookmark(app, -1))
+            .button("Move down", |app| move_selected_bookmark(app, 1))
             .button("Delete", |app| {
                 let selected = app
                     .call_on_name("bookmarks", |view: &mut SelectView<Bookmark>| {
@@ -130,128 +486,853 @@ pub(super) fn edit_bookmarks(app: &mut Cursive) {
                         })
                         .unwrap();
 
-                        Controller::remove_bookmark_action(app, (*bookmark).clone());
-                    }
-                }
-            })
-            .button("Open", |app| {
-                let selected = app
-                    .find_name::<SelectView<Bookmark>>("bookmarks")
-                    .expect("bookmarks view missing")
-                    .selection();
-                match selected {
-                    None => (),
-                    Some(b) => {
-                        app.user_data::<Controller>()
-                            .expect("controller missing")
-                            .open_url(b.url.clone(), true, 0);
-                    }
-                }
+                        Controller::remove_bookmark_action(app, (*bookmark).clone());
+                    }
+                }
+            })
+            .button("Open", |app| {
+                let selected = app
+                    .find_name::<SelectView<Bookmark>>("bookmarks")
+                    .expect("bookmarks view missing")
+                    .selection();
+                match selected {
+                    None => (),
+                    Some(b) => {
+                        app.user_data::<Controller>()
+                            .expect("controller missing")
+                            .open_url(b.url.clone(), true, 0);
+                    }
+                }
+                app.pop_layer();
+            })
+            .button("Edit", |app| {
+                let selected = app
+                    .call_on_name("bookmarks", |view: &mut SelectView<Bookmark>| {
+                        view.selection()
+                    })
+                    .unwrap();
+                match selected {
+                    None => (),
+                    Some(b) => {
+                        app.pop_layer();
+                        edit_existing_bookmark(app, (*b).clone());
+                    }
+                }
+            })
+            .button("Close", |app| {
+                app.pop_layer();
+            }),
+    );
+}
+
+/// Moves the bookmark selected in the bookmark manager up or down
+fn move_selected_bookmark(app: &mut Cursive, offset: isize) {
+    let selected = app
+        .find_name::<SelectView<Bookmark>>("bookmarks")
+        .expect("bookmarks view missing")
+        .selection();
+    let b = match selected {
+        Some(b) => (*b).clone(),
+        None => return,
+    };
+    if let Some(position) = Controller::move_bookmark_action(app, &b.url, offset) {
+        let mut view = app
+            .find_name::<SelectView<Bookmark>>("bookmarks")
+            .expect("bookmarks view missing");
+        if let Some(id) = view.selected_id() {
+            view.remove_item(id);
+        }
+        view.insert_item(position, bookmark_label(&b), b);
+        view.set_selection(position);
+    }
+}
+
+/// Assigns the bookmark selected in the bookmark manager to a quick
+/// access slot
+fn bookmark_slot(app: &mut Cursive) {
+    let selected = app
+        .find_name::<SelectView<Bookmark>>("bookmarks")
+        .expect("bookmarks view missing")
+        .selection();
+    let b = match selected {
+        Some(b) => (*b).clone(),
+        None => return,
+    };
+    let mut view: SelectView<Option<u8>> = SelectView::new();
+    view.add_item("No slot", None);
+    for slot in 1..=9 {
+        view.add_item(format!("Slot {} (Alt-{})", slot, slot), Some(slot));
+    }
+    view.set_selection(b.slot.map_or(0, usize::from));
+    let title = format!("Slot of {}", b.title);
+    view.set_on_submit(move |app, slot: &Option<u8>| {
+        app.pop_layer();
+        let controller = app.user_data::<Controller>().expect("controller missing");
+        controller.bookmarks.lock().unwrap().set_slot(&b.url, *slot);
+        let bookmarks = controller.bookmarks.lock().unwrap().get_bookmarks();
+        // other bookmarks may have lost their slot
+        if let Some(mut view) = app.find_name::<SelectView<Bookmark>>("bookmarks") {
+            let selected = view.selected_id();
+            view.clear();
+            for b in bookmarks {
+                view.add_item(bookmark_label(&b), b);
+            }
+            if let Some(selected) = selected {
+                view.set_selection(selected);
+            }
+        }
+    });
+    app.add_layer(
+        Dialog::new()
+            .title(title)
+            .content(view)
+            .dismiss_button("Cancel"),
+    );
+}
+
+/// Returns the slot, title and URL of a bookmark in columns
+fn bookmark_label(b: &Bookmark) -> String {
+    let mut title = format!("{:<20}", b.title.as_str());
+    title.truncate(20);
+    let mut url = format!("{:<50}", b.url.as_str());
+    url.truncate(50);
+    let slot = b.slot.map_or(" ".to_string(), |slot| slot.to_string());
+    format!("{} {} | {}", slot, title, url)
+}
+
+/// Number of bookmarks listed by the bookmark finder
+const MAX_FOUND_BOOKMARKS: usize = 12;
+
+/// Finds a bookmark by typing a few characters of its title, URL or
+/// tags, and opens it
+pub(crate) fn find_bookmark(app: &mut Cursive) {
+    let edit = EditView::new()
+        .on_edit(|app, query, _| update_found_bookmarks(app, query))
+        .on_submit(|app, _| {
+            let selected = app
+                .find_name::<SelectView<Url>>("found_bookmarks")
+                .and_then(|view| view.selection());
+            if let Some(url) = selected {
+                open_found_bookmark(app, &url);
+            }
+        })
+        .with_name("bookmark_query");
+    // the arrow keys move through the matches while typing
+    let edit = OnEventView::new(edit)
+        .on_event(Key::Down, |app| {
+            if let Some(mut view) = app.find_name::<SelectView<Url>>("found_bookmarks") {
+                let callback = view.select_down(1);
+                drop(view);
+                callback(app);
+            }
+        })
+        .on_event(Key::Up, |app| {
+            if let Some(mut view) = app.find_name::<SelectView<Url>>("found_bookmarks") {
+                let callback = view.select_up(1);
+                drop(view);
+                callback(app);
+            }
+        });
+    let found: SelectView<Url> = SelectView::new().on_submit(open_found_bookmark);
+    app.add_layer(
+        Dialog::new()
+            .title("Find bookmark")
+            .content(
+                LinearLayout::vertical()
+                    .child(edit)
+                    .child(DummyView)
+                    .child(found.with_name("found_bookmarks").min_height(MAX_FOUND_BOOKMARKS))
+                    .fixed_width(70),
+            )
+            .dismiss_button("Cancel"),
+    );
+    update_found_bookmarks(app, "");
+}
+
+fn update_found_bookmarks(app: &mut Cursive, query: &str) {
+    let found = app
+        .user_data::<Controller>()
+        .expect("controller missing")
+        .bookmarks
+        .lock()
+        .unwrap()
+        .fuzzy_find(query)
+        .into_iter()
+        .take(MAX_FOUND_BOOKMARKS)
+        .map(|b| {
+            let title = if b.title.is_empty() { b.url.as_str() } else { b.title.as_str() };
+            let mut label = StyledString::plain(format!("{:<30.30} ", title));
+            label.append_styled(b.url.as_str(), ColorStyle::secondary());
+            (label, b.url.clone())
+        })
+        .collect::<Vec<_>>();
+    if let Some(mut view) = app.find_name::<SelectView<Url>>("found_bookmarks") {
+        view.clear();
+        view.add_all(found);
+    }
+}
+
+fn open_found_bookmark(app: &mut Cursive, url: &Url) {
+    app.pop_layer();
+    app.user_data::<Controller>()
+        .expect("controller missing")
+        .open_url(url.clone(), true, 0);
+}
+
+pub(super) fn bookmark_trash(app: &mut Cursive) {
+    let trash = app
+        .user_data::<Controller>()
+        .expect("controller missing")
+        .bookmarks
+        .lock()
+        .unwrap()
+        .trash
+        .clone();
+    let mut view: SelectView<Bookmark> = SelectView::new();
+    // most recently deleted first
+    for b in trash.into_iter().rev() {
+        let mut title = format!("{:<20}", b.title.clone().as_str());
+        title.truncate(20);
+        let mut url = format!("{:<50}", b.url.clone().as_str());
+        url.truncate(50);
+        view.add_item(format!("{} | {}", title, url), b);
+    }
+    app.add_layer(
+        Dialog::new()
+            .title("Bookmark trash")
+            .content(LinearLayout::vertical().child(view.with_name("trash").scrollable()))
+            .button("Restore", |app| {
+                let selected = app
+                    .call_on_name("trash", |view: &mut SelectView<Bookmark>| {
+                        let selected = view.selection();
+                        if let Some(id) = view.selected_id() {
+                            view.remove_item(id);
+                        }
+                        selected
+                    })
+                    .unwrap();
+                if let Some(b) = selected {
+                    Controller::restore_bookmark_action(app, &b.url);
+                }
+            })
+            .button("Empty trash", |app| {
+                app.pop_layer();
+                app.user_data::<Controller>()
+                    .expect("controller missing")
+                    .bookmarks
+                    .lock()
+                    .unwrap()
+                    .empty_trash();
+            })
+            .button("Close", |app| {
+                app.pop_layer();
+            }),
+    );
+}
+
+/// Returns a bar for a download. The size of downloads is not known in
+/// advance, so active downloads get a marker that moves as data arrives.
+fn download_bar(download: &Download) -> String {
+    const WIDTH: usize = 10;
+    match download.state {
+        DownloadState::Active => {
+            let position = download.received / 16384 % WIDTH;
+            let mut bar = vec!['-'; WIDTH];
+            bar[position] = '#';
+            format!("[{}]", bar.into_iter().collect::<String>())
+        }
+        DownloadState::Finished => format!("[{}]", "#".repeat(WIDTH)),
+        _ => format!("[{}]", " ".repeat(WIDTH)),
+    }
+}
+
+/// Fills the downloads panel, if it is open
+pub(crate) fn refresh_downloads(app: &mut Cursive) {
+    let downloads = app
+        .user_data::<Controller>()
+        .expect("controller missing")
+        .downloads
+        .lock()
+        .unwrap()
+        .clone();
+    let mut view = match app.find_name::<SelectView<String>>("downloads") {
+        Some(view) => view,
+        None => return,
+    };
+    let selected = view.selected_id();
+    view.clear();
+    // most recent first
+    for d in downloads.iter().rev() {
+        let name = Path::new(&d.filename)
+            .file_name()
+            .map_or(d.filename.clone(), |name| name.to_string_lossy().to_string());
+        let name: String = name.chars().take(30).collect();
+        let state = match &d.state {
+            DownloadState::Active => "downloading".to_string(),
+            DownloadState::Finished => "done".to_string(),
+            DownloadState::Failed(err) => format!("failed: {}", err),
+            DownloadState::Cancelled => "cancelled".to_string(),
+        };
+        view.add_item(
+            format!(
+                "{:<30} {} {:>8} {:>10}/s {}",
+                name,
+                download_bar(d),
+                human_readable_size(d.received),
+                human_readable_size(d.speed()),
+                state
+            ),
+            d.filename.clone(),
+        );
+    }
+    if let Some(id) = selected {
+        view.set_selection(id);
+    }
+}
+
+/// Returns the local filename of the download selected in the panel
+fn selected_download(app: &mut Cursive) -> Option<String> {
+    app.find_name::<SelectView<String>>("downloads")
+        .and_then(|view| view.selection())
+        .map(|filename| filename.to_string())
+}
+
+/// Shows the active and completed downloads
+pub(crate) fn downloads(app: &mut Cursive) {
+    let view: SelectView<String> = SelectView::new();
+    app.add_layer(
+        Dialog::new()
+            .title("Downloads")
+            .content(
+                view.with_name("downloads")
+                    .scrollable()
+                    .min_width(80)
+                    .min_height(8),
+            )
+            .button("Cancel download", |app| {
+                if let Some(filename) = selected_download(app) {
+                    app.user_data::<Controller>()
+                        .expect("controller missing")
+                        .cancel_download(&filename);
+                }
+            })
+            .button("Open", |app| {
+                if let Some(filename) = selected_download(app) {
+                    let controller = app.user_data::<Controller>().expect("controller missing");
+                    let finished = controller
+                        .downloads
+                        .lock()
+                        .unwrap()
+                        .iter()
+                        .any(|d| d.filename == filename && d.state == DownloadState::Finished);
+                    if !finished {
+                        controller.set_message("Download is not finished");
+                        return;
+                    }
+                    let command = SETTINGS.read().unwrap().config.html_command.clone();
+                    if command.is_empty() {
+                        controller.set_message("No command for opening files defined.");
+                    } else if let Err(err) = external::spawn(&command, &filename) {
+                        controller.set_message(&format!("Command failed: {}: {}", err, command));
+                    }
+                }
+            })
+            .button("Clear finished", |app| {
+                app.user_data::<Controller>()
+                    .expect("controller missing")
+                    .downloads
+                    .lock()
+                    .unwrap()
+                    .retain(Download::is_active);
+                refresh_downloads(app);
+            })
+            .button("Close", |app| {
+                app.pop_layer();
+            }),
+    );
+    refresh_downloads(app);
+}
+
+/// Shows the recent status messages, most recent first
+pub(crate) fn message_history(app: &mut Cursive) {
+    let messages = app
+        .user_data::<Controller>()
+        .expect("controller missing")
+        .message_history
+        .lock()
+        .unwrap()
+        .clone();
+    let format = format_description::parse_borrowed::<1>("[hour]:[minute]:[second]")
+        .expect("Could not parse timestamp format");
+    let mut view: SelectView<String> = SelectView::new();
+    for (timestamp, message) in messages.into_iter().rev() {
+        let time = timestamp.format(&format).unwrap_or_default();
+        view.add_item(format!("{} {}", time, message), message);
+    }
+    if view.is_empty() {
+        view.add_item("No messages yet", String::new());
+    }
+    app.add_layer(
+        Dialog::new()
+            .title("Messages")
+            .content(view.scrollable().min_width(60).max_height(20))
+            .button("Close", |app| {
+                app.pop_layer();
+            }),
+    );
+}
+
+/// Fills the history browser with the visited URLs matching a filter
+fn filter_history(app: &mut Cursive, filter: &str) {
+    let entries = app
+        .user_data::<Controller>()
+        .expect("controller missing")
+        .history
+        .lock()
+        .unwrap()
+        .entries(filter)
+        .unwrap_or_else(|err| {
+            warn!("Could not get history: {}", err);
+            Vec::new()
+        });
+    let format = format_description::parse_borrowed::<1>(
+        "[year]-[month]-[day] [hour]:[minute]"
+    ).expect("Could not parse timestamp format");
+    let mut view = app
+        .find_name::<SelectView<HistoryEntry>>("entries")
+        .expect("history view missing");
+    view.clear();
+    for e in entries {
+        let title: String = sanitize(&e.title).chars().take(30).collect();
+        view.add_item(
+            format!(
+                "{:<16} | {:<30} | {}",
+                e.timestamp.format(&format).expect("Invalid timestamp from database"),
+                title,
+                e.url
+            ),
+            e,
+        );
+    }
+}
+
+/// Opens the URL selected in the history browser
+fn open_history_entry(app: &mut Cursive) {
+    let selected = app
+        .find_name::<SelectView<HistoryEntry>>("entries")
+        .unwrap()
+        .selection();
+    if let Some(entry) = selected {
+        app.pop_layer();
+        app.user_data::<Controller>()
+            .expect("controller missing")
+            .open_url(entry.url.clone(), true, 0);
+    }
+}
+
+pub(super) fn edit_history(app: &mut Cursive) {
+    let view: SelectView<HistoryEntry> =
+        SelectView::new().on_submit(|app, _| open_history_entry(app));
+    app.add_layer(
+        Dialog::new()
+            .title("Show history")
+            .content(
+                LinearLayout::vertical()
+                    .child(
+                        EditView::new()
+                            .on_edit(|app, filter, _| filter_history(app, filter))
+                            .on_submit(|app, _| {
+                                app.focus_name("entries").ok();
+                            })
+                            .with_name("history_filter")
+                            .full_width(),
+                    )
+                    .child(DummyView)
+                    .child(TextView::new("Last visited     | Title                          | URL"))
+                    .child(view.with_name("entries").scrollable().min_height(15)),
+            )
+            .button("Clear all history", |app| {
+                app.add_layer(
+                    Dialog::around(TextView::new("Do you want to delete the history?"))
+                        .button("Cancel", |app| {
+                            app.pop_layer();
+                        })
+                        .button("Yes", |app| {
+                            app.pop_layer();
+                            app.call_on_name("entries", |view: &mut SelectView<HistoryEntry>| {
+                                view.clear()
+                            });
+                            app.user_data::<Controller>()
+                                .expect("controller missing")
+                                .clear_history();
+                        }),
+                );
+            })
+            .button("Open URL", open_history_entry)
+            .button("Close", |app| {
+                // close dialog
+                app.pop_layer();
+            }),
+    );
+    filter_history(app, "");
+}
+
+pub(super) fn search_pages(app: &mut Cursive) {
+    app.add_layer(
+        Dialog::new()
+            .title("Search visited pages")
+            .content(
+                LinearLayout::vertical()
+                    .child(
+                        EditView::new()
+                            .on_submit(|app, query| {
+                                let matches = app
+                                    .user_data::<Controller>()
+                                    .expect("controller missing")
+                                    .history
+                                    .lock()
+                                    .unwrap()
+                                    .search_pages(query)
+                                    .unwrap_or_else(|err| {
+                                        warn!("Could not search pages: {}", err);
+                                        Vec::new()
+                                    });
+                                let mut view = app
+                                    .find_name::<SelectView<PageMatch>>("page_matches")
+                                    .expect("page matches view missing");
+                                view.clear();
+                                for m in matches {
+                                    let mut title = m.title.clone();
+                                    title.truncate(30);
+                                    view.add_item(format!("{:<30} | {}", title, m.snippet), m);
+                                }
+                                if !view.is_empty() {
+                                    app.focus_name("page_matches").ok();
+                                }
+                            })
+                            .with_name("page_query")
+                            .fixed_width(70),
+                    )
+                    .child(DummyView)
+                    .child(
+                        SelectView::<PageMatch>::new()
+                            .on_submit(|app, m: &PageMatch| {
+                                app.pop_layer();
+                                app.user_data::<Controller>()
+                                    .expect("controller missing")
+                                    .open_url(m.url.clone(), true, 0);
+                            })
+                            .with_name("page_matches")
+                            .scrollable()
+                            .fixed_height(15),
+                    ),
+            )
+            .button("Close", |app| {
+                app.pop_layer();
+            }),
+    );
+}
+
+/// Offers to view or save a file decoded from a uuencoded gopher item
+pub(crate) fn uudecoded_file(app: &mut Cursive, file: UudecodedFile) {
+    let view_file = file.clone();
+    app.add_layer(
+        Dialog::text(format!(
+            "Decoded {} ({} bytes)",
+            file.filename,
+            file.data.len()
+        ))
+        .title("Uuencoded file")
+        .button("View as text", move |app| {
+            app.pop_layer();
+            let text = crate::text::decode(&view_file.data);
+            app.user_data::<Controller>()
+                .expect("controller missing")
+                .show_text(text);
+        })
+        .button("Save", move |app| {
+            app.pop_layer();
+            let download_path = SETTINGS.read().unwrap().config.download_path.clone();
+            let path = std::path::Path::new(&download_path).join(&file.filename);
+            let result = std::fs::OpenOptions::new()
+                .write(true)
+                // make sure to not clobber downloaded files
+                .create_new(true)
+                .open(&path)
+                .and_then(|mut f| std::io::Write::write_all(&mut f, &file.data));
+            let message = match result {
+                Ok(()) => format!("File saved: {}", path.display()),
+                Err(err) => format!("Unable to save file '{}': {}", path.display(), err),
+            };
+            app.user_data::<Controller>()
+                .expect("controller missing")
+                .set_message(&message);
+        })
+        .dismiss_button("Cancel"),
+    );
+}
+
+/// Exports the bookmarks as gemtext or gophermap, e.g. for publishing
+/// them as links page
+pub(super) fn export_bookmarks(app: &mut Cursive) {
+    let tags = app
+        .user_data::<Controller>()
+        .expect("controller missing")
+        .bookmarks
+        .lock()
+        .unwrap()
+        .tags();
+    let mut tag_view: SelectView<Option<String>> = SelectView::new().popup();
+    tag_view.add_item("All bookmarks", None);
+    for tag in tags {
+        tag_view.add_item(format!("Tagged {}", tag), Some(tag));
+    }
+    let download_path = SETTINGS.read().unwrap().config.download_path.clone();
+    let path = std::path::Path::new(&download_path).join("bookmarks.gmi");
+
+    // the format is taken from the file extension, which follows the
+    // selected format
+    let mut format: RadioGroup<&str> = RadioGroup::new();
+    format.set_on_change(|app, extension| {
+        app.call_on_name("export_path", |v: &mut EditView| {
+            let path = v.get_content();
+            let path = std::path::Path::new(path.as_str()).with_extension(extension);
+            v.set_content(path.display().to_string());
+        });
+    });
+    app.add_layer(
+        Dialog::new()
+            .title("Export bookmarks")
+            .content(
+                LinearLayout::vertical()
+                    .child(tag_view.with_name("export_tag"))
+                    .child(DummyView)
+                    .child(format.button("gmi", "Gemtext"))
+                    .child(format.button("gph", "Gophermap"))
+                    .child(format.button("html", "HTML (for web browsers)"))
+                    .child(DummyView)
+                    .child(TextView::new("File:"))
+                    .child(
+                        EditView::new()
+                            .content(path.display().to_string())
+                            .with_name("export_path")
+                            .fixed_width(50),
+                    ),
+            )
+            .button("Export", |app| {
+                let tag = app
+                    .find_name::<SelectView<Option<String>>>("export_tag")
+                    .expect("tag view missing")
+                    .selection()
+                    .and_then(|tag| (*tag).clone());
+                let path = app
+                    .find_name::<EditView>("export_path")
+                    .expect("path field missing")
+                    .get_content();
+                let bookmarks = app
+                    .user_data::<Controller>()
+                    .expect("controller missing")
+                    .bookmarks
+                    .lock()
+                    .unwrap()
+                    .clone();
+                let content = if path.ends_with(".gmi") {
+                    bookmarks.to_gemtext(tag.as_deref())
+                } else if path.ends_with(".html") || path.ends_with(".htm") {
+                    bookmarks.to_html(tag.as_deref())
+                } else {
+                    bookmarks.to_gophermap(tag.as_deref())
+                };
+                app.pop_layer();
+                let message = match std::fs::write(path.as_str(), content) {
+                    Ok(()) => format!("Bookmarks exported to {}", path),
+                    Err(err) => format!("Could not export bookmarks: {}", err),
+                };
+                app.user_data::<Controller>()
+                    .expect("controller missing")
+                    .set_message(&message);
+            })
+            .dismiss_button("Cancel"),
+    );
+}
+
+/// Returns the gophermap entries marked for bulk actions
+fn marked_entries(app: &mut Cursive) -> Vec<GopherMapEntry> {
+    let marked = app
+        .user_data::<Controller>()
+        .expect("controller missing")
+        .marked
+        .clone();
+    let view = app
+        .find_name::<SelectView<GopherMapEntry>>("content")
+        .expect("View content missing");
+    marked
+        .into_iter()
+        .filter_map(|i| view.get_item(i).map(|(_, entry)| entry.clone()))
+        .collect()
+}
+
+/// Offers actions on all marked gophermap entries
+pub(super) fn bulk_actions(app: &mut Cursive) {
+    let count = marked_entries(app).len();
+    if count == 0 {
+        app.add_layer(Dialog::info("No entries marked. Mark entries with m."));
+        return;
+    }
+    app.add_layer(
+        Dialog::text(format!("{} entries marked", count))
+            .title("Bulk actions")
+            .button("Download all", |app| {
                 app.pop_layer();
+                let entries = marked_entries(app);
+                super::setup::clear_marks(app);
+                app.user_data::<Controller>()
+                    .expect("controller missing")
+                    .download_entries(entries);
             })
-            .button("Edit", |app| {
-                let selected = app
-                    .call_on_name("bookmarks", |view: &mut SelectView<Bookmark>| {
-                        view.selection()
-                    })
-                    .unwrap();
-                match selected {
-                    None => (),
-                    Some(b) => {
-                        app.pop_layer();
-                        crate::ui::dialogs::edit_bookmark(
-                            app,
-                            b.url.clone(),
-                            &b.title,
-                            &b.tags.join(","),
-                        );
-                    }
+            .button("Bookmark all", |app| {
+                app.pop_layer();
+                let entries = marked_entries(app);
+                super::setup::clear_marks(app);
+                let controller = app.user_data::<Controller>().expect("controller missing");
+                for entry in entries {
+                    controller.add_bookmark_action(
+                        entry.url,
+                        entry.name,
+                        String::new(),
+                        Vec::new(),
+                    );
                 }
             })
-            .button("Close", |app| {
+            .button("Copy URLs", |app| {
                 app.pop_layer();
-            }),
+                let urls = marked_entries(app)
+                    .into_iter()
+                    .map(|entry| entry.url.to_string())
+                    .collect::<Vec<_>>();
+                super::setup::clear_marks(app);
+                super::terminal::copy_to_clipboard(&urls.join("\n"));
+                app.user_data::<Controller>()
+                    .expect("controller missing")
+                    .set_message(&format!("Copied {} URLs to clipboard", urls.len()));
+            })
+            .button("Unmark all", |app| {
+                app.pop_layer();
+                super::setup::clear_marks(app);
+            })
+            .dismiss_button("Cancel"),
     );
 }
 
-pub(super) fn edit_history(app: &mut Cursive) {
-    let entries = app
-        .user_data::<Controller>()
-        .expect("controller missing")
-        .history
-        .lock()
-        .unwrap()
-        .get_latest_history(500)
-        .expect("could not get latest history");
-    let mut view: SelectView<HistoryEntry> = SelectView::new();
+/// Shows the link under the cursor or the current URL as QR code, e.g.
+/// for opening it on a phone
+pub(super) fn qr_code(app: &mut Cursive) {
+    let url = match super::setup::selected_url(app) {
+        Some(url) => url,
+        None => app
+            .user_data::<Controller>()
+            .expect("controller missing")
+            .current_url
+            .lock()
+            .unwrap()
+            .clone(),
+    };
+    let code = match QrCode::new(url.as_str()) {
+        Ok(code) => code,
+        Err(err) => {
+            app.add_layer(Dialog::info(format!("Could not create QR code: {}", err)));
+            return;
+        }
+    };
+    // Two rows of modules per line. Scanners need dark modules on a
+    // light background, whatever the theme.
+    let image = code.render::<unicode::Dense1x2>().build();
+    let style = ColorStyle::new(
+        Color::Dark(BaseColor::Black),
+        Color::Light(BaseColor::White),
+    );
+    app.add_layer(
+        Dialog::new()
+            .title(human_readable_url(&url))
+            .content(TextView::new(StyledString::styled(image, style)).scrollable())
+            .dismiss_button("Close"),
+    );
+}
 
-    let format = format_description::parse(
-        "[year]-[month]-[day] [hour]:[minute]:[second]"
-    ).expect("Could not parse timestamp format");
-    for e in entries {
-        let mut url = e.url.to_string();
-        url.truncate(50);
-        view.add_item(
-            format!(
-                "{:>4}|{:<20}|{}",
-                e.visited_count,
-                e.timestamp.format(&format).expect("Invalid timestamp from database"),
-                url
-            ),
-            e,
-        );
+/// Lints the gophermap of the current page
+pub(super) fn lint_gophermap(app: &mut Cursive) {
+    let controller = app.user_data::<Controller>().expect("controller missing");
+    let url = controller.current_url.lock().unwrap().clone();
+    if url.scheme() != "gopher" || !ItemType::from_url(&url).is_dir() {
+        app.add_layer(Dialog::info("The current page is not a gophermap."));
+        return;
     }
+    let issues = lint(&controller.content.lock().unwrap());
+    show_lint_issues(app, url.as_str(), issues);
+}
+
+/// Asks for a local gophermap file and lints it
+pub(super) fn lint_gophermap_file(app: &mut Cursive) {
     app.add_layer(
         Dialog::new()
-            .title("Show history")
+            .title("Lint gophermap file")
             .content(
-                LinearLayout::vertical()
-                    .child(TextView::new("#Vis|Last Visited        |URL"))
-                    .child(LinearLayout::vertical().child(view.with_name("entries").scrollable())),
+                EditView::new()
+                    .on_submit(|app, path| {
+                        app.pop_layer();
+                        lint_file(app, path);
+                    })
+                    .with_name("lint_path")
+                    .fixed_width(50),
             )
-            .button("Clear all history", |app| {
-                app.add_layer(
-                    Dialog::around(TextView::new("Do you want to delete the history?"))
-                        .button("Cancel", |app| {
-                            app.pop_layer();
-                        })
-                        .button("Yes", |app| {
-                            app.pop_layer();
-                            app.call_on_name("entries", |view: &mut SelectView<HistoryEntry>| {
-                                view.clear()
-                            });
-                            app.user_data::<Controller>()
-                                .expect("controller missing")
-                                .clear_history();
-                        }),
-                );
-            })
-            .button("Open URL", |app| {
-                let selected = app
-                    .find_name::<SelectView<HistoryEntry>>("entries")
-                    .unwrap()
-                    .selection();
+            .button("Cancel", |app| {
                 app.pop_layer();
-                match selected {
-                    None => (),
-                    Some(b) => {
-                        app.user_data::<Controller>()
-                            .expect("controller missing")
-                            .open_url(b.url.clone(), true, 0);
-                    }
-                }
             })
-            .button("Close", |app| {
-                // close dialog
+            .button("Ok", |app| {
+                let path = app
+                    .find_name::<EditView>("lint_path")
+                    .expect("path field missing")
+                    .get_content();
                 app.pop_layer();
+                lint_file(app, &path);
             }),
     );
 }
 
+fn lint_file(app: &mut Cursive, path: &str) {
+    match std::fs::read(path) {
+        Ok(content) => {
+            let issues = lint(&String::from_utf8_lossy(&content));
+            show_lint_issues(app, path, issues);
+        }
+        Err(err) => app.add_layer(Dialog::info(format!("Could not read {}: {}", path, err))),
+    }
+}
+
+fn show_lint_issues(app: &mut Cursive, name: &str, issues: Vec<LintIssue>) {
+    let text = if issues.is_empty() {
+        "No problems found.".to_string()
+    } else {
+        issues
+            .iter()
+            .map(|issue| format!("{:>5}: {}", issue.line, issue.message))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    app.add_layer(
+        Dialog::around(TextView::new(text).scrollable().max_height(20))
+            .title(format!("Lint: {}", name))
+            .button("Close", |app| {
+                app.pop_layer();
+            })
+            .max_width(80),
+    );
+}
+
 pub(crate) fn gemini_query(app: &mut Cursive, url: Url, query: String, secret: bool) {
     app.add_layer(
         Dialog::new()
-            .title(query)
+            .title(sanitize(&query))
             .content(
                 if secret {
                     EditView::new().secret()
@@ -295,18 +1376,118 @@ pub(super) fn open_current_url(app: &mut Cursive) {
     open_given_url(app, Some(current_url));
 }
 
+/// Lists the directories above the current page to jump to one of them
+pub(crate) fn go_up(app: &mut Cursive) {
+    let controller = app.user_data::<Controller>().expect("controller missing");
+    let current_url = controller.current_url.lock().unwrap().clone();
+    let ancestors = ancestors(&current_url);
+    if ancestors.is_empty() {
+        controller.set_message("No directory above this page");
+        return;
+    }
+    let mut view: SelectView<Url> = SelectView::new().on_submit(|app, url: &Url| {
+        app.pop_layer();
+        app.user_data::<Controller>()
+            .expect("controller missing")
+            .open_url(url.clone(), true, 0);
+    });
+    for url in ancestors {
+        view.add_item(breadcrumb(&url), url);
+    }
+    app.add_layer(
+        Dialog::new()
+            .title("Go up to")
+            .content(view.scrollable())
+            .button("Cancel", |app| {
+                app.pop_layer();
+            }),
+    );
+}
+
+/// Number of URLs suggested while typing a URL
+const MAX_URL_SUGGESTIONS: usize = 8;
+
+/// Returns the URLs of bookmarks and visited pages whose URL or title
+/// contains the typed text, URLs starting with it first
+fn url_suggestions(app: &mut Cursive, typed: &str) -> Vec<String> {
+    if typed.chars().count() < 2 {
+        return Vec::new();
+    }
+    let controller = app.user_data::<Controller>().expect("controller missing");
+    let needle = typed.to_lowercase();
+    let mut urls: Vec<String> = controller
+        .bookmarks
+        .lock()
+        .unwrap()
+        .get_bookmarks()
+        .into_iter()
+        .filter(|b| {
+            b.url.as_str().to_lowercase().contains(&needle)
+                || b.title.to_lowercase().contains(&needle)
+        })
+        .map(|b| b.url.to_string())
+        .collect();
+    let history = controller
+        .history
+        .lock()
+        .unwrap()
+        .entries(typed)
+        .unwrap_or_default();
+    urls.extend(history.into_iter().map(|e| e.url.to_string()));
+    let mut seen = std::collections::HashSet::new();
+    urls.retain(|url| url != typed && seen.insert(url.clone()));
+    // the scheme does not need to be typed
+    let starts_with_typed = |url: &String| {
+        let url = url.to_lowercase();
+        url.starts_with(&needle)
+            || url.split_once("://").is_some_and(|(_, rest)| rest.starts_with(&needle))
+    };
+    urls.sort_by_key(|url| !starts_with_typed(url));
+    urls.truncate(MAX_URL_SUGGESTIONS);
+    urls
+}
+
+fn update_url_suggestions(app: &mut Cursive, typed: &str) {
+    let urls = url_suggestions(app, typed);
+    if let Some(mut view) = app.find_name::<SelectView<String>>("url_suggestions") {
+        view.clear();
+        view.add_all_str(urls);
+    }
+}
+
+/// Completes the URL being typed with the selected suggestion
+fn accept_url_suggestion(app: &mut Cursive) {
+    let selected = app
+        .find_name::<SelectView<String>>("url_suggestions")
+        .and_then(|view| view.selection());
+    let callback = match (selected, app.find_name::<EditView>("goto_url")) {
+        (Some(url), Some(mut edit)) => edit.set_content(url.as_str()),
+        _ => return,
+    };
+    // updates the suggestions
+    callback(app);
+}
+
 fn open_given_url(app: &mut Cursive, url: Option<Url>) {
+    let edit = EditView::new()
+        .on_submit(|app, goto_url| {
+            app.pop_layer();
+            Controller::open_url_action(app, goto_url);
+        })
+        .on_edit(|app, typed, _| update_url_suggestions(app, typed))
+        .content(match url { Some(url) => url.to_string(), None => "".to_string() })
+        .with_name("goto_url");
+    let suggestions: SelectView<String> = SelectView::new().on_submit(|app, url: &str| {
+        app.pop_layer();
+        Controller::open_url_action(app, url);
+    });
     app.add_layer(
         Dialog::new()
             .title("Enter gopher or gemini URL:")
             .content(
-                EditView::new()
-                    .on_submit(|app, goto_url| {
-                        app.pop_layer();
-                        Controller::open_url_action(app, goto_url);
-                    })
-                    .content(match url { Some(url) => url.to_string(), None => "".to_string() })
-                    .with_name("goto_url")
+                LinearLayout::vertical()
+                    .child(OnEventView::new(edit).on_event(Key::Tab, accept_url_suggestion))
+                    .child(suggestions.with_name("url_suggestions"))
                     .fixed_width(50),
             )
             .button("Cancel", |app| {
@@ -323,6 +1504,113 @@ fn open_given_url(app: &mut Cursive, url: Option<Url>) {
     );
 }
 
+/// Asks for a local file, e.g. a download, and shows it as text
+pub(super) fn view_file(app: &mut Cursive) {
+    let download_path = SETTINGS.read().unwrap().config.download_path.clone();
+    app.add_layer(
+        Dialog::new()
+            .title("View file as text")
+            .content(
+                EditView::new()
+                    .content(format!("{}/", download_path.trim_end_matches('/')))
+                    .on_submit(|app, path| {
+                        app.pop_layer();
+                        open_file(app, path);
+                    })
+                    .with_name("view_path")
+                    .fixed_width(50),
+            )
+            .button("Cancel", |app| {
+                app.pop_layer();
+            })
+            .button("Ok", |app| {
+                let path = app
+                    .find_name::<EditView>("view_path")
+                    .expect("path field missing")
+                    .get_content();
+                app.pop_layer();
+                open_file(app, &path);
+            }),
+    );
+}
+
+fn open_file(app: &mut Cursive, path: &str) {
+    let url = std::fs::canonicalize(path)
+        .ok()
+        .and_then(|path| Url::from_file_path(path).ok());
+    match url {
+        Some(url) => app
+            .user_data::<Controller>()
+            .expect("controller missing")
+            .open_url(url, true, 0),
+        None => app.add_layer(Dialog::info(format!("File {} not found", path))),
+    }
+}
+
+/// Asks whether to restore the pages that were open when ncgopher last
+/// quit
+pub(crate) fn restore_last_session(app: &mut Cursive, session: Session) {
+    let current = session
+        .page
+        .last()
+        .map(|page| human_readable_url(&page.url))
+        .unwrap_or_default();
+    app.add_layer(
+        Dialog::around(TextView::new(format!(
+            "Restore the last session?\n\n{}\nand {} pages of its history",
+            current,
+            session.page.len().saturating_sub(1)
+        )))
+        .title("Restore session")
+        .button("Restore", move |app| {
+            app.pop_layer();
+            app.user_data::<Controller>()
+                .expect("controller missing")
+                .restore_session(session.clone());
+        })
+        .dismiss_button("Start fresh"),
+    );
+}
+
+/// Asks for the file to export the session to or import it from
+fn session_file(app: &mut Cursive, title: &str, action: fn(&mut Cursive, &str)) {
+    let download_path = SETTINGS.read().unwrap().config.download_path.clone();
+    let path = std::path::Path::new(&download_path).join("session.toml");
+    app.add_layer(
+        Dialog::new()
+            .title(title)
+            .content(
+                EditView::new()
+                    .content(path.display().to_string())
+                    .on_submit(move |app, path| {
+                        app.pop_layer();
+                        action(app, path);
+                    })
+                    .with_name("session_path")
+                    .fixed_width(50),
+            )
+            .button("Cancel", |app| {
+                app.pop_layer();
+            })
+            .button("Ok", move |app| {
+                let path = app
+                    .find_name::<EditView>("session_path")
+                    .expect("path field missing")
+                    .get_content();
+                app.pop_layer();
+                action(app, &path);
+            }),
+    );
+}
+
+pub(super) fn export_session(app: &mut Cursive) {
+    session_file(app, "Export session", Controller::export_session_action);
+}
+
+pub(super) fn import_session(app: &mut Cursive) {
+    session_file(app, "Import session", Controller::import_session_action);
+}
+
 pub(super) fn save_as(app: &mut Cursive) {
     let current_url = app
         .user_data::<Controller>()
@@ -354,6 +1642,63 @@ pub(super) fn save_as(app: &mut Cursive) {
     );
 }
 
+pub(super) fn reload_with_encoding(app: &mut Cursive) {
+    let current_url = app
+        .user_data::<Controller>()
+        .expect("controller missing")
+        .current_url
+        .lock()
+        .unwrap()
+        .clone();
+    let pinned = current_url
+        .host_str()
+        .and_then(crate::text::encoding_for_host)
+        .unwrap_or_default();
+
+    let mut view: SelectView<String> = SelectView::new();
+    for encoding in crate::text::COMMON_ENCODINGS {
+        view.add_item_str(*encoding);
+    }
+    if let Some(i) = crate::text::COMMON_ENCODINGS
+        .iter()
+        .position(|e| *e == pinned)
+    {
+        view.set_selection(i);
+    }
+    app.add_layer(
+        Dialog::new()
+            .title("Reload with encoding")
+            .content(
+                LinearLayout::vertical()
+                    .child(view.with_name("encoding").scrollable().max_height(10))
+                    .child(DummyView)
+                    .child(
+                        LinearLayout::horizontal()
+                            .child(Checkbox::new().with_name("remember_encoding"))
+                            .child(DummyView)
+                            .child(TextView::new("Always use for this host")),
+                    ),
+            )
+            .button("Cancel", |app| {
+                app.pop_layer();
+            })
+            .button("Reload", |app| {
+                let encoding = app
+                    .find_name::<SelectView<String>>("encoding")
+                    .expect("encoding view missing")
+                    .selection();
+                let remember = app
+                    .find_name::<Checkbox>("remember_encoding")
+                    .expect("checkbox missing")
+                    .is_checked();
+                app.pop_layer();
+                if let Some(encoding) = encoding {
+                    Controller::reload_with_encoding_action(app, &encoding, remember);
+                }
+            }),
+    );
+}
+
 pub(super) fn settings(app: &mut Cursive) {
     let download_path = SETTINGS.read().unwrap().config.download_path.clone();
     let homepage_url = SETTINGS.read().unwrap().config.homepage.clone();
@@ -361,10 +1706,24 @@ pub(super) fn settings(app: &mut Cursive) {
     let html_command = SETTINGS.read().unwrap().config.html_command.clone();
     let image_command = SETTINGS.read().unwrap().config.image_command.clone();
     let telnet_command = SETTINGS.read().unwrap().config.telnet_command.clone();
-    let darkmode = theme == "darkmode";
+    let tts_command = SETTINGS.read().unwrap().config.tts_command.clone();
+    let media_command = SETTINGS.read().unwrap().config.media_command.clone();
+    let mut themes = SelectView::new().popup();
+    for name in SETTINGS.read().unwrap().theme_names() {
+        themes.add_item_str(name);
+    }
+    let selected = themes.iter().position(|(name, _)| name == theme);
+    if let Some(index) = selected {
+        themes.set_selection(index);
+    }
     let textwrap = SETTINGS.read().unwrap().config.textwrap.clone();
     let disable_history = SETTINGS.read().unwrap().config.disable_history;
     let disable_identities = SETTINGS.read().unwrap().config.disable_identities;
+    let autohide_menu = SETTINGS.read().unwrap().config.autohide_menu;
+    let open_images = SETTINGS.read().unwrap().config.open_images;
+    let show_type_labels = SETTINGS.read().unwrap().config.show_type_labels;
+    let number_links = SETTINGS.read().unwrap().config.number_links;
+    let line_numbers = SETTINGS.read().unwrap().config.line_numbers;
     app.add_layer(
         Dialog::new()
             .title("Settings")
@@ -379,13 +1738,21 @@ pub(super) fn settings(app: &mut Cursive) {
                     .child(EditView::new().content(html_command.as_str()).with_name("html_command").fixed_width(50))
                     .child(TextView::new("Images viewer:"))
                     .child(EditView::new().content(image_command.as_str()).with_name("image_command").fixed_width(50))
+                    .child(LinearLayout::horizontal()
+                           .child(Checkbox::new().with_checked(open_images).with_name("open_images"))
+                           .child(DummyView)
+                           .child(TextView::new("Show images right away instead of downloading"))
+                    )
                     .child(TextView::new("Telnet client:"))
                     .child(EditView::new().content(telnet_command.as_str()).with_name("telnet_command").fixed_width(50))
+                    .child(TextView::new("Media player (reads the stream from stdin):"))
+                    .child(EditView::new().content(media_command.as_str()).with_name("media_command").fixed_width(50))
+                    .child(TextView::new("Text to speech (called with the text):"))
+                    .child(EditView::new().content(tts_command.as_str()).with_name("tts_command").fixed_width(50))
                     .child(DummyView)
                     .child(LinearLayout::horizontal()
-                           .child(Checkbox::new().with_checked(darkmode).with_name("darkmode"))
-                           .child(DummyView)
-                           .child(TextView::new("Dark mode"))
+                           .child(TextView::new("Theme: "))
+                           .child(themes.with_name("theme"))
                     )
                     .child(LinearLayout::horizontal()
                            .child(Checkbox::new().with_checked(disable_history).with_name("disable_history"))
@@ -397,6 +1764,26 @@ pub(super) fn settings(app: &mut Cursive) {
                            .child(DummyView)
                            .child(TextView::new("Disable identities"))
                     )
+                    .child(LinearLayout::horizontal()
+                           .child(Checkbox::new().with_checked(autohide_menu).with_name("autohide_menu"))
+                           .child(DummyView)
+                           .child(TextView::new("Hide menubar until Esc is pressed"))
+                    )
+                    .child(LinearLayout::horizontal()
+                           .child(Checkbox::new().with_checked(show_type_labels).with_name("show_type_labels"))
+                           .child(DummyView)
+                           .child(TextView::new("Show item types in gophermaps"))
+                    )
+                    .child(LinearLayout::horizontal()
+                           .child(Checkbox::new().with_checked(number_links).with_name("number_links"))
+                           .child(DummyView)
+                           .child(TextView::new("Number links to jump to them by typing"))
+                    )
+                    .child(LinearLayout::horizontal()
+                           .child(Checkbox::new().with_checked(line_numbers).with_name("line_numbers"))
+                           .child(DummyView)
+                           .child(TextView::new("Show line numbers in text files"))
+                    )
                     .child(DummyView)
                     .child(LinearLayout::horizontal()
                            .child(TextView::new("Text wrap column:"))
@@ -407,12 +1794,23 @@ pub(super) fn settings(app: &mut Cursive) {
             .button("Apply",  |app| {
                 let homepage = app.find_name::<EditView>("homepage").unwrap().get_content();
                 let download = app.find_name::<EditView>("download_path").unwrap().get_content();
-                let darkmode = app.find_name::<Checkbox>("darkmode").unwrap().is_checked();
+                let theme = app
+                    .find_name::<SelectView<String>>("theme")
+                    .unwrap()
+                    .selection()
+                    .map(|theme| theme.to_string());
                 let disable_history = app.find_name::<Checkbox>("disable_history").unwrap().is_checked();
                 let disable_identities = app.find_name::<Checkbox>("disable_identities").unwrap().is_checked();
+                let autohide_menu = app.find_name::<Checkbox>("autohide_menu").unwrap().is_checked();
+                let open_images = app.find_name::<Checkbox>("open_images").unwrap().is_checked();
+                let show_type_labels = app.find_name::<Checkbox>("show_type_labels").unwrap().is_checked();
+                let number_links = app.find_name::<Checkbox>("number_links").unwrap().is_checked();
+                let line_numbers = app.find_name::<Checkbox>("line_numbers").unwrap().is_checked();
                 let html_command = app.find_name::<EditView>("html_command").unwrap().get_content();
                 let image_command = app.find_name::<EditView>("image_command").unwrap().get_content();
                 let telnet_command = app.find_name::<EditView>("telnet_command").unwrap().get_content();
+                let tts_command = app.find_name::<EditView>("tts_command").unwrap().get_content();
+                let media_command = app.find_name::<EditView>("media_command").unwrap().get_content();
                 let textwrap = app.find_name::<EditView>("textwrap").unwrap().get_content();
                 app.pop_layer();
                 if Url::parse(&homepage).is_ok() {
@@ -422,12 +1820,23 @@ pub(super) fn settings(app: &mut Cursive) {
                     SETTINGS.write().unwrap().config.html_command = html_command.to_string();
                     SETTINGS.write().unwrap().config.image_command = image_command.to_string();
                     SETTINGS.write().unwrap().config.telnet_command = telnet_command.to_string();
+                    SETTINGS.write().unwrap().config.tts_command = tts_command.to_string();
+                    SETTINGS.write().unwrap().config.media_command = media_command.to_string();
                     SETTINGS.write().unwrap().config.textwrap = textwrap.to_string();
                     SETTINGS.write().unwrap().config.disable_history = disable_history;
                     SETTINGS.write().unwrap().config.disable_identities = disable_identities;
-                    let theme = if darkmode { "darkmode" } else { "lightmode" };
-                    app.load_toml(SETTINGS.read().unwrap().get_theme_by_name(theme.to_string())).unwrap();
-                    SETTINGS.write().unwrap().config.theme = theme.to_string();
+                    SETTINGS.write().unwrap().config.autohide_menu = autohide_menu;
+                    SETTINGS.write().unwrap().config.open_images = open_images;
+                    SETTINGS.write().unwrap().config.show_type_labels = show_type_labels;
+                    SETTINGS.write().unwrap().config.number_links = number_links;
+                    SETTINGS.write().unwrap().config.line_numbers = line_numbers;
+                    app.set_autohide_menu(autohide_menu);
+                    if let Some(theme) = theme {
+                        crate::ui::theme::load_theme(app, &theme);
+                        SETTINGS.write().unwrap().config.theme = theme;
+                    }
+                    // after loading the theme, which may change the item colors
+                    Controller::render_again(app);
 
                     if let Err(why) = SETTINGS.write().unwrap().write_settings_to_file() {
                         app.add_layer(Dialog::info(format!("Could not write config file: {}", why)));
@@ -453,8 +1862,8 @@ pub(crate) fn manage_client_certificates(app: &mut Cursive) {
     let mut view: SelectView<ClientCertificate> = SelectView::new();
     for cc in client_certificates {
         let mut common_name = format!("{:<30}", cc.common_name.clone().as_str());
-        let format =
-            format_description::parse("[year]-[month]-[day]").expect("Could not parse date format");
+        let format = format_description::parse_borrowed::<1>("[year]-[month]-[day]")
+            .expect("Could not parse date format");
         let now = OffsetDateTime::now_utc().date();
         let expiration_date = format!("{:<10}", cc.expiration_date.format(&format).unwrap());
         let warning = if now > cc.expiration_date { "!" } else { " " };
@@ -551,8 +1960,8 @@ pub(crate) fn choose_client_certificate(app: &mut Cursive, url: Url) {
     let mut view: SelectView<ClientCertificate> = SelectView::new();
     for cc in client_certificates {
         let mut common_name = format!("{:<30}", cc.common_name.clone().as_str());
-        let format =
-            format_description::parse("[year]-[month]-[day]").expect("Could not parse date format");
+        let format = format_description::parse_borrowed::<1>("[year]-[month]-[day]")
+            .expect("Could not parse date format");
         let now = OffsetDateTime::now_utc().date();
         let expiration_date = format!("{:<10}", cc.expiration_date.format(&format).unwrap());
         let warning = if now > cc.expiration_date { "!" } else { " " };
@@ -675,8 +2084,8 @@ pub fn add_client_certificate(app: &mut Cursive, url: Option<Url>) {
     date = date
         .replace_year(date.year() + 1)
         .expect("Cannot create expiry date");
-    let format =
-        format_description::parse("[year]-[month]-[day]").expect("Could not parse date format");
+    let format = format_description::parse_borrowed::<1>("[year]-[month]-[day]")
+        .expect("Could not parse date format");
     let expiry_date = date.format(&format).unwrap();
     let original_url = url.clone();
 
@@ -725,7 +2134,7 @@ pub fn add_client_certificate(app: &mut Cursive, url: Option<Url>) {
                     )
             .button("Ok", move |app| {
                 let common_name = app.find_name::<EditView>("common_name").unwrap().get_content();
-                let notes = app.find_name::<TextArea>("notes").unwrap().get_content().to_string();
+                let _notes = app.find_name::<TextArea>("notes").unwrap().get_content().to_string();
                 let valid_until = app.find_name::<EditView>("valid_until").unwrap().get_content();
                 let specified_url = app.find_name::<EditView>("specified_url").unwrap().get_content();
 
@@ -738,10 +2147,9 @@ pub fn add_client_certificate(app: &mut Cursive, url: Option<Url>) {
                 }
 
                 // Validate date
-                let format = format_description::parse(
+                let format = format_description::parse_borrowed::<1>(
                     "[year]-[month]-[day]"
                 ).expect("Could not parse date format");
-                let valid_until_date : Date = date;
                 if let Ok(valid_until_date) = Date::parse(&valid_until, &format) {
                     info!("Parsed client certificate date: {:?}", valid_until_date);
                 } else {
@@ -778,7 +2186,6 @@ pub fn add_client_certificate(app: &mut Cursive, url: Option<Url>) {
                     }
                 }
 
-                let controller = app.user_data::<Controller>().expect("controller missing");
                 app.pop_layer();
                 if let Some(original_url) = &original_url {
                     let controller = app.user_data::<Controller>().expect("controller missing");
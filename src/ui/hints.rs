@@ -0,0 +1,196 @@
+use crate::controller::Controller;
+use crate::gophermap::GopherMapEntry;
+use crate::ui::layout::Layout;
+use cursive::{
+    event::{Event, EventResult, EventTrigger, Key},
+    theme::ColorStyle,
+    utils::markup::StyledString,
+    views::{DummyView, NamedView, OnEventView, ResizedView, ScrollView, SelectView},
+    Cursive, View,
+};
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+/// Letters of the hints, from the home row
+const HINT_KEYS: &[char] = &['a', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l'];
+
+/// Hints shown on the links of the current view
+struct Hints {
+    /// Name of the view, "content" or "gemini_content"
+    view: String,
+    /// Hints and the rows of their links
+    hints: Vec<(String, usize)>,
+    /// Rows with hints as they were before
+    originals: Vec<(usize, StyledString)>,
+    /// Letters typed so far
+    typed: String,
+}
+
+/// Returns `count` hints, single letters if there are few links
+fn hint_labels(count: usize) -> Vec<String> {
+    if count <= HINT_KEYS.len() {
+        return HINT_KEYS.iter().take(count).map(|c| c.to_string()).collect();
+    }
+    HINT_KEYS
+        .iter()
+        .flat_map(|a| HINT_KEYS.iter().map(move |b| format!("{}{}", a, b)))
+        .take(count)
+        .collect()
+}
+
+/// Returns the rows with links that are scrolled into view
+fn visible_links<T: 'static>(
+    app: &mut Cursive,
+    name: &str,
+    is_link: fn(&T) -> bool,
+) -> Vec<usize> {
+    let scroll_name = format!("{}_scroll", name);
+    let viewport = match app.find_name::<ScrollView<ResizedView<NamedView<SelectView<T>>>>>(
+        &scroll_name,
+    ) {
+        Some(scroll_view) => scroll_view.content_viewport(),
+        None => return Vec::new(),
+    };
+    let view = match app.find_name::<SelectView<T>>(name) {
+        Some(view) => view,
+        None => return Vec::new(),
+    };
+    (viewport.top()..=viewport.bottom())
+        .filter(|i| view.get_item(*i).is_some_and(|(_, item)| is_link(item)))
+        .collect()
+}
+
+/// Puts the hints in front of the rows of their links and returns the
+/// rows as they were before
+fn mark<T: 'static>(
+    app: &mut Cursive,
+    name: &str,
+    hints: &[(String, usize)],
+) -> Vec<(usize, StyledString)> {
+    let mut view = app.find_name::<SelectView<T>>(name).expect("content view missing");
+    let mut originals = Vec::new();
+    for (hint, row) in hints {
+        if let Some((label, _)) = view.get_item_mut(*row) {
+            originals.push((*row, label.clone()));
+            let mut marked = StyledString::styled(hint.as_str(), ColorStyle::highlight());
+            marked.append_plain(" ");
+            marked.append(label.clone());
+            *label = marked;
+        }
+    }
+    originals
+}
+
+fn restore<T: 'static>(app: &mut Cursive, name: &str, originals: Vec<(usize, StyledString)>) {
+    if let Some(mut view) = app.find_name::<SelectView<T>>(name) {
+        for (row, original) in originals {
+            if let Some((label, _)) = view.get_item_mut(row) {
+                *label = original;
+            }
+        }
+    }
+}
+
+/// Opens the link of a row as if it had been selected and submitted
+fn submit<T: 'static>(app: &mut Cursive, name: &str, row: usize) {
+    let result = match app.find_name::<SelectView<T>>(name) {
+        Some(mut view) => {
+            view.set_selection(row);
+            view.on_event(Event::Key(Key::Enter))
+        }
+        None => return,
+    };
+    result.process(app);
+}
+
+/// Shows two-letter hints on the visible links of the current view.
+/// Typing a hint opens its link, any other key cancels.
+pub fn show_hints(app: &mut Cursive) {
+    let view = app
+        .find_name::<Layout>("main")
+        .expect("main layout missing")
+        .get_current_view();
+    let rows = match view.as_str() {
+        "content" => visible_links::<GopherMapEntry>(app, &view, |e| !e.item_type.is_inline()),
+        "gemini_content" => visible_links::<Option<Url>>(app, &view, |url| url.is_some()),
+        _ => Vec::new(),
+    };
+    if rows.is_empty() {
+        app.user_data::<Controller>()
+            .expect("controller missing")
+            .set_message("No links in view");
+        return;
+    }
+    let hints: Vec<(String, usize)> = hint_labels(rows.len()).into_iter().zip(rows).collect();
+    let originals = match view.as_str() {
+        "content" => mark::<GopherMapEntry>(app, &view, &hints),
+        _ => mark::<Option<Url>>(app, &view, &hints),
+    };
+    let state = Arc::new(Mutex::new(Hints {
+        view,
+        hints,
+        originals,
+        typed: String::new(),
+    }));
+
+    // an invisible layer on top catches the keys typed
+    let on_key = move |_: &mut DummyView, event: &Event| {
+        let state = state.clone();
+        match event {
+            Event::Char(c) => {
+                let c = c.to_ascii_lowercase();
+                Some(EventResult::with_cb(move |app| on_letter(app, &state, c)))
+            }
+            Event::Key(_) | Event::CtrlChar(_) | Event::AltChar(_) => {
+                Some(EventResult::with_cb(move |app| {
+                    hide_hints(app, &state);
+                }))
+            }
+            _ => None,
+        }
+    };
+    let layer = OnEventView::new(DummyView).on_event_inner(EventTrigger::any(), on_key);
+    app.screen_mut().add_transparent_layer(layer);
+    app.user_data::<Controller>()
+        .expect("controller missing")
+        .set_message("Type a hint to open its link, Esc to cancel");
+}
+
+/// Removes the hints and returns the row of the link to open, if any
+fn hide_hints(app: &mut Cursive, state: &Arc<Mutex<Hints>>) -> Option<usize> {
+    app.pop_layer();
+    let mut state = state.lock().unwrap();
+    let originals = std::mem::take(&mut state.originals);
+    match state.view.as_str() {
+        "content" => restore::<GopherMapEntry>(app, &state.view, originals),
+        _ => restore::<Option<Url>>(app, &state.view, originals),
+    }
+    app.user_data::<Controller>()
+        .expect("controller missing")
+        .set_message("");
+    let typed = &state.typed;
+    state
+        .hints
+        .iter()
+        .find(|(hint, _)| hint == typed)
+        .map(|(_, row)| *row)
+}
+
+fn on_letter(app: &mut Cursive, state: &Arc<Mutex<Hints>>, c: char) {
+    let mut guard = state.lock().unwrap();
+    guard.typed.push(c);
+    let typed = guard.typed.clone();
+    let complete = guard.hints.iter().any(|(hint, _)| *hint == typed);
+    let possible = guard.hints.iter().any(|(hint, _)| hint.starts_with(&typed));
+    let view = guard.view.clone();
+    drop(guard);
+    if possible && !complete {
+        return;
+    }
+    if let Some(row) = hide_hints(app, state) {
+        match view.as_str() {
+            "content" => submit::<GopherMapEntry>(app, &view, row),
+            _ => submit::<Option<Url>>(app, &view, row),
+        }
+    }
+}
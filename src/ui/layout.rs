@@ -7,9 +7,16 @@ use cursive::theme::ColorStyle;
 use cursive::traits::View;
 use cursive::vec::Vec2;
 use cursive::view::{CannotFocus, IntoBoxedView, Selector};
-use cursive::views::EditView;
+use cursive::views::{EditView, NamedView, ResizedView, ScrollView, SelectView};
 use cursive::Printer;
 use unicode_width::UnicodeWidthStr;
+use url::Url;
+
+use crate::gophermap::GopherMapEntry;
+use crate::ui::statusbar::StatusBar;
+
+type GopherScrollView = ScrollView<ResizedView<NamedView<SelectView<GopherMapEntry>>>>;
+pub(crate) type GeminiScrollView = ScrollView<ResizedView<NamedView<SelectView<Option<Url>>>>>;
 
 struct Screen {
     title: String,
@@ -23,6 +30,14 @@ pub struct Layout {
     pub search: EditView,
     search_focused: bool,
     focus: Option<String>,
+    /// Right pane, e.g. the preview shown next to the gophermap
+    preview: Option<Screen>,
+    /// Name of the view the right pane is shown next to
+    preview_beside: String,
+    preview_focused: bool,
+    /// Width of the centered text column in reading mode, which hides
+    /// the title, statusbar and right pane
+    reading_column: Option<usize>,
     screenchange: bool,
     last_size: Vec2,
     //    theme: Theme,
@@ -37,6 +52,10 @@ impl Layout {
             search: EditView::new(),
             search_focused: false,
             focus: None,
+            preview: None,
+            preview_beside: String::new(),
+            preview_focused: false,
+            reading_column: None,
             screenchange: true,
             last_size: Vec2::new(0, 0),
             // theme,
@@ -54,7 +73,7 @@ impl Layout {
     }
 
     pub fn view<S: Into<String>, T: IntoBoxedView>(mut self, id: S, view: T, title: S) -> Self {
-        (&mut self).add_view(id, view, title);
+        self.add_view(id, view, title);
         self
     }
 
@@ -63,6 +82,7 @@ impl Layout {
         self.focus = Some(s);
         self.screenchange = true;
         self.search_focused = false;
+        self.preview_focused = false;
         self.stack.clear();
     }
 
@@ -72,6 +92,117 @@ impl Layout {
         }
     }
 
+    /// Shows a pane to the right of a view, e.g. "content"
+    pub fn set_preview<T: IntoBoxedView>(&mut self, view: T, title: String, beside: &str) {
+        self.preview = Some(Screen {
+            title,
+            view: view.into_boxed_view(),
+        });
+        self.preview_beside = beside.to_string();
+        self.preview_focused = false;
+    }
+
+    pub fn remove_preview(&mut self) {
+        self.preview = None;
+        self.preview_focused = false;
+    }
+
+    /// Returns whether the right pane belongs to a view, e.g. "content"
+    pub fn has_preview_beside(&self, view: &str) -> bool {
+        self.preview.is_some() && self.preview_beside == view
+    }
+
+    pub fn set_preview_title(&mut self, title: String) {
+        if let Some(preview) = &mut self.preview {
+            preview.title = title;
+        }
+    }
+
+    /// Moves the keyboard focus between the gophermap and the preview
+    pub fn toggle_preview_focus(&mut self) {
+        self.preview_focused = !self.preview_focused && self.preview_visible();
+    }
+
+    /// Turns reading mode on, showing the content in a centered column
+    /// of a width, or off
+    pub fn set_reading_column(&mut self, column: Option<usize>) {
+        self.reading_column = column;
+        self.preview_focused = false;
+    }
+
+    pub fn reading_column(&self) -> Option<usize> {
+        self.reading_column
+    }
+
+    /// Returns whether the right pane is shown, which it is only next
+    /// to the view it belongs to
+    fn preview_visible(&self) -> bool {
+        self.preview.is_some()
+            && self.reading_column.is_none()
+            && self.stack.is_empty()
+            && self.focus.as_deref() == Some(self.preview_beside.as_str())
+    }
+
+    /// Returns the width of the left pane when the screen is split
+    fn split_width(&self, width: usize) -> usize {
+        if self.preview_visible() {
+            width / 2
+        } else {
+            width
+        }
+    }
+
+    /// Returns the position and size of the content, which is below the
+    /// title and above the statusbar, or in the centered column of
+    /// reading mode
+    fn content_area(&self, size: Vec2) -> (Vec2, Vec2) {
+        match self.reading_column {
+            Some(column) => {
+                let column = column.min(size.x);
+                // the last line is kept for the search and command line
+                (
+                    Vec2::new((size.x - column) / 2, 0),
+                    Vec2::new(column, size.y.saturating_sub(1)),
+                )
+            }
+            None => (
+                Vec2::new(0, 1),
+                Vec2::new(self.split_width(size.x), size.y.saturating_sub(3)),
+            ),
+        }
+    }
+
+    /// Returns the scroll position of the content, e.g. "142/980 14%",
+    /// counting the rows down to the bottom of the screen
+    fn scroll_position(&mut self) -> Option<String> {
+        if !self.stack.is_empty() {
+            return None;
+        }
+        let name = match self.get_current_view().as_str() {
+            "content" => "content_scroll",
+            "gemini_content" => "gemini_content_scroll",
+            _ => return None,
+        };
+        let mut size = None;
+        self.get_current_screen_mut()
+            .view
+            .call_on_any(&Selector::Name(name), &mut |v| {
+                size = v
+                    .downcast_mut::<GopherScrollView>()
+                    .map(|s| (s.content_viewport(), s.inner_size()))
+                    .or_else(|| {
+                        v.downcast_mut::<GeminiScrollView>()
+                            .map(|s| (s.content_viewport(), s.inner_size()))
+                    });
+            });
+        let (viewport, inner) = size?;
+        if inner.y == 0 {
+            return None;
+        }
+        let line = (viewport.bottom() + 1).min(inner.y);
+        Some(format!("{}/{} {}%", line, inner.y, line * 100 / inner.y))
+    }
+
     fn get_current_screen(&self) -> &Screen {
         if !self.stack.is_empty() {
             self.stack.last().unwrap()
@@ -102,8 +233,25 @@ impl Layout {
     }
 
     pub fn enable_search(&mut self) {
+        self.enable_prompt("/");
+    }
+
+    /// Opens the command line for commands like ":open URL"
+    pub fn enable_command(&mut self) {
+        self.enable_prompt(":");
+    }
+
+    /// Opens the command line with the beginning of a command, e.g.
+    /// "goto " for the command ":goto LINE"
+    pub fn enable_command_with(&mut self, command: &str) {
+        self.enable_prompt(&format!(":{}", command));
+    }
+
+    /// Opens the line below the content, which starts with a prompt
+    /// telling searches and commands apart
+    fn enable_prompt(&mut self, prompt: &str) {
         if !self.search_focused {
-            self.search.set_content("/");
+            self.search.set_content(prompt);
             self.search_focused = true;
         }
     }
@@ -116,28 +264,49 @@ impl Layout {
 
 impl View for Layout {
     fn draw(&self, printer: &Printer<'_, '_>) {
-        let search_visible = self.search.get_content().len() > 0;
+        let search_visible = !self.search.get_content().is_empty();
         let screen = self.get_current_screen();
+        let width = self.split_width(printer.size.x);
+        let (content_offset, content_size) = self.content_area(printer.size);
         // screen title
-        printer.with_color(ColorStyle::title_primary(), |printer| {
-            let offset = HAlign::Center.get_offset(screen.title.width(), printer.size.x);
-            printer.print((offset, 0), &screen.title);
+        if self.reading_column.is_none() {
+            printer.with_color(ColorStyle::title_primary(), |printer| {
+                let offset = HAlign::Center.get_offset(screen.title.width(), width);
+                printer.print((offset, 0), &screen.title);
 
-            if !self.stack.is_empty() {
-                printer.print((1, 0), "<");
-            }
-        });
+                if !self.stack.is_empty() {
+                    printer.print((1, 0), "<");
+                }
+            });
+        }
 
         // screen content
         screen.view.draw(
             &printer
-                .offset((0, 1))
-                .cropped((printer.size.x, printer.size.y - 3))
-                .focused(true),
+                .offset(content_offset)
+                .cropped(content_size)
+                .focused(!self.preview_focused),
         );
 
-        self.statusbar
-            .draw(&printer.offset((0, printer.size.y - 2)));
+        if let Some(preview) = self.preview.as_ref().filter(|_| self.preview_visible()) {
+            let preview_width = printer.size.x.saturating_sub(width + 1);
+            printer.with_color(ColorStyle::title_primary(), |printer| {
+                let offset = HAlign::Center.get_offset(preview.title.width(), preview_width);
+                printer.print((width + 1 + offset, 0), &preview.title);
+            });
+            printer.print_vline((width, 0), printer.size.y - 2, "│");
+            preview.view.draw(
+                &printer
+                    .offset((width + 1, 1))
+                    .cropped((preview_width, printer.size.y - 3))
+                    .focused(self.preview_focused),
+            );
+        }
+
+        if self.reading_column.is_none() {
+            self.statusbar
+                .draw(&printer.offset((0, printer.size.y - 2)));
+        }
 
         if search_visible {
             let printer = &printer.offset((0, printer.size.y - 1));
@@ -151,9 +320,23 @@ impl View for Layout {
         self.statusbar.layout(Vec2::new(size.x, 2));
         self.search.layout(Vec2::new(size.x, 1));
 
-        self.get_current_screen_mut()
-            .view
-            .layout(Vec2::new(size.x, size.y - 3));
+        let width = self.split_width(size.x);
+        let (_, content_size) = self.content_area(size);
+        self.get_current_screen_mut().view.layout(content_size);
+        let position = self.scroll_position();
+        self.statusbar
+            .call_on_any(&Selector::Name("statusbar"), &mut |v| {
+                if let Some(statusbar) = v.downcast_mut::<StatusBar>() {
+                    statusbar.set_position(position.clone());
+                }
+            });
+        if self.preview_visible() {
+            if let Some(preview) = &mut self.preview {
+                preview
+                    .view
+                    .layout(Vec2::new(size.x.saturating_sub(width + 1), size.y - 3));
+            }
+        }
 
         // the focus view has changed, let the views know so they can redraw
         // their items
@@ -167,9 +350,20 @@ impl View for Layout {
     }
 
     fn on_event(&mut self, event: Event) -> EventResult {
-        let search_visible = self.search.get_content().len() > 0;
+        let search_visible = !self.search.get_content().is_empty();
         if let Event::Mouse { position, .. } = event {
-            if position.y < self.last_size.y.saturating_sub(2) {
+            let width = self.split_width(self.last_size.x);
+            if self.reading_column.is_some() {
+                let (content_offset, _) = self.content_area(self.last_size);
+                if let Some(ref id) = self.focus {
+                    let screen = self.views.get_mut(id).unwrap();
+                    screen.view.on_event(event.relativized(content_offset));
+                }
+            } else if position.y < self.last_size.y.saturating_sub(2) && position.x > width {
+                if let Some(preview) = &mut self.preview {
+                    preview.view.on_event(event.relativized(Vec2::new(width + 1, 1)));
+                }
+            } else if position.y < self.last_size.y.saturating_sub(2) {
                 if let Some(ref id) = self.focus {
                     let screen = self.views.get_mut(id).unwrap();
                     screen.view.on_event(event.relativized(Vec2::new(0, 1)));
@@ -182,6 +376,8 @@ impl View for Layout {
             EventResult::Consumed(None)
         } else if search_visible {
             self.search.on_event(event)
+        } else if self.preview_focused && self.preview_visible() {
+            self.preview.as_mut().unwrap().view.on_event(event)
         } else {
             self.get_current_screen_mut().view.on_event(event)
         }
@@ -190,6 +386,8 @@ impl View for Layout {
     fn call_on_any<'a>(&mut self, s: &Selector, c: AnyCb<'a>) {
         if let Selector::Name("statusbar") = s {
             self.statusbar.call_on_any(s, c);
+        } else if let (Selector::Name("preview" | "toc"), Some(preview)) = (s, &mut self.preview) {
+            preview.view.call_on_any(s, c)
         } else {
             self.get_current_screen_mut().view.call_on_any(s, c)
         }
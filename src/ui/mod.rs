@@ -1,4 +1,10 @@
+pub mod command;
+pub mod composer;
 pub mod dialogs;
+pub mod hints;
 pub mod layout;
 pub mod setup;
 pub mod statusbar;
+pub mod terminal;
+pub mod theme;
+pub mod toc;
@@ -1,19 +1,68 @@
-use crate::controller::{Controller, Direction};
+use crate::controller::{Controller, Direction, BOOKMARK_MENU_ITEMS, HISTORY_MENU_ITEMS};
 use crate::gophermap::{GopherMapEntry, ItemType};
 use crate::ui::{dialogs, layout::Layout, statusbar::StatusBar};
 use cursive::{
-    event::Key,
+    event::{Event, Key},
     menu::Tree,
+    utils::markup::StyledString,
     view::{Nameable, Resizable, Scrollable},
     views::{Dialog, NamedView, OnEventView, ResizedView, ScrollView, SelectView, ViewRef},
     Cursive, View,
 };
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use url::Url;
 use crate::bookmarks::Bookmark;
 use crate::history::HistoryEntry;
+use crate::SETTINGS;
 
 const HELP: &str = include_str!("../help.txt");
 
+/// Keys whose default bindings are replaced by the vim keymap
+const VIM_REPLACED_KEYS: [char; 7] = ['g', 'G', 'h', 'H', 'L', 'o', 'O'];
+
+/// Keys of the vim keymap and their commands
+const VIM_KEYS: &[(&str, &str)] = &[
+    ("gg", "Go to the first line"),
+    ("G", "Go to the last line"),
+    ("Ctrl-D", "Move half a page down"),
+    ("Ctrl-U", "Move half a page up"),
+    ("h", "Go to previous link"),
+    ("H", "Navigate back"),
+    ("L", "Navigate forward"),
+    ("o", "Open new URL"),
+    ("O", "Edit current URL"),
+];
+
+/// Returns the bound keys and their commands: the keys of the help text,
+/// with the changes of the keymap setting
+pub fn key_bindings() -> Vec<(String, String)> {
+    let mut bindings: Vec<(String, String)> = HELP
+        .lines()
+        .filter_map(|line| {
+            let mut cells = line.trim().strip_prefix('|')?.strip_suffix('|')?.split('|');
+            let key = cells.next()?.trim();
+            let command = cells.next()?.trim();
+            Some((key.to_string(), command.to_string()))
+        })
+        .filter(|(key, _)| key != "Key")
+        .collect();
+    if SETTINGS.read().unwrap().config.keymap == "vim" {
+        bindings.retain(|(key, _)| {
+            let mut chars = key.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => !VIM_REPLACED_KEYS.contains(&c),
+                _ => true,
+            }
+        });
+        let keys = VIM_KEYS.iter().map(|(key, command)| (key.to_string(), command.to_string()));
+        // before the help key
+        let help = bindings.len().saturating_sub(1);
+        bindings.splice(help..help, keys);
+    }
+    bindings
+}
+
 pub fn setup(app: &mut Cursive) {
     trace!("ui::setup");
     setup_keys(app);
@@ -23,10 +72,15 @@ pub fn setup(app: &mut Cursive) {
 
 /// Register global keys.
 fn setup_keys(app: &mut Cursive) {
-    app.set_autohide_menu(false);
+    // The menubar is always revealed by pressing Esc, unless a page is
+    // being loaded
+    app.set_autohide_menu(SETTINGS.read().unwrap().config.autohide_menu);
 
     // TODO: Make keys configurable
     app.add_global_callback(Key::Esc, |app| {
+        if Controller::cancel_request_action(app) {
+            return;
+        }
         app.call_on_name("main", |v: &mut Layout| v.clear_search())
             .expect("main layout missing");
         app.select_menubar()
@@ -61,15 +115,6 @@ fn setup_keys(app: &mut Cursive) {
                 let cur = view.selected_id().unwrap_or(0);
                 if let Some((_, item)) = view.get_item(cur) {
                     match item.item_type {
-                        ItemType::Html => {
-                            let mut url = item.url.to_string();
-                            if url.starts_with("URL:") {
-                                url.replace_range(..3, "");
-                            }
-                            app.user_data::<Controller>()
-                                .expect("controller missing")
-                                .set_message(&format!("URL '{}'", url));
-                        }
                         ItemType::Inline => (),
                         _ => app
                             .user_data::<Controller>()
@@ -109,11 +154,55 @@ fn setup_keys(app: &mut Cursive) {
         move_to_link(app, Direction::Previous);
     });
     app.add_global_callback('a', dialogs::add_bookmark_current_url);
-    app.add_global_callback('?', |s| s.add_layer(Dialog::info(HELP)));
+    app.add_global_callback(Event::WindowResize, Controller::reflow_action);
+    if SETTINGS.read().unwrap().config.keymap == "vim" {
+        setup_vim_keys(app);
+    }
+    #[cfg(unix)]
+    app.add_global_callback(Event::CtrlChar('z'), crate::ui::terminal::suspend_to_shell);
+    app.add_global_callback('C', Controller::toggle_cp437_action);
+    app.add_global_callback('m', toggle_mark);
+    app.add_global_callback('u', Controller::undo_remove_bookmark_action);
+    app.add_global_callback('M', dialogs::bulk_actions);
+    app.add_global_callback('Q', dialogs::qr_code);
+    app.add_global_callback('v', Controller::view_as_text_action);
+    app.add_global_callback('p', Controller::open_in_pager_action);
+    app.add_global_callback('t', Controller::toggle_type_labels_action);
+    app.add_global_callback('w', Controller::toggle_split_action);
+    app.add_global_callback('f', crate::ui::hints::show_hints);
+    app.add_global_callback('D', dialogs::downloads);
+    app.add_global_callback('e', dialogs::message_history);
+    app.add_global_callback('U', dialogs::go_up);
+    app.add_global_callback('T', crate::ui::toc::toggle_toc);
+    app.add_global_callback('z', Controller::toggle_reading_mode_action);
+    app.add_global_callback('#', Controller::toggle_line_numbers_action);
+    app.add_global_callback('B', dialogs::find_bookmark);
+    for digit in '0'..='9' {
+        app.add_global_callback(digit, move |app| Controller::link_number_action(app, digit));
+        app.add_global_callback(Event::AltChar(digit), move |app| {
+            Controller::open_bookmark_slot_action(app, digit)
+        });
+    }
+    app.add_global_callback(Key::Tab, |app| {
+        app.call_on_name("main", |v: &mut Layout| v.toggle_preview_focus())
+            .expect("main layout missing");
+    });
+    app.add_global_callback('I', Controller::item_info_action);
+    app.add_global_callback('R', Controller::read_aloud_action);
+    app.add_global_callback('S', Controller::stop_reading_action);
+    app.add_global_callback('?', show_key_bindings);
     app.add_global_callback('/', move |app| {
         app.call_on_name("main", |v: &mut Layout| v.enable_search())
             .expect("main layout missing");
     });
+    app.add_global_callback(':', |app| {
+        app.call_on_name("main", |v: &mut Layout| v.enable_command())
+            .expect("main layout missing");
+    });
+    app.add_global_callback('%', |app| {
+        app.call_on_name("main", |v: &mut Layout| v.enable_command_with("goto "))
+            .expect("main layout missing");
+    });
     app.add_global_callback('n', |app| {
         let controller = app.user_data::<Controller>().expect("controller missing");
         let hits = controller.current_search_results.clone();
@@ -164,8 +253,19 @@ fn setup_menu(app: &mut Cursive) {
         "File",
         Tree::new()
             .leaf("Open URL...", dialogs::open_url)
+            .leaf("View file as text...", dialogs::view_file)
             .delimiter()
             .leaf("Save page as...", dialogs::save_as)
+            .leaf("Reload with encoding...", dialogs::reload_with_encoding)
+            .leaf("Show QR code", dialogs::qr_code)
+            .leaf("Downloads...", dialogs::downloads)
+            .delimiter()
+            .leaf("Export session...", dialogs::export_session)
+            .leaf("Import session...", dialogs::import_session)
+            .delimiter()
+            .leaf("Lint gophermap", dialogs::lint_gophermap)
+            .leaf("Lint gophermap file...", dialogs::lint_gophermap_file)
+            .delimiter()
             .leaf("Settings...", dialogs::settings)
             .delimiter()
             .leaf("Quit", Cursive::quit),
@@ -174,6 +274,7 @@ fn setup_menu(app: &mut Cursive) {
         "History",
         Tree::new()
             .leaf("Show all history...", dialogs::edit_history)
+            .leaf("Search visited pages...", dialogs::search_pages)
             .leaf("Clear history", |app| {
                 app.user_data::<Controller>()
                     .expect("controller missing")
@@ -185,7 +286,17 @@ fn setup_menu(app: &mut Cursive) {
         "Bookmarks",
         Tree::new()
             .leaf("Edit...", dialogs::edit_bookmarks)
+            .leaf("Find...", dialogs::find_bookmark)
             .leaf("Add bookmark", dialogs::add_bookmark_current_url)
+            .leaf("Edit this bookmark...", dialogs::edit_current_bookmark)
+            .leaf("Delete this bookmark", dialogs::delete_current_bookmark)
+            .leaf("Export...", dialogs::export_bookmarks)
+            .leaf("Trash...", dialogs::bookmark_trash)
+            .leaf("Sync", |app| {
+                app.user_data::<Controller>()
+                    .expect("controller missing")
+                    .sync_bookmarks();
+            })
             .delimiter(),
     );
     menubar.add_subtree(
@@ -202,13 +313,14 @@ fn setup_menu(app: &mut Cursive) {
             .subtree(
                 "Help",
                 Tree::new()
-                    .leaf("Keys", |s| s.add_layer(Dialog::info(HELP)))
+                    .leaf("Keys", show_key_bindings)
                     .leaf("Extended", |app| {
                         app.user_data::<Controller>()
                             .expect("controller missing")
                             .open_url(Url::parse("about:help").unwrap(), false, 0);
                     }),
             )
+            .leaf("Messages...", dialogs::message_history)
             .leaf("About", |s| {
                 s.add_layer(Dialog::info(format!(
                     "                      ncgopher v{:<15}\n\
@@ -263,6 +375,10 @@ fn setup_ui(app: &mut Cursive) {
             },
         );
     });
+    if !SETTINGS.read().unwrap().config.clock_format.is_empty() {
+        // redraw regularly to keep the clock in the status bar current
+        app.set_fps(1);
+    }
     let status = StatusBar::new().with_name("statusbar");
     let mut layout = Layout::new(status /*, theme*/)
         .view("content", event_view, "Gophermap")
@@ -277,14 +393,24 @@ fn setup_ui(app: &mut Cursive) {
                     v.clear_search();
                 }
             });
+            // search while typing
+            if let Some(search_str) = cmd.strip_prefix('/') {
+                app.user_data::<Controller>()
+                    .expect("controller missing")
+                    .search(search_str.to_string());
+            }
         });
-        v.search.set_on_submit(move |app, search_str| {
+        v.search.set_on_submit(move |app, cmd| {
             app.call_on_name("main", |v: &mut Layout| {
                 v.clear_search();
             });
-            app.user_data::<Controller>()
-                .expect("controller missing")
-                .search(search_str[1..].to_string());
+            match cmd.strip_prefix(':') {
+                Some(command) => crate::ui::command::run(app, command),
+                None => app
+                    .user_data::<Controller>()
+                    .expect("controller missing")
+                    .search(cmd[1..].to_string()),
+            }
         });
     })
     .expect("main layout missing");
@@ -292,15 +418,18 @@ fn setup_ui(app: &mut Cursive) {
 
 
 pub fn setup_bookmark_menu(app: &mut Cursive, bookmarks: &Vec<Bookmark>) {
-    // Add bookmarks to bookmark menu on startup
+    // Replace the bookmarks in the bookmark menu, keeping "Edit..." etc.
     info!("Adding existing bookmarks to menu");
     let menutree = app
         .menubar()
         .find_subtree("Bookmarks")
         .expect("bookmarks menu missing");
+    while menutree.len() > BOOKMARK_MENU_ITEMS {
+        menutree.remove(BOOKMARK_MENU_ITEMS);
+    }
     for entry in bookmarks {
         let url = entry.url.clone();
-        menutree.insert_leaf(3, &entry.title, move |app| {
+        menutree.add_leaf(&entry.title, move |app| {
             app.user_data::<Controller>()
                 .expect("controller missing")
                 .open_url(url.clone(), true, 0);
@@ -317,7 +446,7 @@ pub fn setup_history_menu(app: &mut Cursive, entries: &Vec<HistoryEntry>) {
     for entry in entries {
         let title = entry.title.clone();
         let url = entry.url.clone();
-        menutree.insert_leaf(3, &title, move |app| {
+        menutree.insert_leaf(HISTORY_MENU_ITEMS, &title, move |app| {
             app.user_data::<Controller>()
                 .expect("controller missing")
                 .open_url(url.clone(), true, 0);
@@ -328,6 +457,63 @@ pub fn setup_history_menu(app: &mut Cursive, entries: &Vec<HistoryEntry>) {
 //--------- interface manipulation functions ---------------------------
 
 fn move_selection(app: &mut Cursive, dir: Direction) {
+    move_selection_by(app, dir, 1);
+}
+
+/// Replaces some of the default keys with the ones of vim: gg and G
+/// go to the top and bottom, Ctrl-D and Ctrl-U scroll half a page, H and
+/// L go back and forward in the history, o opens a URL and O edits the
+/// current one. h goes to the previous link instead of L.
+fn setup_vim_keys(app: &mut Cursive) {
+    for key in VIM_REPLACED_KEYS {
+        app.clear_global_callbacks(key);
+    }
+    // gg: the first g is remembered for a second
+    let pending_g = Arc::new(Mutex::new(None::<Instant>));
+    app.add_global_callback('g', move |app| {
+        let mut pending = pending_g.lock().unwrap();
+        match pending.take() {
+            Some(typed) if typed.elapsed() < Duration::from_secs(1) => {
+                drop(pending);
+                move_selection_by(app, Direction::Previous, usize::MAX);
+            }
+            _ => *pending = Some(Instant::now()),
+        }
+    });
+    app.add_global_callback('G', |app| move_selection_by(app, Direction::Next, usize::MAX));
+    app.add_global_callback(Event::CtrlChar('d'), |app| {
+        let rows = app.screen_size().y / 2;
+        move_selection_by(app, Direction::Next, rows);
+    });
+    app.add_global_callback(Event::CtrlChar('u'), |app| {
+        let rows = app.screen_size().y / 2;
+        move_selection_by(app, Direction::Previous, rows);
+    });
+    app.add_global_callback('H', |app| {
+        app.user_data::<Controller>()
+            .expect("controller missing")
+            .navigate_back();
+    });
+    app.add_global_callback('L', |app| {
+        app.user_data::<Controller>()
+            .expect("controller missing")
+            .navigate_forward();
+    });
+    app.add_global_callback('h', |app| move_to_link(app, Direction::Previous));
+    app.add_global_callback('o', dialogs::open_url);
+    app.add_global_callback('O', dialogs::open_current_url);
+}
+
+/// Opens the page listing the bound keys
+fn show_key_bindings(app: &mut Cursive) {
+    app.user_data::<Controller>()
+        .expect("controller missing")
+        .open_url(Url::parse("about:keys").unwrap(), true, 0);
+}
+
+/// Moves the selection by a number of rows, stopping at the first or
+/// last row
+fn move_selection_by(app: &mut Cursive, dir: Direction, rows: usize) {
     let current_view = app
         .find_name::<Layout>("main")
         .expect("main layout missing")
@@ -338,9 +524,10 @@ fn move_selection(app: &mut Cursive, dir: Direction) {
             let mut view = app
                 .find_name::<SelectView<GopherMapEntry>>("content")
                 .expect("View content missing");
+            let rows = rows.min(view.len());
             let callback = match dir {
-                Direction::Next => view.select_down(1),
-                Direction::Previous => view.select_up(1),
+                Direction::Next => view.select_down(rows),
+                Direction::Previous => view.select_up(rows),
             };
             callback(app);
             if let Some(id) = view.selected_id() {
@@ -355,9 +542,10 @@ fn move_selection(app: &mut Cursive, dir: Direction) {
             let mut view = app
                 .find_name::<SelectView<Option<Url>>>("gemini_content")
                 .expect("View gemini_content missing");
+            let rows = rows.min(view.len());
             let callback = match dir {
-                Direction::Next => view.select_down(1),
-                Direction::Previous => view.select_up(1),
+                Direction::Next => view.select_down(rows),
+                Direction::Previous => view.select_up(rows),
             };
             callback(app);
             if let Some(id) = view.selected_id() {
@@ -372,6 +560,157 @@ fn move_selection(app: &mut Cursive, dir: Direction) {
     }
 }
 
+/// Goes to a line of the current page, e.g. "42", or to a percentage
+/// of it, e.g. "50%", like less does
+pub(crate) fn go_to_line(app: &mut Cursive, position: &str) {
+    let position = position.trim();
+    let (number, percent) = match position.strip_suffix('%') {
+        Some(number) => (number.trim(), true),
+        None => (position, false),
+    };
+    let number: usize = match number.parse() {
+        Ok(number) => number,
+        Err(_) => {
+            app.user_data::<Controller>()
+                .expect("controller missing")
+                .set_message(&format!("Not a line number or percentage: {}", position));
+            return;
+        }
+    };
+    let current_view = app
+        .find_name::<Layout>("main")
+        .expect("main layout missing")
+        .get_current_view();
+    let len = match current_view.as_str() {
+        "content" => app.find_name::<SelectView<GopherMapEntry>>("content").map(|v| v.len()),
+        "gemini_content" => app
+            .find_name::<SelectView<Option<Url>>>("gemini_content")
+            .map(|v| v.len()),
+        _ => None,
+    }
+    .unwrap_or_default();
+    if len == 0 {
+        return;
+    }
+    let row = if percent {
+        len * number.min(100) / 100
+    } else {
+        number.saturating_sub(1)
+    }
+    .min(len - 1);
+    match current_view.as_str() {
+        "content" => {
+            app.find_name::<SelectView<GopherMapEntry>>("content")
+                .expect("View content missing")
+                .set_selection(row);
+            app.find_name::<ScrollView<ResizedView<NamedView<SelectView<GopherMapEntry>>>>>(
+                "content_scroll",
+            )
+            .expect("gopher scroll view missing")
+            .set_offset(cursive::Vec2::new(0, row));
+        }
+        _ => {
+            app.find_name::<SelectView<Option<Url>>>("gemini_content")
+                .expect("View gemini_content missing")
+                .set_selection(row);
+            app.find_name::<ScrollView<ResizedView<NamedView<SelectView<Option<Url>>>>>>(
+                "gemini_content_scroll",
+            )
+            .expect("gemini scroll view missing")
+            .set_offset(cursive::Vec2::new(0, row));
+        }
+    }
+}
+
+/// Returns the URL of the link under the cursor, if any
+pub(crate) fn selected_url(app: &mut Cursive) -> Option<Url> {
+    let current_view = app
+        .find_name::<Layout>("main")
+        .expect("main layout missing")
+        .get_current_view();
+    match current_view.as_str() {
+        "content" => {
+            let view = app
+                .find_name::<SelectView<GopherMapEntry>>("content")
+                .expect("View content missing");
+            let (_, entry) = view.get_item(view.selected_id()?)?;
+            if entry.item_type.is_inline() {
+                None
+            } else {
+                Some(entry.url.clone())
+            }
+        }
+        "gemini_content" => {
+            let view = app
+                .find_name::<SelectView<Option<Url>>>("gemini_content")
+                .expect("View gemini_content missing");
+            let (_, url) = view.get_item(view.selected_id()?)?;
+            url.clone()
+        }
+        other => unreachable!("unknown view {} in main layout", other),
+    }
+}
+
+/// Marks or unmarks the selected gophermap entry for bulk actions and
+/// moves to the next line
+fn toggle_mark(app: &mut Cursive) {
+    let current_view = app
+        .find_name::<Layout>("main")
+        .expect("main layout missing")
+        .get_current_view();
+    if current_view != "content" {
+        return;
+    }
+    let mut view = app
+        .find_name::<SelectView<GopherMapEntry>>("content")
+        .expect("View content missing");
+    let id = match view.selected_id() {
+        Some(id) if !view.get_item(id).unwrap().1.item_type.is_inline() => id,
+        _ => return,
+    };
+    let controller = app.user_data::<Controller>().expect("controller missing");
+    let marked = match controller.marked.iter().position(|&i| i == id) {
+        Some(pos) => {
+            controller.marked.remove(pos);
+            false
+        }
+        None => {
+            controller.marked.push(id);
+            true
+        }
+    };
+    controller.set_message(&format!("{} entries marked", controller.marked.len()));
+    if let Some((label, _)) = view.get_item_mut(id) {
+        let source = label.source().to_string();
+        *label = if marked {
+            StyledString::plain(format!("*{}", source))
+        } else {
+            StyledString::plain(source.strip_prefix('*').unwrap_or(&source))
+        };
+    }
+    drop(view);
+    move_selection(app, Direction::Next);
+}
+
+/// Removes all marks from gophermap entries
+pub(crate) fn clear_marks(app: &mut Cursive) {
+    let marked = std::mem::take(
+        &mut app
+            .user_data::<Controller>()
+            .expect("controller missing")
+            .marked,
+    );
+    let mut view = app
+        .find_name::<SelectView<GopherMapEntry>>("content")
+        .expect("View content missing");
+    for id in marked {
+        if let Some((label, _)) = view.get_item_mut(id) {
+            let source = label.source().to_string();
+            *label = StyledString::plain(source.strip_prefix('*').unwrap_or(&source));
+        }
+    }
+}
+
 fn move_to_link(app: &mut Cursive, dir: Direction) {
     let current_view = app
         .find_name::<Layout>("main")
@@ -1,12 +1,28 @@
+use ::time::OffsetDateTime;
 use cursive::theme::ColorStyle;
 use cursive::traits::View;
 use cursive::vec::Vec2;
 use cursive::Printer;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
+use unicode_width::UnicodeWidthStr;
+use url::Url;
+
+use crate::downloads::Download;
+use crate::url_tools::human_readable_url;
+use crate::SETTINGS;
+
+/// Marks the start of the right-aligned part of the statusbar format
+const RIGHT: &str = "{right}";
 
 pub struct StatusBar {
     last_size: Vec2,
     message: Arc<RwLock<String>>,
+    /// Scroll position of the content, e.g. "142/980 14%"
+    position: Option<String>,
+    /// URL of the current page, shared with the controller
+    url: Option<Arc<Mutex<Url>>>,
+    /// Downloads, shared with the controller
+    downloads: Option<Arc<Mutex<Vec<Download>>>>,
 }
 
 impl StatusBar {
@@ -14,12 +30,127 @@ impl StatusBar {
         StatusBar {
             last_size: Vec2::new(0, 0),
             message: Arc::new(RwLock::new(String::new())),
+            position: None,
+            url: None,
+            downloads: None,
         }
     }
 
     pub fn get_message(&self) -> Arc<RwLock<String>> {
         self.message.clone()
     }
+
+    pub fn set_position(&mut self, position: Option<String>) {
+        self.position = position;
+    }
+
+    /// Gives the statusbar access to the state shown by the {url} and
+    /// {downloads} segments
+    pub fn share(&mut self, url: Arc<Mutex<Url>>, downloads: Arc<Mutex<Vec<Download>>>) {
+        self.url = Some(url);
+        self.downloads = Some(downloads);
+    }
+
+    /// Returns the value of a segment of the statusbar format
+    fn segment(&self, name: &str, message: &str) -> Option<String> {
+        let value = match name {
+            "message" => message.to_string(),
+            "url" => self
+                .url
+                .as_ref()
+                .map(|url| human_readable_url(&url.lock().unwrap()))
+                .unwrap_or_default(),
+            "position" => self.position.clone().unwrap_or_default(),
+            "time" => {
+                let clock_format = SETTINGS.read().unwrap().config.clock_format.clone();
+                if clock_format.is_empty() {
+                    String::new()
+                } else {
+                    let now =
+                        OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+                    strftime(&now, &clock_format)
+                }
+            }
+            "downloads" => {
+                let active = self.downloads.as_ref().map_or(0, |downloads| {
+                    downloads.lock().unwrap().iter().filter(|d| d.is_active()).count()
+                });
+                match active {
+                    0 => String::new(),
+                    1 => "1 download".to_string(),
+                    n => format!("{} downloads", n),
+                }
+            }
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Replaces the segments of a part of the statusbar format, e.g.
+    /// "{url} | {position}". Unknown segments are copied verbatim.
+    fn render(&self, template: &str, message: &str) -> String {
+        let mut out = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let value = rest
+                .find('}')
+                .and_then(|end| Some((end, self.segment(&rest[1..end], message)?)));
+            match value {
+                Some((end, value)) => {
+                    out.push_str(&value);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    out.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        out.push_str(rest);
+        out.trim().to_string()
+    }
+}
+
+/// Formats a timestamp using the most common strftime(3) conversion
+/// specifications. Unknown specifications are copied verbatim.
+fn strftime(time: &OffsetDateTime, format: &str) -> String {
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('H') => out.push_str(&format!("{:02}", time.hour())),
+            Some('I') => out.push_str(&format!("{:02}", (time.hour() + 11) % 12 + 1)),
+            Some('M') => out.push_str(&format!("{:02}", time.minute())),
+            Some('S') => out.push_str(&format!("{:02}", time.second())),
+            Some('p') => out.push_str(if time.hour() < 12 { "AM" } else { "PM" }),
+            Some('Y') => out.push_str(&time.year().to_string()),
+            Some('y') => out.push_str(&format!("{:02}", time.year() % 100)),
+            Some('m') => out.push_str(&format!("{:02}", u8::from(time.month()))),
+            Some('d') => out.push_str(&format!("{:02}", time.day())),
+            Some('e') => out.push_str(&format!("{:>2}", time.day())),
+            Some('j') => out.push_str(&format!("{:03}", time.ordinal())),
+            Some('a') => out.push_str(&time.weekday().to_string()[..3]),
+            Some('A') => out.push_str(&time.weekday().to_string()),
+            Some('b') => out.push_str(&time.month().to_string()[..3]),
+            Some('B') => out.push_str(&time.month().to_string()),
+            Some('F') => out.push_str(&strftime(time, "%Y-%m-%d")),
+            Some('R') => out.push_str(&strftime(time, "%H:%M")),
+            Some('T') => out.push_str(&strftime(time, "%H:%M:%S")),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
 }
 
 impl View for StatusBar {
@@ -28,12 +159,20 @@ impl View for StatusBar {
             warn!("status bar height is zero");
             return;
         }
-        let msg = self.message.read().unwrap();
+        let msg = self.message.read().unwrap().clone();
+        let format = SETTINGS.read().unwrap().config.statusbar_format.clone();
+        let (left, right) = format.split_once(RIGHT).unwrap_or((format.as_str(), ""));
+        let left = self.render(left, &msg);
+        let right = self.render(right, &msg);
         printer.with_color(ColorStyle::highlight_inactive(), |printer| {
             // clear line
             printer.print_hline((0, 0), printer.size.x, " ");
             // write content
-            printer.print((1, 0), msg.as_str());
+            printer.print((1, 0), &left);
+            if !right.is_empty() {
+                let offset = printer.size.x.saturating_sub(right.width() + 1);
+                printer.print((offset, 0), &format!(" {}", right));
+            }
         });
         printer.with_color(ColorStyle::tertiary(), |printer|{
             // clear line
@@ -0,0 +1,122 @@
+use base64::{engine::general_purpose, Engine as _};
+use cursive::{Cursive, CursiveRunnable};
+use std::io::{stdout, Write};
+
+/// Names of the backends compiled into this binary
+pub const BACKENDS: &[&str] = &[
+    #[cfg(feature = "pancurses-backend")]
+    "pancurses",
+    #[cfg(feature = "ncurses-backend")]
+    "ncurses",
+    #[cfg(feature = "termion-backend")]
+    "termion",
+    #[cfg(feature = "crossterm-backend")]
+    "crossterm",
+];
+
+/// Creates the cursive runner for the backend with the given name. An
+/// empty name selects the first backend compiled into the binary.
+pub fn create_runner(backend: &str) -> Result<CursiveRunnable, String> {
+    let backend = if backend.is_empty() {
+        BACKENDS.first().copied().unwrap_or_default()
+    } else {
+        backend
+    };
+    let runner = match backend {
+        #[cfg(feature = "pancurses-backend")]
+        "pancurses" => CursiveRunnable::pancurses(),
+        #[cfg(feature = "ncurses-backend")]
+        "ncurses" => CursiveRunnable::ncurses(),
+        #[cfg(feature = "termion-backend")]
+        "termion" => CursiveRunnable::termion(),
+        #[cfg(feature = "crossterm-backend")]
+        "crossterm" => CursiveRunnable::crossterm(),
+        other => {
+            return Err(format!(
+                "Backend '{}' is not available. Available backends: {}",
+                other,
+                BACKENDS.join(", ")
+            ))
+        }
+    };
+    *crate::BACKEND.write().unwrap() = backend.to_string();
+    Ok(runner)
+}
+
+/// Hands the terminal over to an external program running in the
+/// foreground. Call `resume` once the program has finished.
+///
+/// The termion backend keeps the terminal in raw mode for the whole
+/// session, so programs started from it share the screen with ncgopher.
+pub fn suspend() {
+    match crate::BACKEND.read().unwrap().as_str() {
+        #[cfg(feature = "pancurses-backend")]
+        "pancurses" => {
+            pancurses::endwin();
+        }
+        #[cfg(feature = "ncurses-backend")]
+        "ncurses" => {
+            cursive::backends::curses::n::ncurses::endwin();
+        }
+        #[cfg(feature = "crossterm-backend")]
+        "crossterm" => {
+            use cursive::backends::crossterm::crossterm::{execute, terminal};
+            terminal::disable_raw_mode().ok();
+            execute!(std::io::stdout(), terminal::LeaveAlternateScreen).ok();
+        }
+        _ => (),
+    }
+}
+
+/// Takes the terminal back after `suspend` and redraws the screen.
+pub fn resume(app: &mut Cursive) {
+    match crate::BACKEND.read().unwrap().as_str() {
+        #[cfg(feature = "crossterm-backend")]
+        "crossterm" => {
+            use cursive::backends::crossterm::crossterm::{execute, terminal};
+            execute!(std::io::stdout(), terminal::EnterAlternateScreen).ok();
+            terminal::enable_raw_mode().ok();
+        }
+        // curses restores its screen on the next refresh
+        _ => (),
+    }
+    app.clear();
+}
+
+/// Stops ncgopher like Ctrl-Z does in other programs. The terminal is
+/// handed back to the shell until the job is continued with `fg`, then
+/// the screen is redrawn.
+#[cfg(unix)]
+pub fn suspend_to_shell(app: &mut Cursive) {
+    suspend();
+    // The terminal is in raw mode, so Ctrl-Z does not send SIGTSTP.
+    // SIGSTOP can not be caught; execution continues here after `fg`.
+    unsafe {
+        libc::raise(libc::SIGSTOP);
+    }
+    resume(app);
+}
+
+/// Restores the terminal after the main loop has ended
+pub fn shutdown() {
+    match crate::BACKEND.read().unwrap().as_str() {
+        #[cfg(feature = "pancurses-backend")]
+        "pancurses" => {
+            pancurses::endwin();
+        }
+        _ => (),
+    }
+}
+
+/// Copies text to the system clipboard with the OSC 52 escape sequence,
+/// which most terminal emulators support, also over ssh.
+pub fn copy_to_clipboard(text: &str) {
+    let mut stdout = stdout();
+    write!(
+        stdout,
+        "\x1b]52;c;{}\x07",
+        general_purpose::STANDARD.encode(text)
+    )
+    .and_then(|_| stdout.flush())
+    .unwrap_or_else(|err| warn!("Could not copy to clipboard: {}", err));
+}
@@ -0,0 +1,208 @@
+use cursive::Cursive;
+use std::collections::HashMap;
+use std::env;
+use toml::Value;
+
+use crate::SETTINGS;
+
+/// The number of colors a terminal can display.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorDepth {
+    /// The 16 base colors
+    Basic,
+    /// The 256 color palette
+    Palette256,
+    /// 24 bit colors
+    TrueColor,
+}
+
+impl ColorDepth {
+    /// Guesses the color capability of the terminal from the environment.
+    pub fn detect() -> ColorDepth {
+        if let Ok(colorterm) = env::var("COLORTERM") {
+            if colorterm == "truecolor" || colorterm == "24bit" {
+                return ColorDepth::TrueColor;
+            }
+        }
+        match env::var("TERM") {
+            Ok(term) if term.ends_with("-direct") => ColorDepth::TrueColor,
+            Ok(term) if term.contains("256color") => ColorDepth::Palette256,
+            _ => ColorDepth::Basic,
+        }
+    }
+
+    /// Returns the color depth from the color_depth setting, which
+    /// is one of "16", "256", "truecolor" or "auto".
+    pub fn from_settings() -> ColorDepth {
+        ColorDepth::from_setting(&SETTINGS.read().unwrap().config.color_depth)
+    }
+
+    /// Returns the color depth for a value of the color_depth setting
+    pub fn from_setting(color_depth: &str) -> ColorDepth {
+        match color_depth {
+            "16" => ColorDepth::Basic,
+            "256" => ColorDepth::Palette256,
+            "truecolor" => ColorDepth::TrueColor,
+            _ => ColorDepth::detect(),
+        }
+    }
+
+    /// Returns the color depth needed to display a color given in
+    /// cursive's theme notation.
+    fn required_by(color: &str) -> ColorDepth {
+        if color.starts_with('#') {
+            ColorDepth::TrueColor
+        } else if color.len() == 3 && color.chars().all(|c| ('0'..='5').contains(&c)) {
+            ColorDepth::Palette256
+        } else {
+            ColorDepth::Basic
+        }
+    }
+}
+
+/// The 16 base colors with their approximate RGB values
+const BASE_COLORS: [(&str, (u8, u8, u8)); 16] = [
+    ("black", (0, 0, 0)),
+    ("red", (170, 0, 0)),
+    ("green", (0, 170, 0)),
+    ("yellow", (170, 85, 0)),
+    ("blue", (0, 0, 170)),
+    ("magenta", (170, 0, 170)),
+    ("cyan", (0, 170, 170)),
+    ("white", (170, 170, 170)),
+    ("light black", (85, 85, 85)),
+    ("light red", (255, 85, 85)),
+    ("light green", (85, 255, 85)),
+    ("light yellow", (255, 255, 85)),
+    ("light blue", (85, 85, 255)),
+    ("light magenta", (255, 85, 255)),
+    ("light cyan", (85, 255, 255)),
+    ("light white", (255, 255, 255)),
+];
+
+/// Parses a hex (`#123456`, `#123`) or low-resolution (`541`) color.
+fn to_rgb(color: &str) -> Option<(u8, u8, u8)> {
+    if let Some(hex) = color.strip_prefix('#') {
+        // the digits are sliced by bytes below
+        if !hex.is_ascii() {
+            return None;
+        }
+        let digits = match hex.len() {
+            3 => hex.chars().flat_map(|c| [c, c]).collect::<String>(),
+            6 => hex.to_string(),
+            _ => return None,
+        };
+        let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).ok();
+        Some((channel(0)?, channel(2)?, channel(4)?))
+    } else if ColorDepth::required_by(color) == ColorDepth::Palette256 {
+        let channel = |c: char| c.to_digit(6).map(|d| d as u8 * 51);
+        let mut chars = color.chars();
+        Some((
+            channel(chars.next()?)?,
+            channel(chars.next()?)?,
+            channel(chars.next()?)?,
+        ))
+    } else {
+        None
+    }
+}
+
+/// Converts a color to one that can be displayed with the given color
+/// depth, choosing the closest color where necessary.
+pub fn degrade_color(color: &str, depth: ColorDepth) -> String {
+    if ColorDepth::required_by(color) <= depth {
+        return color.to_string();
+    }
+    let (r, g, b) = match to_rgb(color) {
+        Some(rgb) => rgb,
+        None => return color.to_string(),
+    };
+    if depth == ColorDepth::Palette256 {
+        let scale = |c: u8| ((c as u16 + 25) / 51).to_string();
+        return format!("{}{}{}", scale(r), scale(g), scale(b));
+    }
+    let distance = |(br, bg, bb): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, br) + d(g, bg) + d(b, bb)
+    };
+    BASE_COLORS
+        .iter()
+        .min_by_key(|(_, rgb)| distance(*rgb))
+        .map(|(name, _)| name.to_string())
+        .unwrap()
+}
+
+/// Rewrites the colors of a cursive theme so they can be displayed with the
+/// given color depth. For color arrays, the first color that is supported
+/// is used, otherwise the first color is degraded.
+pub fn adapt_theme(theme: &str, depth: ColorDepth) -> String {
+    let mut theme: Value = match toml::from_str(theme) {
+        Ok(theme) => theme,
+        Err(err) => {
+            warn!("Could not parse theme: {}", err);
+            return theme.to_string();
+        }
+    };
+    if let Some(Value::Table(colors)) = theme.get_mut("colors") {
+        for (_name, value) in colors.iter_mut() {
+            let color = match value {
+                Value::String(color) => degrade_color(color, depth),
+                Value::Array(candidates) => {
+                    let candidates = candidates
+                        .iter()
+                        .filter_map(|c| c.as_str())
+                        .collect::<Vec<_>>();
+                    match candidates
+                        .iter()
+                        .find(|c| ColorDepth::required_by(c) <= depth)
+                    {
+                        Some(color) => color.to_string(),
+                        None => match candidates.first() {
+                            Some(color) => degrade_color(color, depth),
+                            None => continue,
+                        },
+                    }
+                }
+                _ => continue,
+            };
+            *value = Value::String(color);
+        }
+    }
+    toml::to_string(&theme).unwrap_or_default()
+}
+
+/// Loads a built-in or user theme, adapted to the color capability
+/// of the terminal.
+pub fn load_theme(app: &mut Cursive, name: &str) {
+    let theme = match SETTINGS.read().unwrap().get_theme_by_name(name) {
+        Some(theme) => theme.to_string(),
+        None => {
+            warn!("Unknown theme {}", name);
+            return;
+        }
+    };
+    let depth = ColorDepth::from_settings();
+    SETTINGS.write().unwrap().config.theme_type_colors = type_colors(&theme, depth);
+    let theme = adapt_theme(&theme, depth);
+    if let Err(err) = app.load_toml(&theme) {
+        warn!("Could not load theme {}: {:?}", name, err);
+    }
+}
+
+/// Returns the colors of gophermap items from the [type_colors] table
+/// of a theme, adapted to the given color depth
+fn type_colors(theme: &str, depth: ColorDepth) -> HashMap<String, String> {
+    let theme: Value = match toml::from_str(theme) {
+        Ok(theme) => theme,
+        Err(_) => return HashMap::new(),
+    };
+    match theme.get("type_colors") {
+        Some(Value::Table(colors)) => colors
+            .iter()
+            .filter_map(|(item_type, color)| {
+                Some((item_type.clone(), degrade_color(color.as_str()?, depth)))
+            })
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
@@ -0,0 +1,88 @@
+use crate::controller::Controller;
+use crate::ui::layout::{GeminiScrollView, Layout};
+use cursive::{
+    traits::{Nameable, Scrollable},
+    views::SelectView,
+    Cursive,
+};
+use url::Url;
+
+/// Shows or hides the outline of the current text or gemini page in a
+/// pane on the right
+pub fn toggle_toc(app: &mut Cursive) {
+    let (shown, current_view) = app
+        .call_on_name("main", |v: &mut Layout| {
+            (v.has_preview_beside("gemini_content"), v.get_current_view())
+        })
+        .expect("main layout missing");
+    if shown {
+        app.call_on_name("main", |v: &mut Layout| v.remove_preview());
+        Controller::render_again(app);
+        return;
+    }
+    if current_view != "gemini_content" {
+        set_message(app, "Contents are only shown for text and gemini pages");
+        return;
+    }
+    let toc = SelectView::<usize>::new()
+        .on_submit(jump_to_row)
+        .with_name("toc")
+        .scrollable();
+    app.call_on_name("main", |v: &mut Layout| {
+        v.set_preview(toc, "Contents".to_string(), "gemini_content")
+    });
+    // wraps the text to the narrower pane, which fills the outline
+    Controller::render_again(app);
+}
+
+/// Fills the outline pane, if it is shown, with the headings of the
+/// current page
+pub fn refresh(app: &mut Cursive) {
+    let content = app
+        .user_data::<Controller>()
+        .expect("controller missing")
+        .content
+        .lock()
+        .unwrap()
+        .clone();
+    let rows: Vec<String> = match app.find_name::<SelectView<Option<Url>>>("gemini_content") {
+        Some(view) => view.iter().map(|(label, _)| label.to_string()).collect(),
+        None => return,
+    };
+    let mut toc = match app.find_name::<SelectView<usize>>("toc") {
+        Some(toc) => toc,
+        None => return,
+    };
+    toc.clear();
+    // headings are looked up in order, by their beginning as long ones
+    // may be wrapped
+    let mut row = 0;
+    for (level, title) in crate::text::outline(&content) {
+        let start: String = title.chars().take(20).collect();
+        if let Some(found) = rows[row..].iter().position(|r| r.contains(&start)) {
+            row += found;
+            toc.add_item(format!("{}{}", "  ".repeat(level - 1), title), row);
+            row += 1;
+        }
+    }
+    if toc.is_empty() {
+        drop(toc);
+        set_message(app, "No sections found");
+    }
+}
+
+/// Scrolls the page so that a row is at the top and selects it
+fn jump_to_row(app: &mut Cursive, row: &usize) {
+    if let Some(mut view) = app.find_name::<SelectView<Option<Url>>>("gemini_content") {
+        view.set_selection(*row);
+    }
+    if let Some(mut scroll) = app.find_name::<GeminiScrollView>("gemini_content_scroll") {
+        scroll.set_offset((0, *row));
+    }
+}
+
+fn set_message(app: &mut Cursive, message: &str) {
+    app.user_data::<Controller>()
+        .expect("controller missing")
+        .set_message(message);
+}
@@ -66,7 +66,7 @@ pub fn download_filename_from_url(url: &Url) -> String {
     let download_path = crate::SETTINGS.read().unwrap().config.download_path.clone();
 
     let filename = match url.path_segments() {
-        Some(path_segments) => path_segments.last().unwrap_or_default(),
+        Some(mut path_segments) => path_segments.next_back().unwrap_or_default(),
         None => "download",
     };
     let filename = if filename.is_empty() {
@@ -79,3 +79,81 @@ pub fn download_filename_from_url(url: &Url) -> String {
     let path = Path::new(&download_path).join(filename);
     path.display().to_string()
 }
+
+/// Returns the directories above a gopher or gemini URL, the closest
+/// first, down to the root of the server
+pub fn ancestors(url: &Url) -> Vec<Url> {
+    let mut segments: Vec<&str> = match url.path_segments() {
+        Some(segments) => segments.filter(|s| !s.is_empty()).collect(),
+        None => return Vec::new(),
+    };
+    let mut ancestors = Vec::new();
+    match url.scheme() {
+        "gopher" | "gophers" => {
+            // the first segment is the item type
+            if !segments.is_empty() {
+                segments.remove(0);
+            }
+            while segments.pop().is_some() {
+                let mut ancestor = url.clone();
+                ancestor.set_query(None);
+                ancestor.set_fragment(None);
+                if segments.is_empty() {
+                    ancestor.set_path("/");
+                } else {
+                    ancestor.set_path(&format!("/1/{}", segments.join("/")));
+                }
+                ancestors.push(ancestor);
+            }
+        }
+        "gemini" => {
+            // the page itself, or a directory with its index page
+            if segments.pop().is_none() {
+                return ancestors;
+            }
+            loop {
+                let mut ancestor = url.clone();
+                ancestor.set_query(None);
+                ancestor.set_fragment(None);
+                ancestor.set_path(&format!("/{}/", segments.join("/")).replace("//", "/"));
+                ancestors.push(ancestor);
+                if segments.pop().is_none() {
+                    break;
+                }
+            }
+        }
+        _ => (),
+    }
+    ancestors
+}
+
+/// Returns the host and path of a gopher or gemini URL as a breadcrumb,
+/// e.g. "example.org › phlog › 2023"
+pub fn breadcrumb(url: &Url) -> String {
+    let host = match (url.scheme(), url.host_str()) {
+        ("gopher" | "gophers" | "gemini", Some(host)) => match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        },
+        _ => return human_readable_url(url),
+    };
+    let mut segments: Vec<String> = url
+        .path_segments()
+        .map(|segments| {
+            segments
+                .filter(|s| !s.is_empty())
+                .map(|s| percent_encoding::percent_decode_str(s).decode_utf8_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    if url.scheme() != "gemini" && !segments.is_empty() {
+        // the item type
+        segments.remove(0);
+    }
+    let mut breadcrumb = host;
+    for segment in segments {
+        breadcrumb.push_str(" › ");
+        breadcrumb.push_str(&segment);
+    }
+    breadcrumb
+}